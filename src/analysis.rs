@@ -0,0 +1,320 @@
+use context::{ActionRecipeItemIdx, ActionRecipeItemStore};
+use recipe::{ActionCondition, ActionInput, ActionRecipeItem};
+use shared::KeySet;
+use ActionConfiguration;
+
+/// A pair of recipes that react to the same inputs, identified by the order
+/// in which they were added with `ActionContextBuilder::add_recipe`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ActionRecipeConflict {
+    /// Both recipes start with exactly the same inputs.
+    SameInputs(usize, usize),
+    /// The inputs `shorter` starts with are a strict prefix of those of `longer`,
+    /// so `shorter` also completes whenever `longer` is being entered.
+    Prefix { shorter: usize, longer: usize },
+}
+
+/// The linear part at the start of a recipe, up to the first filtered input,
+/// unordered or choice item.
+pub(crate) struct ActionRecipeLeadingInputs<C: ActionConfiguration> {
+    inputs: Vec<ActionInput<C>>,
+    // only key conditions can be compared across recipes.
+    key_conditions: Vec<(C::KeyKind, bool)>,
+}
+
+impl<C: ActionConfiguration> ActionRecipeLeadingInputs<C> {
+    pub(crate) fn collect(
+        recipe_items: &ActionRecipeItemStore<C>,
+        root_item: ActionRecipeItemIdx,
+    ) -> Self {
+        let mut leading_inputs = ActionRecipeLeadingInputs {
+            inputs: Vec::new(),
+            key_conditions: Vec::new(),
+        };
+        leading_inputs.collect_item(recipe_items, root_item);
+        leading_inputs
+    }
+
+    // returns false once the linear part has ended.
+    fn collect_item(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        item_idx: ActionRecipeItemIdx,
+    ) -> bool {
+        match recipe_items.get(item_idx) {
+            Ok(ActionRecipeItem::StartInput(input)) => {
+                self.inputs.push(ActionInput::clone(input));
+                true
+            }
+            Ok(ActionRecipeItem::StartCondition(condition)) => {
+                if let ActionCondition::KeyPressed(key, pressed) = condition {
+                    self.key_conditions.push((key.clone(), *pressed));
+                }
+                true
+            }
+            Ok(ActionRecipeItem::Sequential(seq)) => seq
+                .iter()
+                .all(|&child_idx| self.collect_item(recipe_items, child_idx)),
+            Ok(ActionRecipeItem::StartFilteredInput(_))
+            | Ok(ActionRecipeItem::StartExecutionFilteredInput(_))
+            | Ok(ActionRecipeItem::StartDwell(_))
+            | Ok(ActionRecipeItem::StartIdle(_))
+            | Ok(ActionRecipeItem::StartHeldKeyUp(..))
+            | Ok(ActionRecipeItem::StartCursorTransition(..))
+            | Ok(ActionRecipeItem::StartAxisInput(..))
+            | Ok(ActionRecipeItem::StartCharacterInput(_))
+            | Ok(ActionRecipeItem::Unordered(..))
+            | Ok(ActionRecipeItem::Choice(_))
+            | Ok(ActionRecipeItem::Optional(_))
+            | Ok(ActionRecipeItem::Repeated(..))
+            | Err(_) => false,
+            Ok(_) => true,
+        }
+    }
+
+    fn excludes(&self, other: &Self) -> bool {
+        self.key_conditions.iter().any(|(k1, s1)| {
+            other
+                .key_conditions
+                .iter()
+                .any(|(k2, s2)| k1 == k2 && s1 != s2)
+        })
+    }
+
+    pub(crate) fn conflict_with(
+        &self,
+        other: &Self,
+        self_idx: usize,
+        other_idx: usize,
+    ) -> Option<ActionRecipeConflict> {
+        if self.inputs.is_empty() || other.inputs.is_empty() || self.excludes(other) {
+            return None;
+        }
+        if self.inputs == other.inputs {
+            Some(ActionRecipeConflict::SameInputs(self_idx, other_idx))
+        } else if other.inputs.starts_with(&self.inputs) {
+            Some(ActionRecipeConflict::Prefix {
+                shorter: self_idx,
+                longer: other_idx,
+            })
+        } else if self.inputs.starts_with(&other.inputs) {
+            Some(ActionRecipeConflict::Prefix {
+                shorter: other_idx,
+                longer: self_idx,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// An input a recipe waits for, in the simplified shape `ActionContext::recipe_bindings`
+/// reports.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ActionBindingInput<K, T> {
+    KeyDown(K),
+    KeyUp(K),
+    KeyRepeat(K),
+    /// A cursor input over exactly this target.
+    Cursor(T),
+    /// A focus input on exactly this target.
+    Focus(T),
+    Axis(K),
+    Character(char),
+    /// Matched through a filter, a predicate or timing, which can't be inspected.
+    Opaque,
+}
+
+/// One of the inputs listed by `ActionContext::recipe_bindings`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ActionRecipeBinding<K, T> {
+    pub input: ActionBindingInput<K, T>,
+    /// Keys the recipe requires to be held while it waits for the input, such as the
+    /// modifiers of a chord.
+    pub held: Vec<K>,
+}
+
+pub(crate) struct ActionRecipeBindings<C: ActionConfiguration> {
+    bindings: Vec<ActionRecipeBinding<C::KeyKind, C::Target>>,
+    // keys pressed through inputs or conditions whose contracts are still in effect.
+    held: Vec<(ActionRecipeItemIdx, C::KeyKind)>,
+}
+
+impl<C: ActionConfiguration> ActionRecipeBindings<C> {
+    pub(crate) fn collect(
+        recipe_items: &ActionRecipeItemStore<C>,
+        root_item: ActionRecipeItemIdx,
+    ) -> Vec<ActionRecipeBinding<C::KeyKind, C::Target>> {
+        let mut bindings = ActionRecipeBindings {
+            bindings: Vec::new(),
+            held: Vec::new(),
+        };
+        bindings.collect_item(recipe_items, root_item);
+        bindings.bindings
+    }
+
+    fn push(&mut self, input: ActionBindingInput<C::KeyKind, C::Target>) {
+        let mut held = Vec::new();
+        for (_, key) in self.held.iter() {
+            if !held.contains(key) {
+                held.push(key.clone());
+            }
+        }
+        self.bindings.push(ActionRecipeBinding { input, held });
+    }
+
+    fn collect_item(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        item_idx: ActionRecipeItemIdx,
+    ) {
+        let item = match recipe_items.get(item_idx) {
+            Ok(item) => item,
+            Err(_) => return,
+        };
+        match item {
+            ActionRecipeItem::StartInput(input) => {
+                let binding_input = match &**input {
+                    ActionInput::KeyDown(key) => ActionBindingInput::KeyDown(key.clone()),
+                    ActionInput::KeyUp(key) => ActionBindingInput::KeyUp(key.clone()),
+                    ActionInput::KeyRepeat(key) => ActionBindingInput::KeyRepeat(key.clone()),
+                    ActionInput::CursorCoordinate(target) => {
+                        ActionBindingInput::Cursor(target.clone())
+                    }
+                    ActionInput::FocusCoordinate(target) => {
+                        ActionBindingInput::Focus(target.clone())
+                    }
+                    ActionInput::Axis(axis, _) => ActionBindingInput::Axis(axis.clone()),
+                    ActionInput::Character(c) => ActionBindingInput::Character(*c),
+                    ActionInput::Stylus(..)
+                    | ActionInput::TouchStart(_)
+                    | ActionInput::TouchMove(_)
+                    | ActionInput::TouchEnd(_) => ActionBindingInput::Opaque,
+                };
+                self.push(binding_input);
+                if let ActionInput::KeyDown(key) = &**input {
+                    self.held.push((item_idx, key.clone()));
+                }
+            }
+            ActionRecipeItem::StartAxisInput(axis, ..) => {
+                self.push(ActionBindingInput::Axis(axis.clone()));
+            }
+            ActionRecipeItem::StartCondition(ActionCondition::KeyPressed(key, true))
+            | ActionRecipeItem::StartCondition(ActionCondition::ModifierActive(key)) => {
+                self.held.push((item_idx, key.clone()));
+            }
+            ActionRecipeItem::EliminateItem(eliminated) => {
+                self.held.retain(|(idx, _)| idx != eliminated);
+            }
+            _ if item.is_compound() => {
+                if let Ok(seq) = item.compound_sequence(item_idx) {
+                    for &child_idx in seq {
+                        self.collect_item(recipe_items, child_idx);
+                    }
+                }
+            }
+            _ if item.is_interactive() => self.push(ActionBindingInput::Opaque),
+            _ => {}
+        }
+    }
+}
+
+/// The keys a recipe starts by pressing together, and the exact targets it expects
+/// before them, as compared by `ActionContext::duplicate_bindings`.
+pub(crate) struct ActionInitialChord<C: ActionConfiguration> {
+    targets: Vec<ActionBindingInput<C::KeyKind, C::Target>>,
+    keys: KeySet<C::KeyKind>,
+}
+
+impl<C: ActionConfiguration> PartialEq for ActionInitialChord<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.targets == other.targets && self.keys == other.keys
+    }
+}
+
+impl<C: ActionConfiguration> ActionInitialChord<C> {
+    // `None` if something that can't be compared comes before or within the chord.
+    pub(crate) fn collect(bindings: &[ActionRecipeBinding<C::KeyKind, C::Target>]) -> Option<Self> {
+        let mut targets = Vec::new();
+        let mut keys = KeySet::new();
+        for binding in bindings {
+            match &binding.input {
+                ActionBindingInput::Cursor(_) | ActionBindingInput::Focus(_) if keys.is_empty() => {
+                    targets.push(binding.input.clone());
+                }
+                ActionBindingInput::KeyDown(key)
+                    if keys
+                        .iter()
+                        .all(|chord_key| binding.held.contains(chord_key)) =>
+                {
+                    keys.insert(key.clone());
+                }
+                ActionBindingInput::Opaque => return None,
+                _ => break,
+            }
+        }
+        if keys.is_empty() {
+            return None;
+        }
+        Some(ActionInitialChord { targets, keys })
+    }
+}
+
+/// Whether the first input the item waits for may be a cursor input; filtered
+/// inputs may accept anything, and optional items may be skipped, so both count.
+pub(crate) fn may_start_with_cursor_input<C: ActionConfiguration>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    item_idx: ActionRecipeItemIdx,
+) -> bool {
+    match recipe_items.get(item_idx) {
+        Ok(ActionRecipeItem::StartInput(input)) => {
+            matches!(**input, ActionInput::CursorCoordinate(_))
+        }
+        Ok(ActionRecipeItem::StartFilteredInput(_))
+        | Ok(ActionRecipeItem::StartExecutionFilteredInput(_))
+        | Ok(ActionRecipeItem::StartDwell(_))
+        | Ok(ActionRecipeItem::StartCursorTransition(..))
+        | Ok(ActionRecipeItem::Optional(_))
+        | Ok(ActionRecipeItem::Repeated(..)) => true,
+        Ok(ActionRecipeItem::Sequential(seq)) => seq
+            .iter()
+            .find(|&&child_idx| {
+                recipe_items
+                    .get(child_idx)
+                    .is_ok_and(|child| child.is_interactive() || child.is_compound())
+            })
+            .is_some_and(|&child_idx| may_start_with_cursor_input(recipe_items, child_idx)),
+        Ok(ActionRecipeItem::Unordered(seq, _)) | Ok(ActionRecipeItem::Choice(seq)) => seq
+            .iter()
+            .any(|&child_idx| may_start_with_cursor_input(recipe_items, child_idx)),
+        _ => false,
+    }
+}
+
+/// The key of a recipe that is a single chord: conditions, if any, then a `KeyDown`,
+/// further conditions and plain commands, so it can complete without an execution.
+pub(crate) fn accelerator_key<C: ActionConfiguration>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    root_item: ActionRecipeItemIdx,
+) -> Option<C::KeyKind> {
+    let seq = match recipe_items.get(root_item) {
+        Ok(ActionRecipeItem::Sequential(seq)) => seq,
+        _ => return None,
+    };
+    let mut key = None;
+    let mut issues_commands = false;
+    for &item_idx in seq.iter() {
+        match recipe_items.get(item_idx).ok()? {
+            ActionRecipeItem::StartInput(input) if key.is_none() => match &**input {
+                ActionInput::KeyDown(input_key) => key = Some(input_key.clone()),
+                _ => return None,
+            },
+            // all conditions are checked before any command is issued.
+            ActionRecipeItem::StartCondition(_) if !issues_commands => {}
+            ActionRecipeItem::EliminateItem(_) => {}
+            ActionRecipeItem::DoCommand(_) if key.is_some() => issues_commands = true,
+            _ => return None,
+        }
+    }
+    key
+}
@@ -0,0 +1,183 @@
+use context::ActionRecipeItemIdx;
+use context::ActionRecipeItemStore;
+use error::ConcertoError;
+use recipe::{ActionRecipeItem, ActionRecipeRepetition};
+use smallvec::SmallVec;
+use ActionConfiguration;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum ActionCompiledWait {
+    Single,
+    /// Satisfied once the given number of edges were taken.
+    Unordered(usize),
+    Choice,
+    /// Waits for the first input of an optional item or the input right after it;
+    /// the latter skips ahead to `skip_to`.
+    Optional,
+    /// Waits for the first input of a repeated item or, once the repetition allows
+    /// it to end, the input right after it; the latter skips ahead to `skip_to`.
+    Repeated(ActionRecipeRepetition),
+}
+
+/// A point where the execution waits for input. Its edges are labelled with the
+/// interactive items in `inputs`; once the wait is satisfied, `then` runs in order
+/// and the execution moves to the next state, or back to `repeat` at the end of an
+/// iteration of a repeated item.
+#[derive(Clone, Debug)]
+pub(crate) struct ActionCompiledState {
+    pub(crate) container: ActionRecipeItemIdx,
+    pub(crate) wait: ActionCompiledWait,
+    pub(crate) inputs: SmallVec<[ActionRecipeItemIdx; 3]>,
+    pub(crate) then: SmallVec<[ActionRecipeItemIdx; 4]>,
+    pub(crate) skip_to: Option<usize>,
+    pub(crate) repeat: Option<usize>,
+}
+
+/// A recipe's item tree lowered into a linear chain of wait states, so execution
+/// no longer needs a frame stack to find the next item.
+#[derive(Clone, Debug)]
+pub(crate) struct ActionCompiledRecipe {
+    pub(crate) entry: SmallVec<[ActionRecipeItemIdx; 4]>,
+    pub(crate) states: Vec<ActionCompiledState>,
+}
+
+impl ActionCompiledRecipe {
+    pub(crate) fn compile<C: ActionConfiguration>(
+        recipe_items: &ActionRecipeItemStore<C>,
+        root_item: ActionRecipeItemIdx,
+    ) -> Result<Self, ConcertoError> {
+        let mut compiled = ActionCompiledRecipe {
+            entry: SmallVec::new(),
+            states: Vec::new(),
+        };
+        if !recipe_items.get(root_item)?.is_compound() {
+            return Err(ConcertoError::UnexpectedItem(root_item));
+        }
+        compiled.lower_item(recipe_items, root_item, root_item)?;
+        Ok(compiled)
+    }
+
+    fn pending_items(&mut self) -> &mut SmallVec<[ActionRecipeItemIdx; 4]> {
+        match self.states.last_mut() {
+            Some(state) => &mut state.then,
+            None => &mut self.entry,
+        }
+    }
+
+    fn push_state(
+        &mut self,
+        container: ActionRecipeItemIdx,
+        wait: ActionCompiledWait,
+        inputs: &[ActionRecipeItemIdx],
+    ) {
+        self.states.push(ActionCompiledState {
+            container,
+            wait,
+            inputs: inputs.iter().cloned().collect(),
+            then: SmallVec::new(),
+            skip_to: None,
+            repeat: None,
+        });
+    }
+
+    fn lower_sequence<C: ActionConfiguration>(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        container: ActionRecipeItemIdx,
+        seq: &[ActionRecipeItemIdx],
+    ) -> Result<(), ConcertoError> {
+        let mut pending_skip = None;
+        for &seq_item_idx in seq.iter() {
+            let next_state = self.states.len();
+            let seq_item = recipe_items.get(seq_item_idx)?;
+            if let Some((error, skip_state)) = pending_skip.take() {
+                if !seq_item.is_interactive() {
+                    return Err(error);
+                }
+                let state: &mut ActionCompiledState = &mut self.states[skip_state];
+                state.inputs.push(seq_item_idx);
+                state.skip_to = Some(next_state);
+            }
+            self.lower_item(recipe_items, container, seq_item_idx)?;
+            pending_skip = match seq_item {
+                ActionRecipeItem::Optional(_) => Some((
+                    ConcertoError::OptionalWithoutFollowingInput(seq_item_idx),
+                    next_state,
+                )),
+                ActionRecipeItem::Repeated(..) => Some((
+                    ConcertoError::RepeatedWithoutFollowingInput(seq_item_idx),
+                    next_state,
+                )),
+                _ => None,
+            };
+        }
+        if let Some((error, _)) = pending_skip {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    fn lower_item<C: ActionConfiguration>(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        container: ActionRecipeItemIdx,
+        item_idx: ActionRecipeItemIdx,
+    ) -> Result<(), ConcertoError> {
+        let item = recipe_items.get(item_idx)?;
+        match item {
+            ActionRecipeItem::Sequential(seq) => {
+                self.lower_sequence(recipe_items, item_idx, seq)?;
+            }
+            ActionRecipeItem::Optional(seq) | ActionRecipeItem::Repeated(seq, _) => {
+                let (&first_idx, rest) = seq
+                    .split_first()
+                    .ok_or(ConcertoError::EmptySequence(item_idx))?;
+                if !recipe_items.get(first_idx)?.is_interactive() {
+                    return Err(ConcertoError::NonInteractiveBranch {
+                        container: item_idx,
+                        item: first_idx,
+                    });
+                }
+                let wait = match item {
+                    ActionRecipeItem::Repeated(_, repetition) => {
+                        ActionCompiledWait::Repeated(*repetition)
+                    }
+                    _ => ActionCompiledWait::Optional,
+                };
+                let first_state = self.states.len();
+                // the edge for the input after the item is added by the parent.
+                self.push_state(item_idx, wait, &[first_idx]);
+                self.lower_sequence(recipe_items, item_idx, rest)?;
+                if let ActionCompiledWait::Repeated(_) = wait {
+                    let last_state = self.states.last_mut().expect("state was pushed");
+                    last_state.repeat = Some(first_state);
+                }
+            }
+            ActionRecipeItem::Unordered(seq, _) | ActionRecipeItem::Choice(seq) => {
+                for &seq_item_idx in seq.iter() {
+                    if !recipe_items.get(seq_item_idx)?.is_interactive() {
+                        return Err(ConcertoError::NonInteractiveBranch {
+                            container: item_idx,
+                            item: seq_item_idx,
+                        });
+                    }
+                }
+                let wait = match item {
+                    ActionRecipeItem::Unordered(_, required) => {
+                        ActionCompiledWait::Unordered(*required)
+                    }
+                    _ => ActionCompiledWait::Choice,
+                };
+                self.push_state(item_idx, wait, seq);
+            }
+            _ if item.is_interactive() => {
+                self.push_state(container, ActionCompiledWait::Single, &[item_idx]);
+            }
+            _ if item.is_condition() || item.is_noninteractive() => {
+                self.pending_items().push(item_idx);
+            }
+            _ => return Err(ConcertoError::UnexpectedItem(item_idx)),
+        }
+        Ok(())
+    }
+}
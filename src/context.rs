@@ -1,71 +1,960 @@
-use execution::{ActionExecutionCtx, ExecutionContextResult};
-use recipe::ActionInput;
+use analysis::accelerator_key;
+use analysis::{
+    ActionInitialChord, ActionRecipeBinding, ActionRecipeBindings, ActionRecipeConflict,
+    ActionRecipeLeadingInputs,
+};
+use compiled::ActionCompiledRecipe;
+use error::ConcertoError;
+use execution::{
+    ActionCommandList, ActionCommandSource, ActionExecutionCtx, ExecutionContextResult,
+    DEFAULT_INLINE_CONTRACT_LIMIT,
+};
+use fixedbitset::FixedBitSet;
+use lifecycle::{
+    record_execution_event, ActionAbortReason, ActionExecutionEvent, ActionLifecycleObserver,
+    ActionLifecycleObserverList, ActionRecipeMetrics,
+};
 use recipe::ActionNestRecipeCommand;
 use recipe::ActionRecipeBuilder;
-use recipe::{ActionRecipe, ActionRecipeItem};
+use recipe::{ActionInput, ActionStylus, ActionTouch};
+use recipe::{ActionRecipe, ActionRecipeItem, ActionRecipeLabel, ActionRecipeMetadata};
+use shared::ActionKey;
+use shared::{GeneratorCell, KeyMap, KeySet, MaybeSend, MaybeSendSync, Shared};
 use slab::Slab;
-use std::collections::BTreeSet;
+use smallvec::SmallVec;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::time::Duration;
+use time::{ActionTimeSource, ActionTimeSourceObj};
+use validation::validate_items_in_use;
 use ActionConfiguration;
 
-pub struct ActionContext<C: ActionConfiguration> {
-    recipe_items: ActionRecipeItemStore<C>,
+/// `'f` bounds the closures held by the recipes, which may borrow application data
+/// outliving the context.
+pub struct ActionContext<'f, C: ActionConfiguration> {
+    recipe_items: Shared<ActionRecipeItemStore<'f, C>>,
     recipes: Vec<(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)>,
-    command_list: Vec<C::Command>,
+    command_list: ActionCommandList<C>,
+    // where the commands of each input, time step or call issuing some begin.
+    command_batch_marks: Vec<usize>,
+    next_execution_id: u64,
     env_tracking_state: ActionEnvironmentTrackingState<C>,
+    current_time: Duration,
+    diagnostics: Vec<ConcertoError>,
+    observers: Shared<ActionLifecycleObserverList<'f>>,
+    prefixes: Vec<ActionRecipePrefix>,
+    prefix_state: ActionPrefixState,
+    held_completion: ActionHeldCompletion<C>,
+    cursor_coalescing: ActionCursorCoalescing,
+    focus_lost_command: Option<Shared<C::Command>>,
+    command_filter: Option<Shared<ActionCommandFilterCell<'f, C>>>,
+    fallback: Option<Shared<ActionFallbackCell<'f, C>>>,
+    interceptors: Vec<Shared<ActionInterceptorCell<'f, C>>>,
+    target_resolver: Option<Shared<dyn ActionTargetResolverObj<C> + 'f>>,
+    time_source: Option<Shared<dyn ActionTimeSourceObj + 'f>>,
+    // what the time source read when the context time was last synced with it.
+    time_source_reading: Duration,
+    reorder_buffer: ActionReorderBuffer<C>,
+    accelerators: ActionAcceleratorTable<C::KeyKind>,
+    // the last position given to `process_cursor_position` that was not resolved yet.
+    pending_cursor_position: Option<C::CursorPos>,
+    // the last position resolved, under which generation, and its target.
+    resolved_cursor_target: Option<(C::CursorPos, u64, C::Target)>,
+    target_generation: u64,
+    // set on the copies `ActionContext::simulate` works on, so no callback is called.
+    dry_run: bool,
+    // Scratch buffers reused across calls, so steady-state processing doesn't allocate.
+    nest_recipe_commands: Vec<ActionNestRecipeCommand>,
+    spare_nest_recipe_commands: Vec<ActionNestRecipeCommand>,
+}
+
+// the source of the command issued by `ActionContext::notify_focus_lost`.
+const FOCUS_LOST_SOURCE: usize = usize::MAX;
+// the source of the commands issued for the inputs no recipe matched.
+const FALLBACK_SOURCE: usize = usize::MAX - 1;
+
+/// A prefix declared with `ActionContextBuilder::add_prefix`.
+#[derive(Clone, Debug)]
+pub(crate) struct ActionRecipePrefix {
+    name: String,
+    timeout: Option<Duration>,
+}
+
+/// The recipes that are a single chord, by the key completing them. Only the input
+/// of that key is offered to one, which completes it without an execution, while the
+/// other recipes go through the full machinery.
+#[derive(Clone, Debug)]
+struct ActionAcceleratorTable<K: ActionKey> {
+    by_key: KeyMap<K, SmallVec<[usize; 2]>>,
+    accelerated: FixedBitSet,
+    others: Vec<usize>,
+}
+
+impl<K: ActionKey> ActionAcceleratorTable<K> {
+    fn new<C: ActionConfiguration<KeyKind = K>>(
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipes: &[(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)],
+    ) -> Self {
+        let mut table = ActionAcceleratorTable {
+            by_key: KeyMap::new(),
+            accelerated: FixedBitSet::with_capacity(recipes.len()),
+            others: Vec::new(),
+        };
+        for (recipe_idx, (recipe, _)) in recipes.iter().enumerate() {
+            match accelerator_key(recipe_items, recipe.root_item) {
+                Some(key) => {
+                    table.by_key.get_or_default(key).push(recipe_idx);
+                    table.accelerated.insert(recipe_idx);
+                }
+                None => table.others.push(recipe_idx),
+            }
+        }
+        table
+    }
+
+    /// The accelerated recipes `input` may start.
+    fn candidates<C: ActionConfiguration<KeyKind = K>>(&self, input: &ActionInput<C>) -> &[usize] {
+        match input {
+            ActionInput::KeyDown(key) => self.by_key.get(key).map_or(&[], |recipes| recipes),
+            _ => &[],
+        }
+    }
+
+    fn is_accelerated(&self, recipe_idx: usize) -> bool {
+        self.accelerated.contains(recipe_idx)
+    }
+
+    /// The recipes an input may start, the other recipes merged with `candidates`
+    /// in recipe order.
+    fn start_order<'a>(&'a self, candidates: &'a [usize]) -> ActionRecipeMerge<'a> {
+        ActionRecipeMerge(&self.others, candidates)
+    }
+}
+
+struct ActionRecipeMerge<'a>(&'a [usize], &'a [usize]);
+
+impl<'a> Iterator for ActionRecipeMerge<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let take_first = match (self.0.first(), self.1.first()) {
+            (Some(first), Some(second)) => first < second,
+            (first, _) => first.is_some(),
+        };
+        let list = if take_first { &mut self.0 } else { &mut self.1 };
+        let (&recipe_idx, rest) = list.split_first()?;
+        *list = rest;
+        Some(recipe_idx)
+    }
+}
+
+/// The prefix that is armed, and when it times out.
+#[derive(Copy, Clone, Default, Debug)]
+struct ActionPrefixState {
+    pending: Option<usize>,
+    deadline: Option<Duration>,
+}
+
+impl ActionPrefixState {
+    // a completed recipe consumes the pending prefix, and may arm another one.
+    fn arm(&mut self, prefixes: &[ActionRecipePrefix], prefix: Option<usize>, now: Duration) {
+        self.pending = prefix;
+        self.deadline = prefix
+            .and_then(|prefix_idx| prefixes[prefix_idx].timeout)
+            .map(|timeout| now + timeout);
+    }
+
+    fn cancel(&mut self) {
+        *self = ActionPrefixState::default();
+    }
+}
+
+/// Inputs given with `ActionContext::process_timed_input`, held back for the window
+/// in timestamp order so an earlier input that arrives late can still go first.
+struct ActionReorderBuffer<C: ActionConfiguration> {
+    window: Option<Duration>,
+    newest: Duration,
+    pending: VecDeque<(Duration, ActionInput<C>)>,
+}
+
+impl<C: ActionConfiguration> Clone for ActionReorderBuffer<C> {
+    fn clone(&self) -> Self {
+        ActionReorderBuffer {
+            window: self.window,
+            newest: self.newest,
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<C: ActionConfiguration> ActionReorderBuffer<C> {
+    fn new(window: Option<Duration>) -> Self {
+        ActionReorderBuffer {
+            window,
+            newest: Duration::default(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    // inputs with the same timestamp keep the order they arrived in.
+    fn insert(&mut self, timestamp: Duration, input: ActionInput<C>) {
+        let position = self
+            .pending
+            .iter()
+            .rposition(|(pending_timestamp, _)| *pending_timestamp <= timestamp)
+            .map_or(0, |position| position + 1);
+        self.pending.insert(position, (timestamp, input));
+        self.newest = self.newest.max(timestamp);
+    }
+
+    /// The earliest input once no input before it can be expected anymore, judged by
+    /// the context time and the newest timestamp seen.
+    fn pop_due(&mut self, now: Duration) -> Option<ActionInput<C>> {
+        let window = self.window.unwrap_or_default();
+        let horizon = now.max(self.newest);
+        match self.pending.front() {
+            Some((timestamp, _)) if *timestamp + window <= horizon => {
+                self.pending.pop_front().map(|(_, input)| input)
+            }
+            _ => None,
+        }
+    }
+
+    fn deadline(&self) -> Option<Duration> {
+        let window = self.window?;
+        self.pending
+            .front()
+            .map(|(timestamp, _)| *timestamp + window)
+    }
+}
+
+/// The commands of a recipe that completed while longer recipes starting the same
+/// way went on, kept back until those give up or the hold-off window ends.
+struct ActionHeldCompletion<C: ActionConfiguration> {
+    window: Option<Duration>,
+    recipe_idx: Option<usize>,
+    commands: ActionCommandList<C>,
+    contenders: Vec<usize>,
+    deadline: Option<Duration>,
+}
+
+impl<C: ActionConfiguration> Clone for ActionHeldCompletion<C> {
+    fn clone(&self) -> Self {
+        ActionHeldCompletion {
+            window: self.window,
+            recipe_idx: self.recipe_idx,
+            commands: self.commands.clone(),
+            contenders: self.contenders.clone(),
+            deadline: self.deadline,
+        }
+    }
+}
+
+impl<C: ActionConfiguration> ActionHeldCompletion<C> {
+    fn new(window: Option<Duration>) -> Self {
+        ActionHeldCompletion {
+            window,
+            recipe_idx: None,
+            commands: Vec::new(),
+            contenders: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Takes the commands `recipe_idx` issued from `mark` on out of `command_list`,
+    /// if hold-off is enabled and `contenders` went on with the same input.
+    fn hold(
+        &mut self,
+        recipe_idx: usize,
+        contenders: &[usize],
+        command_list: &mut ActionCommandList<C>,
+        mark: usize,
+        now: Duration,
+    ) -> bool {
+        let window = match self.window {
+            Some(window) if !contenders.is_empty() => window,
+            _ => return false,
+        };
+        self.contenders.clear();
+        self.contenders.extend_from_slice(contenders);
+        self.commands.clear();
+        let commands = &mut self.commands;
+        let mut position = 0;
+        command_list.retain(|command| {
+            position += 1;
+            if position > mark && command.0.recipe_idx == recipe_idx {
+                commands.push(command.clone());
+                return false;
+            }
+            true
+        });
+        self.recipe_idx = Some(recipe_idx);
+        self.deadline = Some(now + window);
+        true
+    }
+
+    /// Whether the completion of `recipe_idx` is held back; repeating it belongs to the
+    /// longer recipes then.
+    fn is_holding(&self, recipe_idx: usize) -> bool {
+        self.recipe_idx == Some(recipe_idx)
+    }
+
+    fn is_superseded(&self, recipes: &[(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)]) -> bool {
+        self.deadline.is_some()
+            && self
+                .contenders
+                .iter()
+                .all(|&recipe_idx| recipes[recipe_idx].1.is_none())
+    }
+
+    /// Settles what is held once `recipe_idx` completed: it is dropped if that was
+    /// one of the longer recipes, and goes through before anything else otherwise.
+    fn settle(&mut self, recipe_idx: usize, command_list: &mut ActionCommandList<C>, mark: usize) {
+        if self.deadline.is_none() {
+            return;
+        }
+        if self.contenders.contains(&recipe_idx) {
+            self.discard();
+        } else {
+            self.release(command_list, mark);
+        }
+    }
+
+    /// Puts the held commands back into `command_list` at `at`.
+    fn release(&mut self, command_list: &mut ActionCommandList<C>, at: usize) {
+        command_list.splice(at..at, self.commands.drain(..));
+        self.recipe_idx = None;
+        self.contenders.clear();
+        self.deadline = None;
+    }
+
+    fn discard(&mut self) {
+        self.recipe_idx = None;
+        self.commands.clear();
+        self.contenders.clear();
+        self.deadline = None;
+    }
+}
+
+/// What the context does with a `KeyDown` for a key that is already pressed, as
+/// operating systems report for auto-repeat.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ActionKeyRepeat {
+    /// The repeat is dropped before any recipe sees it.
+    Suppress,
+    /// The repeat is processed as another `KeyDown`.
+    #[default]
+    PassThrough,
+    /// The repeat is processed as `ActionInput::KeyRepeat`.
+    Distinct,
+}
+
+/// Which inputs of a run of consecutive `CursorCoordinate` inputs are kept when
+/// they are processed together, thinning out high polling rate pointers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ActionCursorCoalescing {
+    /// Keeps every input of the run.
+    #[default]
+    KeepAll,
+    /// Keeps only the last input of the run.
+    KeepLast,
+    /// Keeps the first and the last input of the run.
+    KeepFirstAndLast,
+    /// Keeps every n-th input of the run, and its last one.
+    Sample(usize),
+}
+
+impl ActionCursorCoalescing {
+    fn keeps(self, position: usize, run_len: usize) -> bool {
+        let last = position + 1 == run_len;
+        match self {
+            ActionCursorCoalescing::KeepAll => true,
+            ActionCursorCoalescing::KeepLast => last,
+            ActionCursorCoalescing::KeepFirstAndLast => position == 0 || last,
+            ActionCursorCoalescing::Sample(n) => last || position.is_multiple_of(n.max(1)),
+        }
+    }
 }
 
 pub(crate) struct ActionEnvironmentTrackingState<C: ActionConfiguration> {
-    pressed_keys: BTreeSet<C::KeyKind>,
+    pressed_keys: KeySet<C::KeyKind>,
+    // the context time each pressed key went down at.
+    key_pressed_at: KeyMap<C::KeyKind, Duration>,
+    // the key released last and how long it was held.
+    released_key: Option<(C::KeyKind, Duration)>,
+    sticky_keys: KeySet<C::KeyKind>,
+    armed_keys: KeySet<C::KeyKind>,
+    // the sticky key pressed last, as long as no other key was pressed since.
+    sticky_key_down: Option<C::KeyKind>,
+    key_repeat: ActionKeyRepeat,
+    cursor_target: Option<C::Target>,
+    // the cursor target before the last cursor input, to tell transitions apart.
+    previous_cursor_target: Option<C::Target>,
+    focused_target: Option<C::Target>,
+    axis_values: KeyMap<C::KeyKind, f32>,
+    stylus: Option<ActionStylus>,
+    touches: Vec<ActionTouch>,
+    ext: C::EnvExt,
+}
+
+/// A command on its way to the queue, as handed to an `ActionCommandFilter`.
+pub struct ActionIssuedCommand<C: ActionConfiguration> {
+    source: ActionCommandSource,
+    command: Shared<C::Command>,
+}
+
+impl<C: ActionConfiguration> ActionIssuedCommand<C> {
+    /// The recipe that issued the command.
+    pub fn source(&self) -> ActionRecipeHandle {
+        ActionRecipeHandle(self.source.recipe_idx)
+    }
+
+    /// The execution that issued the command, `None` for those the context issues
+    /// on its own.
+    pub fn execution_id(&self) -> Option<ActionExecutionId> {
+        self.source.execution_id
+    }
+
+    pub fn command(&self) -> &C::Command {
+        &self.command
+    }
+
+    /// Replaces the command, keeping its source, for example with one merged from
+    /// several.
+    pub fn set_command(&mut self, command: C::Command) {
+        self.command = Shared::new(command);
+    }
+}
+
+/// Post-processes the commands a single `ActionContext::process_input` or
+/// `ActionContext::update` call produced before they are queued, and may merge,
+/// drop or reorder them. Installed with `ActionContextBuilder::with_command_filter`.
+pub trait ActionCommandFilter<C: ActionConfiguration> {
+    fn filter(&mut self, commands: &mut Vec<ActionIssuedCommand<C>>);
+}
+
+impl<C: ActionConfiguration, F> ActionCommandFilter<C> for F
+where
+    F: FnMut(&mut Vec<ActionIssuedCommand<C>>),
+{
+    fn filter(&mut self, commands: &mut Vec<ActionIssuedCommand<C>>) {
+        self(commands)
+    }
+}
+
+trait ActionCommandFilterObj<C: ActionConfiguration>: ActionCommandFilter<C> + MaybeSend {}
+
+impl<C: ActionConfiguration, F: ActionCommandFilter<C> + MaybeSend> ActionCommandFilterObj<C>
+    for F
+{
+}
+
+type ActionCommandFilterCell<'f, C> = GeneratorCell<Box<dyn ActionCommandFilterObj<C> + 'f>>;
+
+trait ActionFallbackFn<C: ActionConfiguration>:
+    FnMut(&ActionInput<C>) -> Option<C::Command> + MaybeSend
+{
+}
+
+impl<C: ActionConfiguration, F> ActionFallbackFn<C> for F where
+    F: FnMut(&ActionInput<C>) -> Option<C::Command> + MaybeSend
+{
+}
+
+type ActionFallbackCell<'f, C> = GeneratorCell<Box<dyn ActionFallbackFn<C> + 'f>>;
+
+/// What an interceptor added with `ActionContextBuilder::add_interceptor` does with
+/// an input before recipes see it.
+pub enum ActionInterceptResult<C: ActionConfiguration> {
+    /// Hands the input, changed or not, on to the next interceptor.
+    Pass(ActionInput<C>),
+    /// Hands each of these inputs on to the next interceptor in turn.
+    Split(Vec<ActionInput<C>>),
+    /// Drops the input.
+    Drop,
+}
+
+trait ActionInterceptorFn<'f, C: ActionConfiguration>:
+    FnMut(ActionInput<C>) -> ActionInterceptResult<C> + MaybeSend
+{
+    fn clone_box(&self) -> Box<dyn ActionInterceptorFn<'f, C> + 'f>;
+}
+
+impl<'f, C: ActionConfiguration, F> ActionInterceptorFn<'f, C> for F
+where
+    F: FnMut(ActionInput<C>) -> ActionInterceptResult<C> + Clone + MaybeSend + 'f,
+{
+    fn clone_box(&self) -> Box<dyn ActionInterceptorFn<'f, C> + 'f> {
+        Box::new(self.clone())
+    }
+}
+
+type ActionInterceptorCell<'f, C> = GeneratorCell<Box<dyn ActionInterceptorFn<'f, C> + 'f>>;
+
+/// Hit-tests raw cursor positions, turning them into the targets recipes match
+/// against. Installed with `ActionContextBuilder::with_target_resolver`.
+pub trait ActionTargetResolver<C: ActionConfiguration> {
+    fn resolve_target(&self, position: &C::CursorPos) -> C::Target;
+
+    /// A cheap check that `position` is still over `target`, the last target resolved,
+    /// which saves the full hit test while the cursor moves within a widget.
+    fn still_over(&self, _target: &C::Target, _position: &C::CursorPos) -> bool {
+        false
+    }
+}
+
+impl<C: ActionConfiguration, F> ActionTargetResolver<C> for F
+where
+    F: Fn(&C::CursorPos) -> C::Target,
+{
+    fn resolve_target(&self, position: &C::CursorPos) -> C::Target {
+        self(position)
+    }
+}
+
+trait ActionTargetResolverObj<C: ActionConfiguration>: ActionTargetResolver<C> + MaybeSendSync {}
+
+impl<C: ActionConfiguration, R: ActionTargetResolver<C> + MaybeSendSync> ActionTargetResolverObj<C>
+    for R
+{
+}
+
+/// What processing a single input did, as returned by
+/// `ActionContext::process_input_with_outcome`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ActionInputOutcome {
+    effect_occurred: bool,
+    consumed: bool,
+    matched: bool,
+}
+
+impl ActionInputOutcome {
+    /// Whether any execution started, advanced or ended, the value `process_input` returns.
+    pub fn effect_occurred(&self) -> bool {
+        self.effect_occurred
+    }
+
+    /// Whether a recipe marked with `with_input_consumption` matched the input.
+    pub fn is_consumed(&self) -> bool {
+        self.consumed
+    }
+
+    /// Whether any recipe matched the input, starting, advancing or completing an
+    /// execution; the fallback set with `ActionContextBuilder::with_fallback` only
+    /// sees the inputs none did.
+    pub fn is_matched(&self) -> bool {
+        self.matched
+    }
+}
+
+/// What `ActionContext::peek_input` found an input would do.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ActionPeekResult {
+    advanced: Vec<ActionRecipeHandle>,
+    completed: Vec<ActionRecipeHandle>,
+    consumed: bool,
+}
+
+impl ActionPeekResult {
+    /// The recipes the input would start or move on without completing them.
+    pub fn advanced(&self) -> &[ActionRecipeHandle] {
+        &self.advanced
+    }
+
+    /// The recipes the input would complete.
+    pub fn completed(&self) -> &[ActionRecipeHandle] {
+        &self.completed
+    }
+
+    /// Whether a recipe marked with `with_input_consumption` would match the input.
+    pub fn is_consumed(&self) -> bool {
+        self.consumed
+    }
+
+    /// Whether the input would leave every recipe as it is.
+    pub fn is_empty(&self) -> bool {
+        self.advanced.is_empty() && self.completed.is_empty()
+    }
+}
+
+/// Read-only view of the environment the context tracks from the inputs it has seen,
+/// handed to custom conditions.
+pub struct ActionEnvironmentView<'a, C: ActionConfiguration> {
+    state: &'a ActionEnvironmentTrackingState<C>,
+}
+
+impl<'a, C: ActionConfiguration> ActionEnvironmentView<'a, C> {
+    pub(crate) fn new(state: &'a ActionEnvironmentTrackingState<C>) -> Self {
+        ActionEnvironmentView { state }
+    }
+
+    pub fn is_key_pressed(&self, key: &C::KeyKind) -> bool {
+        self.state.is_key_pressed(key)
+    }
+
+    pub fn pressed_keys(&self) -> impl Iterator<Item = &'a C::KeyKind> + 'a {
+        self.state.pressed_keys.iter()
+    }
+
+    /// The context time the pressed `key` went down at.
+    pub fn key_pressed_at(&self, key: &C::KeyKind) -> Option<Duration> {
+        self.state.key_pressed_at.get(key).cloned()
+    }
+
+    /// Whether the sticky key was pressed and released on its own since the last
+    /// completed recipe.
+    pub fn is_key_armed(&self, key: &C::KeyKind) -> bool {
+        self.state.is_key_armed(key)
+    }
+
+    /// The target of the last `CursorCoordinate` input.
+    pub fn cursor_target(&self) -> Option<&'a C::Target> {
+        self.state.cursor_target.as_ref()
+    }
+
+    /// The target of the last `FocusCoordinate` input.
+    pub fn focused_target(&self) -> Option<&'a C::Target> {
+        self.state.focused_target.as_ref()
+    }
+
+    /// The last value of an `Axis` input for `axis`.
+    pub fn axis_value(&self, axis: &C::KeyKind) -> Option<f32> {
+        self.state.axis_value(axis)
+    }
+
+    /// The state of the last `Stylus` input.
+    pub fn stylus(&self) -> Option<ActionStylus> {
+        self.state.stylus
+    }
+
+    /// The fingers currently touching, in the order they touched down.
+    pub fn touches(&self) -> &'a [ActionTouch] {
+        &self.state.touches
+    }
+
+    /// The application's own environment state.
+    pub fn ext(&self) -> &'a C::EnvExt {
+        &self.state.ext
+    }
+}
+
+impl<C: ActionConfiguration> Clone for ActionEnvironmentTrackingState<C>
+where
+    C::EnvExt: Clone,
+{
+    fn clone(&self) -> Self {
+        ActionEnvironmentTrackingState {
+            pressed_keys: self.pressed_keys.clone(),
+            key_pressed_at: self.key_pressed_at.clone(),
+            released_key: self.released_key.clone(),
+            sticky_keys: self.sticky_keys.clone(),
+            armed_keys: self.armed_keys.clone(),
+            sticky_key_down: self.sticky_key_down.clone(),
+            key_repeat: self.key_repeat,
+            cursor_target: self.cursor_target.clone(),
+            previous_cursor_target: self.previous_cursor_target.clone(),
+            focused_target: self.focused_target.clone(),
+            axis_values: self.axis_values.clone(),
+            stylus: self.stylus,
+            touches: self.touches.clone(),
+            ext: self.ext.clone(),
+        }
+    }
+}
+
+impl<C: ActionConfiguration> fmt::Debug for ActionEnvironmentTrackingState<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("ActionEnvironmentTrackingState")
+            .field("pressed_keys", &self.pressed_keys)
+            .field("armed_keys", &self.armed_keys)
+            .field("cursor_target", &self.cursor_target)
+            .field("focused_target", &self.focused_target)
+            .field("axis_values", &self.axis_values)
+            .field("stylus", &self.stylus)
+            .field("touches", &self.touches)
+            .finish()
+    }
 }
 
 impl<C: ActionConfiguration> ActionEnvironmentTrackingState<C> {
-    fn new() -> Self {
+    fn new(sticky_keys: KeySet<C::KeyKind>, key_repeat: ActionKeyRepeat) -> Self {
         ActionEnvironmentTrackingState {
-            pressed_keys: BTreeSet::new(),
+            pressed_keys: KeySet::new(),
+            key_pressed_at: KeyMap::new(),
+            released_key: None,
+            sticky_keys,
+            armed_keys: KeySet::new(),
+            sticky_key_down: None,
+            key_repeat,
+            cursor_target: None,
+            previous_cursor_target: None,
+            focused_target: None,
+            axis_values: KeyMap::new(),
+            stylus: None,
+            touches: Vec::new(),
+            ext: C::EnvExt::default(),
         }
     }
 
-    fn update_with_input(&mut self, input: &ActionInput<C>) {
+    /// Applies the auto-repeat policy to a `KeyDown` of a key that is already pressed,
+    /// `None` meaning the input is dropped.
+    fn filter_key_repeat<'i>(&self, input: &'i ActionInput<C>) -> Option<Cow<'i, ActionInput<C>>> {
+        match input {
+            ActionInput::KeyDown(key) if self.pressed_keys.contains(key) => match self.key_repeat {
+                ActionKeyRepeat::Suppress => None,
+                ActionKeyRepeat::PassThrough => Some(Cow::Borrowed(input)),
+                ActionKeyRepeat::Distinct => Some(Cow::Owned(ActionInput::KeyRepeat(key.clone()))),
+            },
+            _ => Some(Cow::Borrowed(input)),
+        }
+    }
+
+    fn update_with_input(&mut self, input: &ActionInput<C>, now: Duration) {
         match input {
             ActionInput::KeyDown(c) => {
-                self.pressed_keys.insert(c.clone());
+                if self.pressed_keys.insert(c.clone()) {
+                    self.key_pressed_at.insert(c.clone(), now);
+                }
+                self.sticky_key_down = if self.sticky_keys.contains(c) {
+                    Some(c.clone())
+                } else {
+                    None
+                };
             }
             ActionInput::KeyUp(c) => {
                 self.pressed_keys.remove(c);
+                self.released_key = self
+                    .key_pressed_at
+                    .remove(c)
+                    .map(|pressed_at| (c.clone(), now.saturating_sub(pressed_at)));
+                if self.sticky_key_down.as_ref() == Some(c) {
+                    self.sticky_key_down = None;
+                    // tapping an armed key again releases it.
+                    if !self.armed_keys.remove(c) {
+                        self.armed_keys.insert(c.clone());
+                    }
+                }
+            }
+            ActionInput::CursorCoordinate(target) => {
+                self.previous_cursor_target = self.cursor_target.replace(target.clone());
+            }
+            ActionInput::FocusCoordinate(target) => {
+                self.focused_target = Some(target.clone());
+            }
+            ActionInput::KeyRepeat(_) | ActionInput::Character(_) => {}
+            ActionInput::Axis(axis, value) => {
+                self.axis_values.insert(axis.clone(), *value);
+            }
+            ActionInput::Stylus(_, stylus) => {
+                self.stylus = Some(*stylus);
+            }
+            ActionInput::TouchStart(touch) | ActionInput::TouchMove(touch) => {
+                match self.touches.iter_mut().find(|t| t.id == touch.id) {
+                    Some(tracked) => *tracked = *touch,
+                    None => self.touches.push(*touch),
+                }
+            }
+            ActionInput::TouchEnd(touch) => {
+                self.touches.retain(|t| t.id != touch.id);
             }
-            _ => {}
         }
     }
 
     pub(crate) fn is_key_pressed(&self, key: &C::KeyKind) -> bool {
         self.pressed_keys.contains(key)
     }
+
+    /// How long `key` was held, if it is the key released last.
+    pub(crate) fn released_key_hold(&self, key: &C::KeyKind) -> Option<Duration> {
+        match &self.released_key {
+            Some((released, held)) if released == key => Some(*held),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_key_armed(&self, key: &C::KeyKind) -> bool {
+        self.armed_keys.contains(key)
+    }
+
+    fn reset(&mut self) {
+        let ext = mem::take(&mut self.ext);
+        let sticky_keys = mem::take(&mut self.sticky_keys);
+        *self = ActionEnvironmentTrackingState {
+            ext,
+            ..ActionEnvironmentTrackingState::new(sticky_keys, self.key_repeat)
+        };
+    }
+
+    fn seed_pressed_keys<I>(&mut self, keys: I)
+    where
+        I: IntoIterator<Item = C::KeyKind>,
+    {
+        self.pressed_keys = keys.into_iter().collect();
+        let pressed_keys = &self.pressed_keys;
+        self.key_pressed_at
+            .retain(|key, _| pressed_keys.contains(key));
+        self.released_key = None;
+        self.sticky_key_down = None;
+    }
+
+    fn release_keys(&mut self) {
+        self.pressed_keys.clear();
+        self.key_pressed_at.clear();
+        self.released_key = None;
+        self.armed_keys.clear();
+        self.sticky_key_down = None;
+        self.touches.clear();
+    }
+
+    fn disarm_keys(&mut self) {
+        self.armed_keys.clear();
+    }
+
+    pub(crate) fn cursor_target(&self) -> Option<&C::Target> {
+        self.cursor_target.as_ref()
+    }
+
+    pub(crate) fn previous_cursor_target(&self) -> Option<&C::Target> {
+        self.previous_cursor_target.as_ref()
+    }
+
+    pub(crate) fn focused_target(&self) -> Option<&C::Target> {
+        self.focused_target.as_ref()
+    }
+
+    pub(crate) fn axis_value(&self, axis: &C::KeyKind) -> Option<f32> {
+        self.axis_values.get(axis).cloned()
+    }
 }
 
-pub(crate) struct ActionRecipeItemStore<C: ActionConfiguration>(Slab<ActionRecipeItem<C>>);
+pub(crate) struct ActionRecipeItemStore<'f, C: ActionConfiguration>(Slab<ActionRecipeItem<'f, C>>);
 
-impl<C: ActionConfiguration> ActionRecipeItemStore<C> {
+impl<'f, C: ActionConfiguration> ActionRecipeItemStore<'f, C> {
     fn new() -> Self {
         ActionRecipeItemStore(Slab::new())
     }
 
-    pub(crate) fn register_item(&mut self, item: ActionRecipeItem<C>) -> ActionRecipeItemIdx {
+    pub(crate) fn register_item(&mut self, item: ActionRecipeItem<'f, C>) -> ActionRecipeItemIdx {
         ActionRecipeItemIdx(self.0.insert(item))
     }
 
-    pub(crate) fn get(&self, idx: ActionRecipeItemIdx) -> &ActionRecipeItem<C> {
+    pub(crate) fn get(
+        &self,
+        idx: ActionRecipeItemIdx,
+    ) -> Result<&ActionRecipeItem<'f, C>, ConcertoError> {
+        self.0.get(idx.0).ok_or(ConcertoError::UnknownItem(idx))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    // replaces the `from` inputs the items reachable from `item_idx` wait for.
+    fn rebind_input(
+        &mut self,
+        item_idx: ActionRecipeItemIdx,
+        from: &ActionInput<C>,
+        to: &Shared<ActionInput<C>>,
+    ) -> Result<usize, ConcertoError> {
+        let item = self.get(item_idx)?;
+        if item.is_compound() {
+            let seq = item.compound_sequence(item_idx)?.to_vec();
+            let mut replaced = 0;
+            for seq_item_idx in seq {
+                replaced += self.rebind_input(seq_item_idx, from, to)?;
+            }
+            return Ok(replaced);
+        }
+        match self.0.get_mut(item_idx.0) {
+            Some(ActionRecipeItem::StartInput(input)) if **input == *from => {
+                *input = to.clone();
+                Ok(1)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    pub(crate) fn item_indices(&self) -> Vec<ActionRecipeItemIdx> {
         self.0
-            .get(idx.0)
-            .expect("ActionRecipeItemStore out-of-bound access!")
+            .iter()
+            .map(|(idx, _)| ActionRecipeItemIdx(idx))
+            .collect()
     }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct ActionRecipeItemIdx(usize);
 
-impl<C: ActionConfiguration> ActionContext<C> {
+/// Refers to a top-level recipe of a context, see `ActionContext::recipe_handle`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ActionRecipeHandle(usize);
+
+/// Identifies one execution of a recipe, unique among those of its context.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ActionExecutionId(u64);
+
+/// Clones share the recipe items, including the state of `FnMut` generators, and the
+/// lifecycle observers, while executions in progress and pending commands are copied.
+impl<'f, C: ActionConfiguration> Clone for ActionContext<'f, C>
+where
+    C::EnvExt: Clone,
+{
+    fn clone(&self) -> Self {
+        ActionContext {
+            recipe_items: self.recipe_items.clone(),
+            recipes: self.recipes.clone(),
+            command_list: self.command_list.clone(),
+            command_batch_marks: self.command_batch_marks.clone(),
+            next_execution_id: self.next_execution_id,
+            env_tracking_state: self.env_tracking_state.clone(),
+            current_time: self.current_time,
+            diagnostics: self.diagnostics.clone(),
+            observers: self.observers.clone(),
+            prefixes: self.prefixes.clone(),
+            prefix_state: self.prefix_state,
+            held_completion: self.held_completion.clone(),
+            cursor_coalescing: self.cursor_coalescing,
+            focus_lost_command: self.focus_lost_command.clone(),
+            command_filter: self.command_filter.clone(),
+            fallback: self.fallback.clone(),
+            interceptors: self.interceptors.clone(),
+            target_resolver: self.target_resolver.clone(),
+            time_source: self.time_source.clone(),
+            time_source_reading: self.time_source_reading,
+            reorder_buffer: self.reorder_buffer.clone(),
+            accelerators: self.accelerators.clone(),
+            pending_cursor_position: self.pending_cursor_position.clone(),
+            resolved_cursor_target: self.resolved_cursor_target.clone(),
+            target_generation: self.target_generation,
+            dry_run: self.dry_run,
+            nest_recipe_commands: Vec::new(),
+            spare_nest_recipe_commands: Vec::new(),
+        }
+    }
+}
+
+// Commands carry no `Debug` bound, so only the number of pending ones is shown.
+impl<'f, C: ActionConfiguration> fmt::Debug for ActionContext<'f, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("ActionContext")
+            .field("recipes", &self.recipes)
+            .field("recipe_items", &self.recipe_items.len())
+            .field("pending_commands", &self.command_list.len())
+            .field("env_tracking_state", &self.env_tracking_state)
+            .field("current_time", &self.current_time)
+            .field("diagnostics", &self.diagnostics)
+            .field("pending_prefix", &self.pending_prefix())
+            .field("held_commands", &self.held_completion.commands.len())
+            .finish()
+    }
+}
+
+impl<'f, C: ActionConfiguration> ActionContext<'f, C> {
     fn locate_nest_recipe(
-        recipes: &Vec<(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)>,
+        recipes: &[(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)],
         recipe_idx: usize,
         nest_recipe_idx: usize,
     ) -> Option<usize> {
@@ -76,213 +965,1822 @@ impl<C: ActionConfiguration> ActionContext<C> {
         }
     }
 
-    pub fn process_inputs(&mut self, inputs: &[ActionInput<C>]) -> bool {
-        let mut result = false;
-        for input in inputs {
-            if self.process_input(input) {
-                result = true;
-            }
+    fn apply_nest_recipe_commands(
+        recipe_items: &ActionRecipeItemStore<C>,
+        observers: &ActionLifecycleObserverList,
+        recipes: &mut [(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)],
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        new_nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+    ) -> bool {
+        let mut some_effect_occurred = false;
+        while !nest_recipe_command_list.is_empty() {
+            debug_assert!(new_nest_recipe_command_list.is_empty());
+            for nest_recipe_cmd in nest_recipe_command_list.drain(..) {
+                match nest_recipe_cmd {
+                    ActionNestRecipeCommand::Enable(recipe_idx, nest_recipe_idx) => {
+                        if let Some(real_recipe_idx) =
+                            Self::locate_nest_recipe(recipes, recipe_idx, nest_recipe_idx)
+                        {
+                            debug!(target: "concerto", "nest recipe {} is now enabled.", recipes[real_recipe_idx].0.label(real_recipe_idx));
+                            recipes[real_recipe_idx].0.is_enabled = true;
+                        }
+                    }
+                    ActionNestRecipeCommand::Disable(recipe_idx, nest_recipe_idx) => {
+                        if let Some(real_recipe_idx) =
+                            Self::locate_nest_recipe(recipes, recipe_idx, nest_recipe_idx)
+                        {
+                            recipes[real_recipe_idx].0.is_enabled = false;
+                        }
+                    }
+                    ActionNestRecipeCommand::Abort(recipe_idx, nest_recipe_idx) => {
+                        if let Some(real_recipe_idx) =
+                            Self::locate_nest_recipe(recipes, recipe_idx, nest_recipe_idx)
+                        {
+                            recipes[real_recipe_idx].0.is_enabled = false;
+
+                            let (recipe, exec_ctx) = &mut recipes[real_recipe_idx];
+                            if let Some(exec_ctx) = exec_ctx {
+                                record_execution_event(
+                                    observers,
+                                    recipe,
+                                    real_recipe_idx,
+                                    ActionExecutionEvent::Aborted(ActionAbortReason::ParentEnded),
+                                );
+                                if exec_ctx.clean_up(
+                                    recipe_items,
+                                    recipe,
+                                    command_list,
+                                    new_nest_recipe_command_list,
+                                    true,
+                                ) {
+                                    some_effect_occurred = true;
+                                }
+                            }
+                            *exec_ctx = None;
+                        }
+                    }
+                }
+            }
+            std::mem::swap(nest_recipe_command_list, new_nest_recipe_command_list);
+        }
+        some_effect_occurred
+    }
+
+    /// Aborts every execution in favour of a recipe that completed, and disables the
+    /// nest recipes again.
+    fn supersede_executions(
+        recipe_items: &ActionRecipeItemStore<C>,
+        observers: &ActionLifecycleObserverList,
+        recipes: &mut [(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)],
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+    ) {
+        Self::abort_executions_except(
+            recipe_items,
+            observers,
+            recipes,
+            command_list,
+            nest_recipe_command_list,
+            &[],
+        );
+        for (recipe, _) in recipes.iter_mut() {
+            recipe.is_enabled = !recipe.is_nested;
+        }
+    }
+
+    /// Aborts the executions of all recipes but `kept` as superseded.
+    fn abort_executions_except(
+        recipe_items: &ActionRecipeItemStore<C>,
+        observers: &ActionLifecycleObserverList,
+        recipes: &mut [(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)],
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        kept: &[usize],
+    ) {
+        for (recipe_idx, (recipe, exec_ctx)) in recipes.iter_mut().enumerate() {
+            if kept.contains(&recipe_idx) {
+                continue;
+            }
+            if let Some(exec_ctx) = exec_ctx {
+                record_execution_event(
+                    observers,
+                    recipe,
+                    recipe_idx,
+                    ActionExecutionEvent::Aborted(ActionAbortReason::Superseded),
+                );
+                exec_ctx.clean_up(
+                    recipe_items,
+                    recipe,
+                    command_list,
+                    nest_recipe_command_list,
+                    true,
+                );
+            }
+            *exec_ctx = None;
+        }
+    }
+
+    fn disable_broken_recipe(
+        recipe: &mut ActionRecipe<C>,
+        recipe_idx: usize,
+        error: ConcertoError,
+        diagnostics: &mut Vec<ConcertoError>,
+    ) {
+        warn!(target: "concerto", "recipe {} is broken and has been disabled: {}", recipe.label(recipe_idx), error);
+        recipe.is_broken = true;
+        diagnostics.push(match &recipe.name {
+            Some(name) => error.in_recipe(name),
+            None => error,
+        });
+    }
+
+    pub fn process_inputs(&mut self, inputs: &[ActionInput<C>]) -> bool {
+        let mut result = false;
+        let mut position = 0;
+        while position < inputs.len() {
+            let run_len = inputs[position..]
+                .iter()
+                .take_while(|input| matches!(input, ActionInput::CursorCoordinate(_)))
+                .count()
+                .max(1);
+            for (run_position, input) in inputs[position..position + run_len].iter().enumerate() {
+                if self.cursor_coalescing.keeps(run_position, run_len) && self.process_input(input)
+                {
+                    result = true;
+                }
+            }
+            position += run_len;
+        }
+        result
+    }
+
+    pub fn process_input(&mut self, input: &ActionInput<C>) -> bool {
+        self.process_input_with_outcome(input).effect_occurred()
+    }
+
+    /// Processes `input`, which happened at `timestamp` in context time. With
+    /// `ActionContextBuilder::with_reorder_window`, it is held back until no earlier
+    /// input can still arrive, and processed by `update` or a later call once due.
+    pub fn process_timed_input(&mut self, input: ActionInput<C>, timestamp: Duration) -> bool {
+        if self.reorder_buffer.window.is_none() {
+            return self.process_input(&input);
+        }
+        self.reorder_buffer.insert(timestamp, input);
+        self.process_due_timed_inputs()
+    }
+
+    /// Processes the inputs the reorder window still holds back right away, e.g.
+    /// before the host stops delivering input.
+    pub fn flush_timed_inputs(&mut self) -> bool {
+        let mut some_effect_occurred = false;
+        while let Some((_, input)) = self.reorder_buffer.pending.pop_front() {
+            some_effect_occurred |= self.process_input(&input);
+        }
+        some_effect_occurred
+    }
+
+    fn process_due_timed_inputs(&mut self) -> bool {
+        let mut some_effect_occurred = false;
+        while let Some(input) = self.reorder_buffer.pop_due(self.current_time) {
+            some_effect_occurred |= self.process_input(&input);
+        }
+        some_effect_occurred
+    }
+
+    /// Like `process_input`, also telling whether a recipe that consumes its inputs
+    /// matched this one, so the host can keep it from other handlers.
+    pub fn process_input_with_outcome(&mut self, input: &ActionInput<C>) -> ActionInputOutcome {
+        self.sync_time();
+        if self.interceptors.is_empty() {
+            return self.process_intercepted_input(input);
+        }
+        let mut outcome = ActionInputOutcome::default();
+        for input in self.intercept_input(input) {
+            let input_outcome = self.process_intercepted_input(&input);
+            outcome.effect_occurred |= input_outcome.effect_occurred;
+            outcome.consumed |= input_outcome.consumed;
+            outcome.matched |= input_outcome.matched;
+        }
+        outcome
+    }
+
+    /// Runs `input` through the interceptor chain, returning what is left of it.
+    fn intercept_input(&self, input: &ActionInput<C>) -> Vec<ActionInput<C>> {
+        let mut inputs = vec![input.clone()];
+        for interceptor in self.interceptors.iter() {
+            inputs =
+                interceptor.with(|interceptor| Self::run_interceptor(&mut **interceptor, inputs));
+        }
+        inputs
+    }
+
+    fn run_interceptor(
+        interceptor: &mut dyn ActionInterceptorFn<'f, C>,
+        inputs: Vec<ActionInput<C>>,
+    ) -> Vec<ActionInput<C>> {
+        let mut passed = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            match (interceptor)(input) {
+                ActionInterceptResult::Pass(input) => passed.push(input),
+                ActionInterceptResult::Split(split) => passed.extend(split),
+                ActionInterceptResult::Drop => {}
+            }
+        }
+        passed
+    }
+
+    /// Copies of the interceptors, in their current state.
+    fn copy_interceptors(&self) -> Vec<Box<dyn ActionInterceptorFn<'f, C> + 'f>> {
+        self.interceptors
+            .iter()
+            .map(|interceptor| interceptor.with(|interceptor| interceptor.clone_box()))
+            .collect()
+    }
+
+    fn process_intercepted_input(&mut self, input: &ActionInput<C>) -> ActionInputOutcome {
+        if let ActionInput::CursorCoordinate(_) = input {
+            self.pending_cursor_position = None;
+        } else {
+            self.resolve_pending_cursor_position();
+        }
+        let command_mark = self.start_command_batch();
+        let outcome = match self.env_tracking_state.filter_key_repeat(input) {
+            Some(input) => {
+                let mut outcome = self.dispatch_input(&input, None);
+                if !outcome.matched && self.issue_fallback(&input) {
+                    outcome.effect_occurred = true;
+                }
+                outcome
+            }
+            None => {
+                debug!(target: "concerto", "dropped key repeat {:?}.", input);
+                ActionInputOutcome::default()
+            }
+        };
+        self.filter_commands(command_mark);
+        outcome
+    }
+
+    /// Processes a cursor input at the raw `position`, hit-tested with the resolver
+    /// installed with `ActionContextBuilder::with_target_resolver`. While no execution
+    /// is in progress and no recipe may start with a cursor input, the position is only
+    /// resolved once another input or time step needs to know the cursor target.
+    pub fn process_cursor_position(&mut self, position: C::CursorPos) -> bool {
+        if self.target_resolver.is_none() {
+            warn!(target: "concerto", "cursor position dropped, no target resolver is installed.");
+            return false;
+        }
+        if !self.wants_cursor_input() {
+            self.pending_cursor_position = Some(position);
+            return false;
+        }
+        match self.resolve_target(position) {
+            Some(target) => self.process_input(&ActionInput::CursorCoordinate(target)),
+            None => false,
+        }
+    }
+
+    /// Tells the context the targets under the cursor positions may have changed, e.g.
+    /// after a layout pass, so the cached target is hit-tested again. Under the same
+    /// generation, the target is reused while the cursor stays put or, as far as
+    /// `ActionTargetResolver::still_over` tells, over it.
+    pub fn set_target_generation(&mut self, generation: u64) {
+        self.target_generation = generation;
+    }
+
+    fn resolve_target(&mut self, position: C::CursorPos) -> Option<C::Target> {
+        let generation = self.target_generation;
+        let target_resolver = self.target_resolver.as_ref()?;
+        if let Some((cached_position, cached_generation, target)) = &self.resolved_cursor_target {
+            if *cached_generation == generation
+                && (*cached_position == position || target_resolver.still_over(target, &position))
+            {
+                return Some(target.clone());
+            }
+        }
+        let target = target_resolver.resolve_target(&position);
+        self.resolved_cursor_target = Some((position, generation, target.clone()));
+        Some(target)
+    }
+
+    fn wants_cursor_input(&self) -> bool {
+        let pending_prefix = self.prefix_state.pending;
+        self.recipes.iter().any(|(recipe, exec_ctx)| {
+            exec_ctx.is_some()
+                || (recipe.starts_with_cursor
+                    && recipe.is_enabled
+                    && !recipe.is_broken
+                    && (recipe.is_nested || recipe.prefix == pending_prefix))
+        })
+    }
+
+    // keeps the tracked cursor target current without dispatching the skipped input.
+    fn resolve_pending_cursor_position(&mut self) {
+        let target = match self.pending_cursor_position.take() {
+            Some(position) => self.resolve_target(position),
+            None => return,
+        };
+        if let Some(target) = target {
+            self.env_tracking_state
+                .update_with_input(&ActionInput::CursorCoordinate(target), self.current_time);
+        }
+    }
+
+    fn issue_fallback(&mut self, input: &ActionInput<C>) -> bool {
+        let command = match &self.fallback {
+            Some(fallback) => fallback.with(|fallback| (fallback)(input)),
+            None => return false,
+        };
+        match command {
+            Some(command) => {
+                let source = ActionCommandSource {
+                    recipe_idx: FALLBACK_SOURCE,
+                    execution_id: None,
+                };
+                self.command_list.push((source, Shared::new(command)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks the commands queued from now on as a new batch, returning where it starts.
+    fn start_command_batch(&mut self) -> usize {
+        let mark = self.command_list.len();
+        if mark > 0 && self.command_batch_marks.last() != Some(&mark) {
+            self.command_batch_marks.push(mark);
+        }
+        mark
+    }
+
+    /// Hands the commands queued from `mark` on to the command filter, if any.
+    fn filter_commands(&mut self, mark: usize) {
+        let command_filter = match &self.command_filter {
+            Some(command_filter) if self.command_list.len() > mark => command_filter,
+            _ => return,
+        };
+        let mut commands = self
+            .command_list
+            .drain(mark..)
+            .map(|(source, command)| ActionIssuedCommand { source, command })
+            .collect();
+        command_filter.with(|command_filter| command_filter.filter(&mut commands));
+        self.command_list.extend(
+            commands
+                .into_iter()
+                .map(|issued| (issued.source, issued.command)),
+        );
+    }
+
+    /// Tells which recipes `input` would start, advance or complete if it were
+    /// processed now, without changing any execution or issuing any command. The
+    /// input goes through copies of the interceptors, and each input they turn it
+    /// into is peeked on its own.
+    pub fn peek_input(&self, input: &ActionInput<C>) -> ActionPeekResult
+    where
+        C::EnvExt: Clone,
+    {
+        let mut inputs = vec![input.clone()];
+        for mut interceptor in self.copy_interceptors() {
+            inputs = Self::run_interceptor(&mut *interceptor, inputs);
+        }
+        let mut peek_result = ActionPeekResult::default();
+        for input in inputs.iter() {
+            let input_result = self.peek_intercepted_input(input);
+            peek_result.advanced.extend(input_result.advanced);
+            peek_result.completed.extend(input_result.completed);
+            peek_result.consumed |= input_result.consumed;
+        }
+        peek_result
+    }
+
+    fn peek_intercepted_input(&self, input: &ActionInput<C>) -> ActionPeekResult
+    where
+        C::EnvExt: Clone,
+    {
+        let mut peek_result = ActionPeekResult::default();
+        let input = match self.env_tracking_state.filter_key_repeat(input) {
+            Some(input) => input,
+            None => return peek_result,
+        };
+        let mut env_tracking_state = self.env_tracking_state.clone();
+        env_tracking_state.update_with_input(&input, self.current_time);
+
+        for (recipe_idx, (recipe, exec_ctx)) in self.recipes.iter().enumerate() {
+            let result = match exec_ctx {
+                Some(exec_ctx) => exec_ctx.peek_input(
+                    &input,
+                    &self.recipe_items,
+                    recipe,
+                    &env_tracking_state,
+                    self.current_time,
+                ),
+                None => continue,
+            };
+            match result {
+                Ok(ExecutionContextResult::Done) => {
+                    peek_result.completed.push(ActionRecipeHandle(recipe_idx));
+                }
+                Ok(ExecutionContextResult::Used) => {
+                    peek_result.advanced.push(ActionRecipeHandle(recipe_idx));
+                }
+                _ => continue,
+            }
+            peek_result.consumed |= recipe.consumes_inputs;
+        }
+        // a completion supersedes, holds back or takes the input from the recipes
+        // that would otherwise start.
+        if !peek_result.completed.is_empty() {
+            return peek_result;
+        }
+
+        let pending_prefix = self.prefix_state.pending;
+        let accelerated = self.accelerators.candidates(&input);
+        for recipe_idx in self.accelerators.start_order(accelerated) {
+            let (recipe, exec_ctx) = &self.recipes[recipe_idx];
+            if !recipe.is_enabled || recipe.is_broken || exec_ctx.is_some() {
+                continue;
+            }
+            if !recipe.is_nested && recipe.prefix != pending_prefix {
+                continue;
+            }
+            let execution_id = ActionExecutionId(self.next_execution_id);
+            let peeked = if self.accelerators.is_accelerated(recipe_idx) {
+                ActionExecutionCtx::complete_accelerated(
+                    &input,
+                    &self.recipe_items,
+                    recipe,
+                    recipe_idx,
+                    execution_id,
+                    &mut Vec::new(),
+                    &env_tracking_state,
+                )
+            } else {
+                ActionExecutionCtx::peek_start_with_input(
+                    &input,
+                    &self.recipe_items,
+                    recipe,
+                    recipe_idx,
+                    execution_id,
+                    &env_tracking_state,
+                    self.current_time,
+                )
+            };
+            match peeked {
+                // after a completion, only the longer recipes go on.
+                Ok(ExecutionContextResult::Done) if peek_result.completed.is_empty() => {
+                    peek_result.completed.push(ActionRecipeHandle(recipe_idx));
+                    peek_result.consumed |= recipe.consumes_inputs;
+                    if self.held_completion.window.is_none() || recipe.is_repeatable {
+                        break;
+                    }
+                }
+                Ok(ExecutionContextResult::Used) => {
+                    peek_result.advanced.push(ActionRecipeHandle(recipe_idx));
+                    peek_result.consumed |= recipe.consumes_inputs;
+                }
+                _ => {}
+            }
+        }
+        peek_result
+    }
+
+    /// Runs `inputs` through a copy of the context and returns the commands they
+    /// would issue, leaving this context as it is. As the copy calls no callback, the
+    /// commands of generators, the fallback and the command filter are left out and no
+    /// lifecycle observer is notified, while the inputs go through copies of the
+    /// interceptors.
+    pub fn simulate(&self, inputs: &[ActionInput<C>]) -> Vec<C::Command>
+    where
+        C::EnvExt: Clone,
+        C::Command: Clone,
+    {
+        let mut context = self.clone();
+        context.dry_run = true;
+        for (_, exec_ctx) in context.recipes.iter_mut() {
+            if let Some(exec_ctx) = exec_ctx {
+                exec_ctx.set_dry_run();
+            }
+        }
+        context.observers = Shared::new(GeneratorCell::new(Vec::new()));
+        context.command_filter = None;
+        context.fallback = None;
+        context.interceptors = self
+            .copy_interceptors()
+            .into_iter()
+            .map(|interceptor| Shared::new(GeneratorCell::new(interceptor)))
+            .collect();
+        context.command_list.clear();
+        context.command_batch_marks.clear();
+        context.process_inputs(inputs);
+        context
+            .collect_commands()
+            .map(|commands| commands.collect())
+            .unwrap_or_default()
+    }
+
+    /// With `dwell_since`, the input only goes to the executions with a dwell that
+    /// came due after that time, and starts nothing.
+    #[allow(unused_assignments, unused_labels)]
+    fn dispatch_input(
+        &mut self,
+        input: &ActionInput<C>,
+        dwell_since: Option<Duration>,
+    ) -> ActionInputOutcome {
+        //use std::mem::drop;
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!(target: "concerto", "process_input", input = ?input).entered();
+        debug!(target: "concerto", "process_input {:?}.", input);
+        self.env_tracking_state
+            .update_with_input(input, self.current_time);
+
+        let command_mark = self.command_list.len();
+        let hold_off = self.held_completion.window;
+        let mut some_recipe_finished = false;
+        let mut some_repeatable_recipe_finished = false;
+        let mut completion_held = false;
+        // the executions this input took part in, tracked for hold-off only.
+        let mut advanced_recipes = Vec::new();
+        let mut completed_recipe = None;
+        let mut some_effect_occurred = false;
+        let mut input_consumed = false;
+        let mut input_matched = false;
+        //first, let's see if we can procede with existing half-baked recipes.
+        let recipe_items = &self.recipe_items;
+        let observers = &*self.observers;
+        let command_list = &mut self.command_list;
+        let env_tracking_state = &self.env_tracking_state;
+        let diagnostics = &mut self.diagnostics;
+        let now = self.current_time;
+        let temporary_nest_recipe_command_list = &mut self.nest_recipe_commands;
+        temporary_nest_recipe_command_list.clear();
+        'step_1: for &recipe_idx in self.accelerators.others.iter() {
+            // accelerated recipes never keep an execution.
+            let (recipe, exec_ctx) = &mut self.recipes[recipe_idx];
+            let mut remove_exec_ctx = false;
+            let mut completed = false;
+            if let (Some(since), Some(ctx)) = (dwell_since, exec_ctx.as_ref()) {
+                if ctx
+                    .dwell_deadlines(recipe, since)
+                    .all(|deadline| deadline > now)
+                {
+                    continue;
+                }
+            }
+            if let Some(exec_ctx) = exec_ctx {
+                match exec_ctx.process_input(
+                    input,
+                    recipe_items,
+                    recipe,
+                    command_list,
+                    temporary_nest_recipe_command_list,
+                    env_tracking_state,
+                    now,
+                ) {
+                    Ok(ExecutionContextResult::Done) => {
+                        input_consumed |= recipe.consumes_inputs;
+                        input_matched = true;
+                        record_execution_event(
+                            observers,
+                            recipe,
+                            recipe_idx,
+                            ActionExecutionEvent::Advanced(None),
+                        );
+                        record_execution_event(
+                            observers,
+                            recipe,
+                            recipe_idx,
+                            ActionExecutionEvent::Completed(
+                                now.saturating_sub(exec_ctx.started_at()),
+                            ),
+                        );
+                        if recipe.is_repeatable {
+                            some_repeatable_recipe_finished = true;
+                            some_effect_occurred = true;
+                        } else {
+                            some_recipe_finished = true;
+                        }
+                        completed_recipe = Some(recipe_idx);
+                        remove_exec_ctx = true;
+                        completed = true;
+                    }
+                    Ok(ExecutionContextResult::Used) => {
+                        input_consumed |= recipe.consumes_inputs;
+                        input_matched = true;
+                        if hold_off.is_some() {
+                            advanced_recipes.push(recipe_idx);
+                        }
+                        record_execution_event(
+                            observers,
+                            recipe,
+                            recipe_idx,
+                            ActionExecutionEvent::Advanced(exec_ctx.current_item()),
+                        );
+                        some_effect_occurred = true;
+                        remove_exec_ctx = false;
+                    }
+                    Ok(ExecutionContextResult::Ignore) => {
+                        remove_exec_ctx = false;
+                    }
+                    Ok(ExecutionContextResult::Abort) => {
+                        record_execution_event(
+                            observers,
+                            recipe,
+                            recipe_idx,
+                            ActionExecutionEvent::Aborted(ActionAbortReason::Input),
+                        );
+                        remove_exec_ctx = true;
+                    }
+                    Err(error) => {
+                        record_execution_event(
+                            observers,
+                            recipe,
+                            recipe_idx,
+                            ActionExecutionEvent::Aborted(ActionAbortReason::Broken),
+                        );
+                        Self::disable_broken_recipe(recipe, recipe_idx, error, diagnostics);
+                        remove_exec_ctx = true;
+                    }
+                };
+            }
+
+            if remove_exec_ctx {
+                if let Some(exec_ctx) = exec_ctx {
+                    if exec_ctx.clean_up(
+                        recipe_items,
+                        recipe,
+                        command_list,
+                        temporary_nest_recipe_command_list,
+                        !completed,
+                    ) {
+                        some_effect_occurred = true;
+                    }
+                }
+                *exec_ctx = None;
+            }
+        }
+
+        // the longer recipes all gave up, so the held one goes through after all, as
+        // if it had completed right away.
+        if completed_recipe.is_none() && self.held_completion.is_superseded(&self.recipes) {
+            debug!(target: "concerto", "releasing the held back recipe.");
+            self.held_completion.release(command_list, command_mark);
+            Self::supersede_executions(
+                recipe_items,
+                observers,
+                &mut self.recipes,
+                command_list,
+                temporary_nest_recipe_command_list,
+            );
+            temporary_nest_recipe_command_list.clear();
+            some_effect_occurred = true;
+        }
+
+        //second, let's see if we can start new recipe with this input
+        let step_1_completed = completed_recipe.is_some();
+        let mut rebuild_recipe_counter = 0;
+        let pending_prefix = self.prefix_state.pending;
+        let accelerated = self.accelerators.candidates(input);
+        'step_2: for recipe_idx in self.accelerators.start_order(accelerated) {
+            let (recipe, exec_ctx) = &mut self.recipes[recipe_idx];
+            // a recipe completing in step 1 takes the input, held back or not.
+            if dwell_since.is_some() || step_1_completed {
+                break;
+            }
+            if !recipe.is_enabled || recipe.is_broken {
+                continue;
+            }
+            let is_accelerated = self.accelerators.is_accelerated(recipe_idx);
+            // while a prefix is pending, only the recipes under it may start.
+            if !recipe.is_nested && recipe.prefix != pending_prefix {
+                continue;
+            }
+            // the input was taken by a recipe that completed without a reset.
+            if exec_ctx.is_some() || some_repeatable_recipe_finished {
+                continue;
+            }
+            if self.held_completion.is_holding(recipe_idx) {
+                continue;
+            }
+            let execution_id = ActionExecutionId(self.next_execution_id);
+            // once a recipe completed, only the longer ones it may turn out to be the
+            // start of go on.
+            if some_recipe_finished {
+                if is_accelerated {
+                    continue;
+                }
+                match ActionExecutionCtx::peek_start_with_input(
+                    input,
+                    &self.recipe_items,
+                    recipe,
+                    recipe_idx,
+                    execution_id,
+                    &self.env_tracking_state,
+                    self.current_time,
+                ) {
+                    Ok(ExecutionContextResult::Used) => {}
+                    _ => continue,
+                }
+            }
+            let started = if is_accelerated {
+                ActionExecutionCtx::complete_accelerated(
+                    input,
+                    &self.recipe_items,
+                    recipe,
+                    recipe_idx,
+                    execution_id,
+                    command_list,
+                    &self.env_tracking_state,
+                )
+                .map(|result| (result, None))
+            } else {
+                ActionExecutionCtx::start_execution_with_input(
+                    input,
+                    &self.recipe_items,
+                    recipe,
+                    recipe_idx,
+                    execution_id,
+                    command_list,
+                    temporary_nest_recipe_command_list,
+                    &self.env_tracking_state,
+                    self.current_time,
+                    self.dry_run,
+                )
+            };
+            let (result, new_exec_ctx) = match started {
+                Ok(started) => started,
+                Err(error) => {
+                    Self::disable_broken_recipe(recipe, recipe_idx, error, &mut self.diagnostics);
+                    continue;
+                }
+            };
+
+            if let ExecutionContextResult::Done | ExecutionContextResult::Used = result {
+                self.next_execution_id += 1;
+            }
+            match result {
+                ExecutionContextResult::Done => {
+                    assert!(new_exec_ctx.is_none());
+                    input_consumed |= recipe.consumes_inputs;
+                    input_matched = true;
+
+                    record_execution_event(
+                        observers,
+                        recipe,
+                        recipe_idx,
+                        ActionExecutionEvent::Started(None),
+                    );
+                    record_execution_event(
+                        observers,
+                        recipe,
+                        recipe_idx,
+                        ActionExecutionEvent::Completed(Duration::default()),
+                    );
+                    if recipe.is_repeatable {
+                        some_repeatable_recipe_finished = true;
+                        some_effect_occurred = true;
+                    } else {
+                        some_recipe_finished = true;
+                    }
+                    completed_recipe = Some(recipe_idx);
+                    // with hold-off, longer recipes may still start with this input.
+                    if hold_off.is_none() {
+                        break 'step_2;
+                    }
+                }
+                ExecutionContextResult::Used => {
+                    assert!(new_exec_ctx.is_some());
+                    input_consumed |= recipe.consumes_inputs;
+                    input_matched = true;
+                    if hold_off.is_some() {
+                        advanced_recipes.push(recipe_idx);
+                    }
+                    *exec_ctx = new_exec_ctx;
+                    if let Some(exec_ctx) = exec_ctx {
+                        exec_ctx.set_started_at(self.current_time);
+                        record_execution_event(
+                            observers,
+                            recipe,
+                            recipe_idx,
+                            ActionExecutionEvent::Started(exec_ctx.current_item()),
+                        );
+                        if let Some(timeout) = recipe.timeout {
+                            exec_ctx.set_deadline(Some(self.current_time + timeout));
+                        }
+                    }
+                    some_effect_occurred = true;
+                    rebuild_recipe_counter += 1;
+                }
+                _ => {
+                    assert!(new_exec_ctx.is_none());
+                }
+            }
+        }
+
+        if let Some(recipe_idx) = completed_recipe {
+            self.env_tracking_state.disarm_keys();
+            self.prefix_state.arm(
+                &self.prefixes,
+                self.recipes[recipe_idx].0.arms_prefix,
+                self.current_time,
+            );
+        }
+
+        if let Some(recipe_idx) = completed_recipe {
+            self.held_completion
+                .settle(recipe_idx, command_list, command_mark);
+        }
+        if let (Some(recipe_idx), true) = (completed_recipe, some_recipe_finished) {
+            if self.held_completion.hold(
+                recipe_idx,
+                &advanced_recipes,
+                command_list,
+                command_mark,
+                now,
+            ) {
+                debug!(target: "concerto", "holding back a finished recipe.");
+                // whichever recipe wins, the others are superseded.
+                Self::abort_executions_except(
+                    recipe_items,
+                    observers,
+                    &mut self.recipes,
+                    command_list,
+                    temporary_nest_recipe_command_list,
+                    &advanced_recipes,
+                );
+                some_recipe_finished = false;
+                completion_held = true;
+            }
+        }
+
+        if some_recipe_finished {
+            debug!(target: "concerto", "finished one recipe, clear all executions.");
+            Self::supersede_executions(
+                recipe_items,
+                observers,
+                &mut self.recipes,
+                command_list,
+                temporary_nest_recipe_command_list,
+            );
+            return ActionInputOutcome {
+                effect_occurred: true,
+                consumed: input_consumed,
+                matched: input_matched,
+            };
+        }
+
+        if rebuild_recipe_counter > 0 {
+            debug!(target: "concerto", "rebuild {} recipes.", rebuild_recipe_counter);
+        }
+
+        // a key that neither completes nor starts anything under the prefix cancels it.
+        if self.prefix_state.pending.is_some()
+            && completed_recipe.is_none()
+            && matches!(input, ActionInput::KeyDown(_))
+            && self.recipes.iter().all(|(_, exec_ctx)| exec_ctx.is_none())
+        {
+            debug!(target: "concerto", "pending prefix cancelled.");
+            self.prefix_state.cancel();
+        }
+
+        if Self::apply_nest_recipe_commands(
+            &self.recipe_items,
+            &self.observers,
+            &mut self.recipes,
+            command_list,
+            temporary_nest_recipe_command_list,
+            &mut self.spare_nest_recipe_commands,
+        ) {
+            some_effect_occurred = true;
+        }
+
+        // a nest recipe abort may have ended the last of the longer recipes.
+        if !completion_held && self.held_completion.is_superseded(&self.recipes) {
+            debug!(target: "concerto", "releasing the held back recipe.");
+            self.held_completion.release(command_list, command_mark);
+            Self::supersede_executions(
+                recipe_items,
+                observers,
+                &mut self.recipes,
+                command_list,
+                temporary_nest_recipe_command_list,
+            );
+            temporary_nest_recipe_command_list.clear();
+            some_effect_occurred = true;
+        }
+
+        ActionInputOutcome {
+            effect_occurred: some_effect_occurred,
+            consumed: input_consumed,
+            matched: input_matched,
+        }
+    }
+
+    /// The per-frame entry point for hosts driven by a game loop: advances every timer
+    /// by `dt` like `advance_time`, hands the commands that came due to the command
+    /// filter and processes the timed inputs that came due. Returns whether any
+    /// command was queued.
+    pub fn update(&mut self, dt: Duration) -> bool {
+        let command_mark = self.command_list.len();
+        self.advance_time(dt);
+        self.filter_commands(command_mark);
+        self.process_due_timed_inputs();
+        self.command_list.len() > command_mark
+    }
+
+    /// Updates the context by the time passed on the source installed with
+    /// `ActionContextBuilder::with_time_source`, like `update`. Without a source,
+    /// does nothing.
+    pub fn sync_time(&mut self) -> bool {
+        let now = match &self.time_source {
+            Some(source) => source.now(),
+            None => return false,
+        };
+        let elapsed = now.saturating_sub(self.time_source_reading);
+        self.time_source_reading = now;
+        elapsed > Duration::default() && self.update(elapsed)
+    }
+
+    pub fn advance_time(&mut self, elapsed: Duration) -> bool {
+        self.resolve_pending_cursor_position();
+        self.start_command_batch();
+        let since = self.current_time;
+        self.current_time += elapsed;
+        let now = self.current_time;
+
+        // dwells that came due see the cursor where it was last reported.
+        let mut some_effect_occurred = false;
+        let dwell_due = self.recipes.iter().any(|(recipe, exec_ctx)| {
+            exec_ctx.as_ref().is_some_and(|exec_ctx| {
+                exec_ctx
+                    .dwell_deadlines(recipe, since)
+                    .any(|deadline| deadline <= now)
+            })
+        });
+        if let Some(target) = self
+            .env_tracking_state
+            .cursor_target()
+            .filter(|_| dwell_due)
+        {
+            let input = ActionInput::CursorCoordinate(target.clone());
+            if self.dispatch_input(&input, Some(since)).effect_occurred() {
+                some_effect_occurred = true;
+            }
+        }
+
+        if self
+            .prefix_state
+            .deadline
+            .is_some_and(|deadline| deadline <= now)
+        {
+            debug!(target: "concerto", "pending prefix timed out.");
+            self.prefix_state.cancel();
+        }
+
+        let recipe_items = &self.recipe_items;
+        let observers = &*self.observers;
+        let command_list = &mut self.command_list;
+        let nest_recipe_command_list = &mut self.nest_recipe_commands;
+        nest_recipe_command_list.clear();
+
+        // the hold-off window ended, so the held recipe wins over the longer ones.
+        let held_completion = &mut self.held_completion;
+        if held_completion
+            .deadline
+            .is_some_and(|deadline| deadline <= now)
+        {
+            debug!(target: "concerto", "releasing the held back recipe.");
+            let at = command_list.len();
+            held_completion.release(command_list, at);
+            Self::supersede_executions(
+                recipe_items,
+                observers,
+                &mut self.recipes,
+                command_list,
+                nest_recipe_command_list,
+            );
+            nest_recipe_command_list.clear();
+            some_effect_occurred = true;
+        }
+
+        for (recipe_idx, (recipe, exec_ctx)) in self.recipes.iter_mut().enumerate() {
+            if let Some(exec_ctx) = exec_ctx {
+                match exec_ctx.flush_updates(recipe_items, command_list, now) {
+                    Ok(issued) => some_effect_occurred |= issued,
+                    Err(error) => Self::disable_broken_recipe(
+                        recipe,
+                        recipe_idx,
+                        error,
+                        &mut self.diagnostics,
+                    ),
+                }
+            }
+        }
+
+        for (recipe_idx, (recipe, exec_ctx)) in self.recipes.iter_mut().enumerate() {
+            let timed_out = match exec_ctx.as_ref().and_then(|x| x.deadline()) {
+                Some(deadline) => deadline <= now,
+                None => false,
+            };
+            if !timed_out {
+                continue;
+            }
+            debug!(target: "concerto", "recipe {} timed out.", recipe.label(recipe_idx));
+            record_execution_event(
+                observers,
+                recipe,
+                recipe_idx,
+                ActionExecutionEvent::Aborted(ActionAbortReason::Timeout),
+            );
+            if let Some(exec_ctx) = exec_ctx {
+                if exec_ctx.clean_up(
+                    recipe_items,
+                    recipe,
+                    command_list,
+                    nest_recipe_command_list,
+                    true,
+                ) {
+                    some_effect_occurred = true;
+                }
+            }
+            *exec_ctx = None;
+        }
+
+        if Self::apply_nest_recipe_commands(
+            &self.recipe_items,
+            &self.observers,
+            &mut self.recipes,
+            command_list,
+            nest_recipe_command_list,
+            &mut self.spare_nest_recipe_commands,
+        ) {
+            some_effect_occurred = true;
+        }
+        some_effect_occurred
+    }
+
+    /// Aborts every execution in progress as if its inputs stopped matching, issuing
+    /// the effect ends and abort commands, while the tracked environment is kept.
+    pub fn cancel_all(&mut self) -> bool {
+        debug!(target: "concerto", "cancelling all executions.");
+        self.abort_all_executions(ActionAbortReason::Cancelled)
+    }
+
+    /// Drops every execution in progress without issuing any of its commands, and
+    /// forgets the tracked environment apart from `env`, including any cursor position
+    /// not resolved yet. Commands already issued are still pending.
+    pub fn reset(&mut self) {
+        debug!(target: "concerto", "resetting.");
+        let observers = &*self.observers;
+        for (recipe_idx, (recipe, exec_ctx)) in self.recipes.iter_mut().enumerate() {
+            if exec_ctx.take().is_some() {
+                record_execution_event(
+                    observers,
+                    recipe,
+                    recipe_idx,
+                    ActionExecutionEvent::Aborted(ActionAbortReason::Cancelled),
+                );
+            }
+            recipe.is_enabled = !recipe.is_nested;
+        }
+        self.held_completion.discard();
+        self.prefix_state.cancel();
+        self.reorder_buffer.pending.clear();
+        self.pending_cursor_position = None;
+        self.resolved_cursor_target = None;
+        self.env_tracking_state.reset();
+    }
+
+    /// Resets the context once the application lost input focus, as keys released in
+    /// the meantime are never reported: aborts every execution in progress, forgets
+    /// the pressed and armed keys, and issues the command set with
+    /// `ActionContextBuilder::with_focus_lost_command`, whose source is labelled
+    /// `focus-lost`.
+    pub fn notify_focus_lost(&mut self) -> bool {
+        debug!(target: "concerto", "focus lost, resetting.");
+        let mut some_effect_occurred = self.abort_all_executions(ActionAbortReason::FocusLost);
+        self.reorder_buffer.pending.clear();
+        self.env_tracking_state.release_keys();
+        if let Some(command) = &self.focus_lost_command {
+            let source = ActionCommandSource {
+                recipe_idx: FOCUS_LOST_SOURCE,
+                execution_id: None,
+            };
+            self.command_list.push((source, command.clone()));
+            some_effect_occurred = true;
+        }
+        some_effect_occurred
+    }
+
+    fn abort_all_executions(&mut self, reason: ActionAbortReason) -> bool {
+        self.start_command_batch();
+        let recipe_items = &self.recipe_items;
+        let observers = &*self.observers;
+        let command_list = &mut self.command_list;
+        let nest_recipe_command_list = &mut self.nest_recipe_commands;
+        nest_recipe_command_list.clear();
+
+        // the held recipe already completed, and none of its contenders will.
+        let mut some_effect_occurred = self.held_completion.deadline.is_some();
+        let at = command_list.len();
+        self.held_completion.release(command_list, at);
+
+        for (recipe_idx, (recipe, exec_ctx)) in self.recipes.iter_mut().enumerate() {
+            if let Some(mut exec_ctx) = exec_ctx.take() {
+                record_execution_event(
+                    observers,
+                    recipe,
+                    recipe_idx,
+                    ActionExecutionEvent::Aborted(reason),
+                );
+                exec_ctx.clean_up(
+                    recipe_items,
+                    recipe,
+                    command_list,
+                    nest_recipe_command_list,
+                    true,
+                );
+                some_effect_occurred = true;
+            }
+        }
+        Self::apply_nest_recipe_commands(
+            &self.recipe_items,
+            &self.observers,
+            &mut self.recipes,
+            command_list,
+            nest_recipe_command_list,
+            &mut self.spare_nest_recipe_commands,
+        );
+        self.prefix_state.cancel();
+        some_effect_occurred
+    }
+
+    /// Makes the recipe wait for `to` wherever it waited for the input `from`, e.g. to
+    /// rebind it from a settings screen, returning how many inputs were replaced. An
+    /// execution of the recipe in progress is cancelled.
+    pub fn rebind_input(
+        &mut self,
+        handle: ActionRecipeHandle,
+        from: &ActionInput<C>,
+        to: ActionInput<C>,
+    ) -> Result<usize, ConcertoError> {
+        let recipe_idx = handle.0;
+        let recipe_items =
+            Shared::get_mut(&mut self.recipe_items).ok_or(ConcertoError::SharedRecipeItems)?;
+        let replaced = recipe_items.rebind_input(
+            self.recipes[recipe_idx].0.root_item,
+            from,
+            &Shared::new(to),
+        )?;
+        if replaced > 0 {
+            self.accelerators = ActionAcceleratorTable::new(recipe_items, &self.recipes);
+            self.cancel_execution(recipe_idx);
+        }
+        Ok(replaced)
+    }
+
+    /// Like `rebind_input`, for the `KeyDown`, `KeyUp` and `KeyRepeat` inputs of `from`.
+    pub fn rebind_key(
+        &mut self,
+        handle: ActionRecipeHandle,
+        from: C::KeyKind,
+        to: C::KeyKind,
+    ) -> Result<usize, ConcertoError> {
+        let mut replaced = 0;
+        let variants: [fn(C::KeyKind) -> ActionInput<C>; 3] = [
+            ActionInput::KeyDown,
+            ActionInput::KeyUp,
+            ActionInput::KeyRepeat,
+        ];
+        for variant in variants.iter() {
+            replaced += self.rebind_input(handle, &variant(from.clone()), variant(to.clone()))?;
+        }
+        Ok(replaced)
+    }
+
+    fn cancel_execution(&mut self, recipe_idx: usize) {
+        let (recipe, exec_ctx) = &mut self.recipes[recipe_idx];
+        if let Some(mut exec_ctx) = exec_ctx.take() {
+            record_execution_event(
+                &self.observers,
+                recipe,
+                recipe_idx,
+                ActionExecutionEvent::Aborted(ActionAbortReason::Cancelled),
+            );
+            let nest_recipe_command_list = &mut self.nest_recipe_commands;
+            nest_recipe_command_list.clear();
+            exec_ctx.clean_up(
+                &self.recipe_items,
+                recipe,
+                &mut self.command_list,
+                nest_recipe_command_list,
+                true,
+            );
+            Self::apply_nest_recipe_commands(
+                &self.recipe_items,
+                &self.observers,
+                &mut self.recipes,
+                &mut self.command_list,
+                nest_recipe_command_list,
+                &mut self.spare_nest_recipe_commands,
+            );
+        }
+    }
+
+    /// Replaces the tracked pressed keys without processing any input, e.g. with the
+    /// keys already held when the window gains focus.
+    pub fn seed_pressed_keys<I>(&mut self, keys: I)
+    where
+        I: IntoIterator<Item = C::KeyKind>,
+    {
+        self.env_tracking_state.seed_pressed_keys(keys);
+    }
+
+    /// Brings the tracked pressed keys in line with `snapshot`, the keys the system
+    /// reports as held. Keys missing from it are released through `KeyUp` inputs
+    /// that go straight to the recipes, past the interceptors, the fallback and the
+    /// command filter, while keys pressed meanwhile are only recorded, so they start
+    /// no recipe.
+    pub fn reconcile_pressed_keys<I>(&mut self, snapshot: I) -> bool
+    where
+        I: IntoIterator<Item = C::KeyKind>,
+    {
+        let snapshot = snapshot.into_iter().collect::<KeySet<_>>();
+        let released = self
+            .env_tracking_state
+            .pressed_keys
+            .iter()
+            .filter(|key| !snapshot.contains(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        // a stale release doesn't count as tapping a sticky key.
+        self.env_tracking_state.sticky_key_down = None;
+        let mut some_effect_occurred = false;
+        self.resolve_pending_cursor_position();
+        self.start_command_batch();
+        for key in released {
+            debug!(target: "concerto", "reconciling stale key {:?}.", key);
+            if self
+                .dispatch_input(&ActionInput::KeyUp(key), None)
+                .effect_occurred
+            {
+                some_effect_occurred = true;
+            }
+        }
+        self.env_tracking_state.seed_pressed_keys(snapshot);
+        some_effect_occurred
+    }
+
+    /// Errors that caused recipes to be disabled while processing input, since the last call.
+    pub fn take_diagnostics(&mut self) -> Vec<ConcertoError> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Registers an observer notified of every execution that starts, advances,
+    /// completes or aborts from now on.
+    pub fn add_lifecycle_observer<O>(&mut self, observer: O)
+    where
+        O: ActionLifecycleObserver + MaybeSend + 'f,
+    {
+        self.observers
+            .with(|observers| observers.push(Box::new(observer) as _));
+    }
+
+    /// Usage counters of every recipe, nested ones included, in registration order.
+    pub fn metrics(
+        &self,
+    ) -> impl Iterator<Item = (ActionRecipeLabel<'_>, &ActionRecipeMetrics)> + '_ {
+        self.recipes
+            .iter()
+            .enumerate()
+            .map(|(recipe_idx, (recipe, _))| (recipe.label(recipe_idx), &recipe.metrics))
+    }
+
+    /// Every top-level recipe, in registration order.
+    pub fn recipe_handles(&self) -> impl Iterator<Item = ActionRecipeHandle> + '_ {
+        self.recipes
+            .iter()
+            .enumerate()
+            .filter(|(_, (recipe, _))| !recipe.is_nested)
+            .map(|(recipe_idx, _)| ActionRecipeHandle(recipe_idx))
+    }
+
+    /// The name, description and category the recipe was given, e.g. for generating
+    /// a list of shortcuts.
+    pub fn recipe_metadata(&self, handle: ActionRecipeHandle) -> ActionRecipeMetadata<'_> {
+        self.recipes[handle.0].0.metadata()
+    }
+
+    /// The top-level recipe given `name` with `ActionRecipeBuilder::with_name`.
+    pub fn recipe_handle(&self, name: &str) -> Option<ActionRecipeHandle> {
+        self.recipes
+            .iter()
+            .position(|(recipe, _)| !recipe.is_nested && recipe.name.as_deref() == Some(name))
+            .map(ActionRecipeHandle)
+    }
+
+    /// The inputs the recipe waits for, in the order they are declared, e.g. for a
+    /// settings screen to show its binding and check it against the others.
+    pub fn recipe_bindings(
+        &self,
+        handle: ActionRecipeHandle,
+    ) -> Vec<ActionRecipeBinding<C::KeyKind, C::Target>> {
+        ActionRecipeBindings::collect(&self.recipe_items, self.recipes[handle.0].0.root_item)
+    }
+
+    /// Issues the commands of the recipe as if its inputs had occurred, e.g. for a
+    /// menu entry doing what a gesture does. The generators see `args` as the matched
+    /// inputs, and the commands go through the command filter. Executions in progress
+    /// and the tracked environment are left alone, and lifecycle observers are not
+    /// notified. Fails with `ConcertoError::BrokenRecipe` for a recipe that broke.
+    pub fn trigger(
+        &mut self,
+        handle: ActionRecipeHandle,
+        args: &[ActionInput<C>],
+    ) -> Result<(), ConcertoError> {
+        let recipe_idx = handle.0;
+        self.start_command_batch();
+        let command_mark = self.command_list.len();
+        let recipe = &self.recipes[recipe_idx].0;
+        if recipe.is_broken {
+            return Err(match &recipe.name {
+                Some(name) => ConcertoError::BrokenRecipe.in_recipe(name),
+                None => ConcertoError::BrokenRecipe,
+            });
         }
+        debug!(target: "concerto", "triggering recipe {}.", recipe.label(recipe_idx));
+        let execution_id = ActionExecutionId(self.next_execution_id);
+        self.next_execution_id += 1;
+        let result = ActionExecutionCtx::trigger(
+            args,
+            &self.recipe_items,
+            recipe,
+            recipe_idx,
+            execution_id,
+            &mut self.command_list,
+            self.current_time,
+        );
+        self.filter_commands(command_mark);
         result
     }
 
-    #[allow(unused_assignments, unused_labels)]
-    pub fn process_input(&mut self, input: &ActionInput<C>) -> bool {
-        //use std::mem::drop;
-        debug!(target: "concerto", "process_input {:?}.", input);
-        self.env_tracking_state.update_with_input(input);
-
-        let mut some_recipe_finished = false;
-        let mut some_effect_occurred = false;
-        //first, let's see if we can procede with existing half-baked recipes.
-        let recipe_items = &self.recipe_items;
-        let command_list = &mut self.command_list;
-        let env_tracking_state = &self.env_tracking_state;
-        let mut temporary_nest_recipe_command_list = &mut Vec::new();
-        'step_1: for (recipe, exec_ctx) in self.recipes.iter_mut() {
-            let mut remove_exec_ctx = false;
-            if let Some(exec_ctx) = exec_ctx {
-                match exec_ctx.process_input(
-                    input,
-                    recipe_items,
-                    recipe,
-                    command_list,
-                    &mut temporary_nest_recipe_command_list,
-                    env_tracking_state,
-                ) {
-                    ExecutionContextResult::Done => {
-                        some_recipe_finished = true;
-                        remove_exec_ctx = true;
-                    }
-                    ExecutionContextResult::Used => {
-                        some_effect_occurred = true;
-                        remove_exec_ctx = false;
-                    }
-                    ExecutionContextResult::Ignore => {
-                        remove_exec_ctx = false;
-                    }
-                    ExecutionContextResult::Abort => {
-                        remove_exec_ctx = true;
-                    }
-                };
-            }
+    /// The start commands of the effects whose ends haven't been issued yet, along
+    /// with the recipe that started them, in the order they started for each recipe.
+    pub fn active_effects(
+        &self,
+    ) -> impl Iterator<Item = (ActionRecipeLabel<'_>, &C::Command)> + '_ {
+        self.recipes
+            .iter()
+            .enumerate()
+            .filter_map(|(recipe_idx, (recipe, exec_ctx))| {
+                Some((recipe.label(recipe_idx), exec_ctx.as_ref()?))
+            })
+            .flat_map(|(label, exec_ctx)| {
+                exec_ctx
+                    .active_effects()
+                    .map(move |effect_start| (label, effect_start))
+            })
+    }
 
-            if remove_exec_ctx {
-                if let Some(exec_ctx) = exec_ctx {
-                    if exec_ctx.clean_up(command_list, &mut temporary_nest_recipe_command_list) {
-                        some_effect_occurred = true;
-                    }
-                }
-                *exec_ctx = None;
-            }
-        }
+    /// Reports pairs of top-level recipes that start by pressing the same keys together,
+    /// over the same targets if they require exact ones. Recipes starting with inputs
+    /// that can't be inspected, such as filtered ones, are left out.
+    pub fn duplicate_bindings(&self) -> Vec<(ActionRecipeHandle, ActionRecipeHandle)> {
+        let chords = self
+            .recipe_handles()
+            .filter_map(|handle| {
+                let chord = ActionInitialChord::<C>::collect(&self.recipe_bindings(handle))?;
+                Some((handle, self.recipes[handle.0].0.prefix, chord))
+            })
+            .collect::<Vec<_>>();
 
-        if some_recipe_finished {
-            debug!(target: "concerto", "finished one recipe, clear all executions.");
-            for (recipe, exec_ctx) in self.recipes.iter_mut() {
-                if let Some(exec_ctx) = exec_ctx {
-                    if exec_ctx.clean_up(command_list, temporary_nest_recipe_command_list) {
-                        some_effect_occurred = true;
-                    }
+        let mut duplicates = Vec::new();
+        for (idx, (handle, prefix, chord)) in chords.iter().enumerate() {
+            for (other_handle, other_prefix, other_chord) in chords.iter().skip(idx + 1) {
+                if prefix == other_prefix && chord == other_chord {
+                    duplicates.push((*handle, *other_handle));
                 }
-                *exec_ctx = None;
-                recipe.is_enabled = !recipe.is_nested;
             }
-            return true;
         }
+        duplicates
+    }
 
-        //second, let's see if we can start new recipe with this input
-        let mut rebuild_recipe_counter = 0;
-        'step_2: for (recipe_idx, (recipe, exec_ctx)) in self.recipes.iter_mut().enumerate() {
-            if !recipe.is_enabled {
-                continue;
-            }
-            if exec_ctx.is_some() {
-                continue;
-            }
-            let (result, new_exec_ctx) = ActionExecutionCtx::start_execution_with_input(
-                input,
-                &self.recipe_items,
-                recipe,
-                recipe_idx,
-                command_list,
-                &mut temporary_nest_recipe_command_list,
-                &self.env_tracking_state,
-            );
-
-            match result {
-                ExecutionContextResult::Done => {
-                    assert!(new_exec_ctx.is_none());
+    /// Reports pairs of top-level recipes that can be triggered by the same inputs.
+    /// Only the linear part each recipe starts with is compared.
+    pub fn analyze(&self) -> Vec<ActionRecipeConflict> {
+        let leading_inputs = self
+            .recipes
+            .iter()
+            .filter(|(recipe, _)| !recipe.is_nested)
+            .map(|(recipe, _)| {
+                (
+                    recipe.prefix,
+                    ActionRecipeLeadingInputs::collect(&self.recipe_items, recipe.root_item),
+                )
+            })
+            .collect::<Vec<_>>();
 
-                    some_recipe_finished = true;
-                    break 'step_2;
-                }
-                ExecutionContextResult::Used => {
-                    assert!(new_exec_ctx.is_some());
-                    *exec_ctx = new_exec_ctx;
-                    some_effect_occurred = true;
-                    rebuild_recipe_counter += 1;
+        let mut conflicts = Vec::new();
+        for (idx, (prefix, inputs)) in leading_inputs.iter().enumerate() {
+            for (other_idx, (other_prefix, other_inputs)) in
+                leading_inputs.iter().enumerate().skip(idx + 1)
+            {
+                // recipes under different prefixes never compete for an input.
+                if prefix != other_prefix {
+                    continue;
                 }
-                _ => {
-                    assert!(new_exec_ctx.is_none());
+                if let Some(conflict) = inputs.conflict_with(other_inputs, idx, other_idx) {
+                    conflicts.push(conflict);
                 }
             }
         }
+        conflicts
+    }
 
-        if some_recipe_finished {
-            debug!(target: "concerto", "immediately finished one recipe, clear all executions.");
-            for (recipe, exec_ctx) in self.recipes.iter_mut() {
-                if let Some(exec_ctx) = exec_ctx {
-                    if exec_ctx.clean_up(command_list, temporary_nest_recipe_command_list) {
-                        some_effect_occurred = true;
-                    }
-                }
-                *exec_ctx = None;
-                recipe.is_enabled = !recipe.is_nested;
-            }
-            return true;
+    /// Lowers every recipe into a flat state machine that new executions step through
+    /// instead of walking the item tree. Executions already in progress are unaffected.
+    pub fn compile(&mut self) -> Result<(), ConcertoError> {
+        let mut compiled_recipes = Vec::with_capacity(self.recipes.len());
+        for (recipe, _) in self.recipes.iter() {
+            let compiled = ActionCompiledRecipe::compile(&self.recipe_items, recipe.root_item)
+                .map_err(|error| match &recipe.name {
+                    Some(name) => error.in_recipe(name),
+                    None => error,
+                })?;
+            compiled_recipes.push(Shared::new(compiled));
         }
-
-        if rebuild_recipe_counter > 0 {
-            debug!(target: "concerto", "rebuild {} recipes.", rebuild_recipe_counter);
+        for ((recipe, _), compiled) in self.recipes.iter_mut().zip(compiled_recipes) {
+            recipe.compiled = Some(compiled);
         }
+        Ok(())
+    }
 
-        while !temporary_nest_recipe_command_list.is_empty() {
-            let mut new_nest_recipe_command_list = Vec::new();
-            for nest_recipe_cmd in temporary_nest_recipe_command_list.drain(..) {
-                match nest_recipe_cmd {
-                    ActionNestRecipeCommand::Enable(recipe_idx, nest_recipe_idx) => {
-                        if let Some(real_recipe_idx) =
-                            Self::locate_nest_recipe(&self.recipes, recipe_idx, nest_recipe_idx)
-                        {
-                            debug!(target: "concerto", "nest recipe {} is now enabled.", rebuild_recipe_counter);
-                            self.recipes[real_recipe_idx].0.is_enabled = true;
-                        }
-                    }
-                    ActionNestRecipeCommand::Disable(recipe_idx, nest_recipe_idx) => {
-                        if let Some(real_recipe_idx) =
-                            Self::locate_nest_recipe(&self.recipes, recipe_idx, nest_recipe_idx)
-                        {
-                            self.recipes[real_recipe_idx].0.is_enabled = false;
-                        }
-                    }
-                    ActionNestRecipeCommand::Abort(recipe_idx, nest_recipe_idx) => {
-                        if let Some(real_recipe_idx) =
-                            Self::locate_nest_recipe(&self.recipes, recipe_idx, nest_recipe_idx)
-                        {
-                            self.recipes[real_recipe_idx].0.is_enabled = false;
+    /// The name of the prefix whose recipes are waiting for their key, if any.
+    pub fn pending_prefix(&self) -> Option<&str> {
+        self.prefix_state
+            .pending
+            .map(|prefix_idx| self.prefixes[prefix_idx].name.as_str())
+    }
 
-                            if let Some(exec_ctx) = &mut self.recipes[real_recipe_idx].1 {
-                                if exec_ctx
-                                    .clean_up(command_list, &mut new_nest_recipe_command_list)
-                                {
-                                    some_effect_occurred = true;
-                                }
-                            }
-                            self.recipes[real_recipe_idx].1 = None;
-                        }
-                    }
+    /// Whether some recipe has matched part of its inputs and is waiting for more.
+    pub fn has_active_executions(&self) -> bool {
+        self.recipes.iter().any(|(_, exec_ctx)| exec_ctx.is_some())
+    }
+
+    /// The application's environment state read by custom conditions.
+    pub fn env(&self) -> &C::EnvExt {
+        &self.env_tracking_state.ext
+    }
+
+    /// Changes take effect for conditions checked from the next input on.
+    pub fn env_mut(&mut self) -> &mut C::EnvExt {
+        &mut self.env_tracking_state.ext
+    }
+
+    /// Total time passed to `advance_time` so far.
+    pub fn current_time(&self) -> Duration {
+        self.current_time
+    }
+
+    /// Time left until the earliest pending execution times out, if any.
+    pub fn next_timeout(&self) -> Option<Duration> {
+        self.recipes
+            .iter()
+            .filter_map(|(_, exec_ctx)| exec_ctx.as_ref().and_then(|x| x.deadline()))
+            .chain(self.recipes.iter().flat_map(|(recipe, exec_ctx)| {
+                exec_ctx
+                    .iter()
+                    .flat_map(move |x| x.dwell_deadlines(recipe, self.current_time))
+            }))
+            .chain(
+                self.recipes
+                    .iter()
+                    .flat_map(|(_, exec_ctx)| exec_ctx.iter().flat_map(|x| x.update_deadlines())),
+            )
+            .chain(self.prefix_state.deadline)
+            .chain(self.held_completion.deadline)
+            .chain(self.reorder_buffer.deadline())
+            .min()
+            .map(|deadline| deadline.saturating_sub(self.current_time))
+    }
+
+    /// Commands still referenced by a recipe are cloned out, generated ones are moved.
+    pub fn collect_commands(&mut self) -> Option<impl Iterator<Item = C::Command> + '_>
+    where
+        C::Command: Clone,
+    {
+        self.collect_shared_commands().map(|commands| {
+            commands.map(|command| Shared::try_unwrap(command).unwrap_or_else(|x| (*x).clone()))
+        })
+    }
+
+    /// Like `collect_commands`, along with the recipe that issued each command.
+    pub fn collect_commands_with_source(
+        &mut self,
+    ) -> Option<impl Iterator<Item = (ActionRecipeLabel<'_>, C::Command)> + '_>
+    where
+        C::Command: Clone,
+    {
+        if self.command_list.is_empty() {
+            return None;
+        }
+        self.command_batch_marks.clear();
+        let recipes = &self.recipes;
+        Some(self.command_list.drain(..).map(move |(source, command)| {
+            (
+                Self::command_source_label(recipes, source),
+                Shared::try_unwrap(command).unwrap_or_else(|x| (*x).clone()),
+            )
+        }))
+    }
+
+    /// Like `collect_commands_with_source`, grouping the commands into batches, each
+    /// holding the consecutive ones a recipe issued on the same input, so the host can
+    /// apply them at once.
+    pub fn collect_command_batches(
+        &mut self,
+    ) -> Option<impl Iterator<Item = (ActionRecipeLabel<'_>, Vec<C::Command>)> + '_>
+    where
+        C::Command: Clone,
+    {
+        if self.command_list.is_empty() {
+            return None;
+        }
+        let mut batches: Vec<(ActionCommandSource, Vec<C::Command>)> = Vec::new();
+        let command_batch_marks = mem::take(&mut self.command_batch_marks);
+        for (position, (source, command)) in self.command_list.drain(..).enumerate() {
+            let command = Shared::try_unwrap(command).unwrap_or_else(|x| (*x).clone());
+            match batches.last_mut() {
+                Some((batch_source, batch))
+                    if *batch_source == source
+                        && command_batch_marks.binary_search(&position).is_err() =>
+                {
+                    batch.push(command)
                 }
+                _ => batches.push((source, vec![command])),
             }
-            temporary_nest_recipe_command_list.extend(new_nest_recipe_command_list.into_iter());
         }
+        let recipes = &self.recipes;
+        Some(
+            batches
+                .into_iter()
+                .map(move |(source, batch)| (Self::command_source_label(recipes, source), batch)),
+        )
+    }
 
-        some_effect_occurred
+    fn command_source_label(
+        recipes: &[(ActionRecipe<C>, Option<ActionExecutionCtx<C>>)],
+        source: ActionCommandSource,
+    ) -> ActionRecipeLabel<'_> {
+        let recipe_idx = source.recipe_idx;
+        match recipes.get(recipe_idx) {
+            Some((recipe, _)) => recipe.label(recipe_idx),
+            None if recipe_idx == FALLBACK_SOURCE => {
+                ActionRecipeLabel::named(recipe_idx, "fallback")
+            }
+            None => ActionRecipeLabel::named(recipe_idx, "focus-lost"),
+        }
+        .with_execution_id(source.execution_id)
     }
 
-    pub fn collect_commands(&mut self) -> Option<impl Iterator<Item = C::Command> + '_> {
+    pub fn collect_shared_commands(
+        &mut self,
+    ) -> Option<impl Iterator<Item = Shared<C::Command>> + '_> {
         if self.command_list.is_empty() {
             None
         } else {
-            Some(self.command_list.drain(..))
+            self.command_batch_marks.clear();
+            Some(self.command_list.drain(..).map(|(_, command)| command))
         }
     }
 }
 
-pub struct ActionContextBuilder<C: ActionConfiguration> {
-    pub(crate) recipe_items: ActionRecipeItemStore<C>,
+pub struct ActionContextBuilder<'f, C: ActionConfiguration> {
+    pub(crate) recipe_items: ActionRecipeItemStore<'f, C>,
     recipes: Vec<ActionRecipe<C>>,
+    error: Option<ConcertoError>,
+    inline_contract_limit: usize,
+    sticky_keys: KeySet<C::KeyKind>,
+    key_repeat: ActionKeyRepeat,
+    cursor_coalescing: ActionCursorCoalescing,
+    prefixes: Vec<ActionRecipePrefix>,
+    hold_off: Option<Duration>,
+    reorder_window: Option<Duration>,
+    focus_lost_command: Option<Shared<C::Command>>,
+    command_filter: Option<Shared<ActionCommandFilterCell<'f, C>>>,
+    fallback: Option<Shared<ActionFallbackCell<'f, C>>>,
+    interceptors: Vec<Shared<ActionInterceptorCell<'f, C>>>,
+    target_resolver: Option<Shared<dyn ActionTargetResolverObj<C> + 'f>>,
+    time_source: Option<Shared<dyn ActionTimeSourceObj + 'f>>,
+}
+
+impl<'f, C: ActionConfiguration> fmt::Debug for ActionContextBuilder<'f, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("ActionContextBuilder")
+            .field("recipes", &self.recipes)
+            .field("recipe_items", &self.recipe_items.len())
+            .field("error", &self.error)
+            .field("inline_contract_limit", &self.inline_contract_limit)
+            .finish()
+    }
+}
+
+impl<'f, C: ActionConfiguration> Default for ActionContextBuilder<'f, C> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<C: ActionConfiguration> ActionContextBuilder<C> {
+impl<'f, C: ActionConfiguration> ActionContextBuilder<'f, C> {
     pub fn new() -> Self {
         ActionContextBuilder {
             recipe_items: ActionRecipeItemStore::new(),
             recipes: Vec::new(),
+            error: None,
+            inline_contract_limit: DEFAULT_INLINE_CONTRACT_LIMIT,
+            sticky_keys: KeySet::new(),
+            key_repeat: ActionKeyRepeat::default(),
+            cursor_coalescing: ActionCursorCoalescing::default(),
+            prefixes: Vec::new(),
+            hold_off: None,
+            reorder_window: None,
+            focus_lost_command: None,
+            command_filter: None,
+            fallback: None,
+            interceptors: Vec::new(),
+            target_resolver: None,
+            time_source: None,
         }
     }
 
-    pub fn build(self) -> ActionContext<C> {
-        ActionContext {
-            recipe_items: self.recipe_items,
-            recipes: self.recipes.into_iter().map(|x| (x, None)).collect(),
-            command_list: Vec::new(),
-            env_tracking_state: ActionEnvironmentTrackingState::new(),
+    /// Tuning knob: how many contracts an execution keeps in a sorted inline vector
+    /// before moving them into a `BTreeMap`. Zero always uses the map.
+    pub fn with_inline_contract_limit(mut self, limit: usize) -> Self {
+        self.inline_contract_limit = limit;
+        self
+    }
+
+    /// Makes `key` sticky: pressing and releasing it on its own arms it until the next
+    /// recipe completes, which `ActionCondition::modifier_active` accepts as held.
+    pub fn with_sticky_key(mut self, key: C::KeyKind) -> Self {
+        self.sticky_keys.insert(key);
+        self
+    }
+
+    /// Sets what happens to a `KeyDown` of a key that is already pressed,
+    /// `PassThrough` by default.
+    pub fn with_key_repeat(mut self, key_repeat: ActionKeyRepeat) -> Self {
+        self.key_repeat = key_repeat;
+        self
+    }
+
+    /// Sets which of the consecutive cursor inputs handed to `process_inputs` at once
+    /// are matched, `KeepAll` by default.
+    pub fn with_cursor_coalescing(mut self, coalescing: ActionCursorCoalescing) -> Self {
+        self.cursor_coalescing = coalescing;
+        self
+    }
+
+    /// When a recipe completes while longer ones that started the same way are still
+    /// in progress, holds its commands back for up to `window`, dropping them if one
+    /// of those completes instead.
+    pub fn with_hold_off(mut self, window: Duration) -> Self {
+        self.hold_off = Some(window);
+        self
+    }
+
+    /// Holds inputs given with `ActionContext::process_timed_input` back for `window`,
+    /// so those delivered out of order by up to that long are matched in the order
+    /// they happened.
+    pub fn with_reorder_window(mut self, window: Duration) -> Self {
+        self.reorder_window = Some(window);
+        self
+    }
+
+    /// Sets the command `ActionContext::notify_focus_lost` issues.
+    pub fn with_focus_lost_command(mut self, command: C::Command) -> Self {
+        self.focus_lost_command = Some(Shared::new(command));
+        self
+    }
+
+    /// Installs `filter`, which sees the commands of each processed input and each
+    /// update before they are queued.
+    pub fn with_command_filter<F>(mut self, filter: F) -> Self
+    where
+        F: ActionCommandFilter<C> + MaybeSend + 'f,
+    {
+        self.command_filter = Some(Shared::new(GeneratorCell::new(Box::new(filter) as _)));
+        self
+    }
+
+    /// Calls `fallback` with every processed input no recipe matched, e.g. to insert
+    /// the text of unbound printable keys, and queues the command it returns, if any,
+    /// labelled `fallback`.
+    pub fn with_fallback<F>(mut self, fallback: F) -> Self
+    where
+        F: FnMut(&ActionInput<C>) -> Option<C::Command> + MaybeSend + 'f,
+    {
+        self.fallback = Some(Shared::new(GeneratorCell::new(Box::new(fallback) as _)));
+        self
+    }
+
+    /// Adds `interceptor` to the end of the chain every processed input goes through
+    /// before recipes see it, e.g. to remap, debounce or log inputs. `peek_input` and
+    /// `simulate` work on clones of it, so its state stays as it is.
+    pub fn add_interceptor<F>(mut self, interceptor: F) -> Self
+    where
+        F: FnMut(ActionInput<C>) -> ActionInterceptResult<C> + Clone + MaybeSend + 'f,
+    {
+        self.interceptors
+            .push(Shared::new(GeneratorCell::new(Box::new(interceptor) as _)));
+        self
+    }
+
+    /// Installs the hit test `ActionContext::process_cursor_position` uses.
+    pub fn with_target_resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: ActionTargetResolver<C> + MaybeSendSync + 'f,
+    {
+        self.target_resolver = Some(Shared::new(resolver) as _);
+        self
+    }
+
+    /// Installs the time source the context syncs its time with before each input and
+    /// in `ActionContext::sync_time`, which then replaces passing the time by hand.
+    pub fn with_time_source<S>(mut self, source: S) -> Self
+    where
+        S: ActionTimeSource + MaybeSendSync + 'f,
+    {
+        self.time_source = Some(Shared::new(source) as _);
+        self
+    }
+
+    /// Fails with the first error reported by any of the added recipes.
+    pub fn build(self) -> Result<ActionContext<'f, C>, ConcertoError> {
+        if let Some(error) = self.error {
+            return Err(error);
         }
+        validate_items_in_use(
+            &self.recipe_items,
+            self.recipes.iter().flat_map(|recipe| {
+                iter::once(recipe.root_item)
+                    .chain(recipe.on_abort.iter().cloned())
+                    .chain(recipe.target_guard)
+            }),
+        )?;
+        let inline_contract_limit = self.inline_contract_limit;
+        let recipes = self
+            .recipes
+            .into_iter()
+            .map(|mut x| {
+                x.inline_contract_limit = inline_contract_limit;
+                (x, None)
+            })
+            .collect::<Vec<_>>();
+        let accelerators = ActionAcceleratorTable::new(&self.recipe_items, &recipes);
+        let time_source_reading = self
+            .time_source
+            .as_ref()
+            .map_or(Duration::default(), |source| source.now());
+        Ok(ActionContext {
+            recipe_items: Shared::new(self.recipe_items),
+            recipes,
+            command_list: Vec::new(),
+            command_batch_marks: Vec::new(),
+            next_execution_id: 0,
+            env_tracking_state: ActionEnvironmentTrackingState::new(
+                self.sticky_keys,
+                self.key_repeat,
+            ),
+            current_time: Duration::default(),
+            diagnostics: Vec::new(),
+            observers: Shared::new(GeneratorCell::new(Vec::new())),
+            prefixes: self.prefixes,
+            prefix_state: ActionPrefixState::default(),
+            held_completion: ActionHeldCompletion::new(self.hold_off),
+            cursor_coalescing: self.cursor_coalescing,
+            focus_lost_command: self.focus_lost_command,
+            command_filter: self.command_filter,
+            fallback: self.fallback,
+            interceptors: self.interceptors,
+            target_resolver: self.target_resolver,
+            time_source: self.time_source,
+            time_source_reading,
+            reorder_buffer: ActionReorderBuffer::new(self.reorder_window),
+            accelerators,
+            pending_cursor_position: None,
+            resolved_cursor_target: None,
+            target_generation: 0,
+            dry_run: false,
+            nest_recipe_commands: Vec::new(),
+            spare_nest_recipe_commands: Vec::new(),
+        })
     }
 }
 
-impl<C: ActionConfiguration> ActionContextBuilder<C> {
+impl<'f, C: ActionConfiguration> ActionContextBuilder<'f, C> {
     pub(crate) fn register_nested_recipe(&mut self, mut nest_recipe: ActionRecipe<C>) -> usize {
         nest_recipe.is_nested = true;
         nest_recipe.is_enabled = false;
@@ -291,9 +2789,34 @@ impl<C: ActionConfiguration> ActionContextBuilder<C> {
         allocated_idx
     }
 
+    pub(crate) fn prefix_index(&self, name: &str) -> Option<usize> {
+        self.prefixes.iter().position(|prefix| prefix.name == name)
+    }
+
+    /// Declares a prefix armed by completing the recipe `f` builds. While it is
+    /// pending, only the recipes added afterwards with `under_prefix(name)` can start;
+    /// it ends when one of them completes, a key starts none of them, or `timeout`
+    /// passes.
+    pub fn add_prefix<S, F>(mut self, name: S, timeout: Option<Duration>, f: F) -> Self
+    where
+        S: Into<String>,
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> Result<ActionRecipe<C>, ConcertoError>,
+    {
+        let prefix_idx = self.prefixes.len();
+        self.prefixes.push(ActionRecipePrefix {
+            name: name.into(),
+            timeout,
+        });
+        self = self.add_recipe(f);
+        if let Some(recipe) = self.recipes.last_mut() {
+            recipe.arms_prefix = Some(prefix_idx);
+        }
+        self
+    }
+
     pub fn add_recipe<F>(mut self, f: F) -> Self
     where
-        F: FnOnce(ActionRecipeBuilder<C>) -> ActionRecipe<C>,
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> Result<ActionRecipe<C>, ConcertoError>,
     {
         let recipe = {
             let builder = ActionRecipeBuilder::new(&mut self);
@@ -301,7 +2824,367 @@ impl<C: ActionConfiguration> ActionContextBuilder<C> {
             (f)(builder)
         };
 
-        self.recipes.push(recipe);
+        match recipe {
+            Ok(recipe) => self.recipes.push(recipe),
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ActionInterceptResult, ActionIssuedCommand};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use testing::Harness;
+    use ActionConfiguration;
+    use ActionContext;
+    use ActionContextBuilder;
+    use ActionInput::{KeyDown, KeyUp};
+
+    struct Config;
+
+    impl ActionConfiguration for Config {
+        type Target = ();
+        type KeyKind = char;
+        type CursorPos = ();
+        type Command = &'static str;
+        type EnvExt = ();
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // `g` completes on the release in step 1, while `gg` goes on with its execution.
+    fn clicked_g_and_gg<'f>() -> ActionContextBuilder<'f, Config> {
+        ActionContextBuilder::new()
+            .with_hold_off(ms(300))
+            .add_recipe(|b| b.add_key_click_input('g').issue_command("g").build())
+            .add_recipe(|b| {
+                b.add_key_click_input('g')
+                    .add_key_click_input('g')
+                    .issue_command("gg")
+                    .build()
+            })
+    }
+
+    // `g` completes as it starts in step 2, while `gg` starts an execution.
+    fn pressed_g_and_gg<'f>() -> ActionContextBuilder<'f, Config> {
+        ActionContextBuilder::new()
+            .with_hold_off(ms(300))
+            .add_recipe(|b| b.add_key_down_input('g').issue_command("g").build())
+            .add_recipe(|b| {
+                b.add_key_down_input('g')
+                    .add_key_up_input('g')
+                    .add_key_down_input('g')
+                    .issue_command("gg")
+                    .build()
+            })
+    }
+
+    #[test]
+    fn hold_off_yields_to_longer_recipe_after_step_1_completion() {
+        Harness::new(clicked_g_and_gg())
+            .feed(vec![KeyDown('g'), KeyUp('g')])
+            .expect_no_commands()
+            .advance(ms(100))
+            .feed(vec![KeyDown('g'), KeyUp('g')])
+            .expect_commands(vec!["gg"])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn hold_off_releases_step_1_completion_after_window() {
+        Harness::new(clicked_g_and_gg())
+            .feed(vec![KeyDown('g'), KeyUp('g')])
+            .expect_no_commands()
+            .advance(ms(400))
+            .expect_commands(vec!["g"])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn hold_off_yields_to_longer_recipe_after_step_2_completion() {
+        Harness::new(pressed_g_and_gg())
+            .feed(vec![KeyDown('g'), KeyUp('g')])
+            .expect_no_commands()
+            .advance(ms(100))
+            .feed(vec![KeyDown('g')])
+            .expect_commands(vec!["gg"])
+            .feed(vec![KeyUp('g')])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn hold_off_releases_step_2_completion_after_window() {
+        Harness::new(pressed_g_and_gg())
+            .feed(vec![KeyDown('g')])
+            .expect_no_commands()
+            .advance(ms(400))
+            .expect_commands(vec!["g"])
+            .expect_no_pending();
+    }
+
+    // `ab` times out 100ms after `a`, on the clock the returned handle sets.
+    fn timed_ab_on_clock<'f>() -> (ActionContextBuilder<'f, Config>, Arc<AtomicU64>) {
+        let clock = Arc::new(AtomicU64::new(0));
+        let source = clock.clone();
+        let builder = ActionContextBuilder::new()
+            .with_time_source(move || ms(source.load(Ordering::SeqCst)))
+            .add_recipe(|b| {
+                b.with_timeout(ms(100))
+                    .add_key_down_input('a')
+                    .add_key_down_input('b')
+                    .issue_command("ab")
+                    .on_abort_issue("timed out")
+                    .build()
+            });
+        (builder, clock)
+    }
+
+    #[test]
+    fn time_source_is_synced_before_each_input() {
+        let (builder, clock) = timed_ab_on_clock();
+        let harness = Harness::new(builder).feed(vec![KeyDown('a')]);
+        clock.store(50, Ordering::SeqCst);
+        let harness = harness.feed(vec![KeyDown('b')]).expect_commands(vec!["ab"]);
+        clock.store(100, Ordering::SeqCst);
+        let harness = harness.feed(vec![KeyDown('a')]);
+        clock.store(250, Ordering::SeqCst);
+        harness
+            .feed(vec![KeyDown('b')])
+            .expect_commands(vec!["timed out"])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn sync_time_times_out_executions() {
+        let (builder, clock) = timed_ab_on_clock();
+        let mut context = Harness::new(builder)
+            .feed(vec![KeyDown('a')])
+            .into_context();
+        clock.store(50, Ordering::SeqCst);
+        assert!(!context.sync_time());
+        assert_eq!(context.current_time(), ms(50));
+        clock.store(150, Ordering::SeqCst);
+        assert!(context.sync_time());
+        Harness::from_context(context)
+            .expect_commands(vec!["timed out"])
+            .expect_no_pending();
+    }
+
+    // `h` only moves left after the `w` prefix.
+    fn window_prefix<'f>() -> ActionContextBuilder<'f, Config> {
+        ActionContextBuilder::new()
+            .add_prefix("window", Some(ms(500)), |b| {
+                b.add_key_down_input('w').build()
+            })
+            .add_recipe(|b| {
+                b.under_prefix("window")
+                    .add_key_down_input('h')
+                    .issue_command("left")
+                    .build()
+            })
+    }
+
+    #[test]
+    fn prefix_lets_its_recipes_start_until_one_completes() {
+        let harness = Harness::new(window_prefix())
+            .feed(vec![KeyDown('h'), KeyUp('h')])
+            .expect_no_commands()
+            .feed(vec![KeyDown('w'), KeyUp('w')]);
+        assert_eq!(harness.context().pending_prefix(), Some("window"));
+        let harness = harness
+            .feed(vec![KeyDown('h'), KeyUp('h')])
+            .expect_commands(vec!["left"]);
+        assert_eq!(harness.context().pending_prefix(), None);
+        harness
+            .feed(vec![KeyDown('h'), KeyUp('h')])
+            .expect_no_commands()
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn prefix_is_cancelled_by_a_key_starting_nothing() {
+        let harness = Harness::new(window_prefix())
+            .feed(vec![KeyDown('w'), KeyUp('w')])
+            .feed(vec![KeyDown('x'), KeyUp('x')]);
+        assert_eq!(harness.context().pending_prefix(), None);
+        harness
+            .feed(vec![KeyDown('h'), KeyUp('h')])
+            .expect_no_commands()
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn prefix_is_cancelled_after_its_timeout() {
+        let harness = Harness::new(window_prefix())
+            .feed(vec![KeyDown('w'), KeyUp('w')])
+            .advance(ms(400));
+        assert_eq!(harness.context().pending_prefix(), Some("window"));
+        let harness = harness.advance(ms(200));
+        assert_eq!(harness.context().pending_prefix(), None);
+        harness
+            .feed(vec![KeyDown('h'), KeyUp('h')])
+            .expect_no_commands()
+            .expect_no_pending();
+    }
+
+    // Both are single chords, `s` only while `c` is held.
+    fn quit_and_save<'f>() -> ActionContextBuilder<'f, Config> {
+        ActionContextBuilder::new()
+            .add_recipe(|b| b.add_key_down_input('q').issue_command("quit").build())
+            .add_recipe(|b| {
+                b.check_modifier('c')
+                    .add_key_down_input('s')
+                    .issue_command("save")
+                    .build()
+            })
+    }
+
+    #[test]
+    fn single_chord_recipe_completes_without_an_execution() {
+        Harness::new(quit_and_save())
+            .feed(vec![KeyDown('q')])
+            .expect_commands(vec!["quit"])
+            .expect_no_pending()
+            .feed(vec![KeyUp('q')])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn single_chord_recipe_checks_its_conditions() {
+        Harness::new(quit_and_save())
+            .feed(vec![KeyDown('s'), KeyUp('s')])
+            .expect_no_commands()
+            .feed(vec![KeyDown('c'), KeyDown('s')])
+            .expect_commands(vec!["save"])
+            .feed(vec![KeyUp('s'), KeyUp('c')])
+            .expect_no_pending();
+    }
+
+    fn reordered_ab<'f>() -> ActionContext<'f, Config> {
+        ActionContextBuilder::new()
+            .with_reorder_window(ms(50))
+            .add_recipe(|b| {
+                b.add_key_down_input('a')
+                    .add_key_down_input('b')
+                    .issue_command("ab")
+                    .build()
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn reorder_window_matches_inputs_in_the_order_they_happened() {
+        let mut context = reordered_ab();
+        assert!(!context.process_timed_input(KeyDown('b'), ms(20)));
+        assert!(!context.process_timed_input(KeyDown('a'), ms(10)));
+        assert_eq!(context.next_timeout(), Some(ms(60)));
+        Harness::from_context(context)
+            .advance(ms(40))
+            .expect_no_commands()
+            .advance(ms(30))
+            .expect_commands(vec!["ab"])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn flushing_processes_held_back_inputs_right_away() {
+        let mut context = reordered_ab();
+        context.process_timed_input(KeyDown('b'), ms(20));
+        context.process_timed_input(KeyDown('a'), ms(10));
+        assert!(context.flush_timed_inputs());
+        Harness::from_context(context)
+            .expect_commands(vec!["ab"])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn interceptors_remap_split_and_drop_inputs_in_turn() {
+        let builder = ActionContextBuilder::<Config>::new()
+            .add_interceptor(|input| match input {
+                KeyDown('A') => ActionInterceptResult::Pass(KeyDown('a')),
+                KeyDown('z') => ActionInterceptResult::Drop,
+                input => ActionInterceptResult::Pass(input),
+            })
+            .add_interceptor(|input| match input {
+                KeyDown('a') => ActionInterceptResult::Split(vec![KeyDown('a'), KeyDown('b')]),
+                input => ActionInterceptResult::Pass(input),
+            })
+            .add_recipe(|b| {
+                b.add_key_down_input('a')
+                    .add_key_down_input('b')
+                    .issue_command("ab")
+                    .build()
+            })
+            .add_recipe(|b| b.add_key_down_input('z').issue_command("z").build());
+        Harness::new(builder)
+            .feed(vec![KeyDown('A')])
+            .expect_commands(vec!["ab"])
+            .feed(vec![KeyDown('z')])
+            .expect_no_commands()
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn peek_input_leaves_interceptor_state_as_it_is() {
+        let mut seen = 0;
+        let mut context = ActionContextBuilder::<Config>::new()
+            // drops every other input.
+            .add_interceptor(move |input| {
+                seen += 1;
+                if seen % 2 == 0 {
+                    ActionInterceptResult::Drop
+                } else {
+                    ActionInterceptResult::Pass(input)
+                }
+            })
+            .add_recipe(|b| {
+                b.with_name("a")
+                    .add_key_down_input('a')
+                    .issue_command("a")
+                    .build()
+            })
+            .build()
+            .unwrap();
+        let handle = context.recipe_handle("a").unwrap();
+        for _ in 0..2 {
+            assert_eq!(context.peek_input(&KeyDown('a')).completed(), &[handle]);
+        }
+        context.process_input(&KeyDown('a'));
+        Harness::from_context(context)
+            .expect_commands(vec!["a"])
+            .feed(vec![KeyDown('a')])
+            .expect_no_commands()
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn trigger_goes_through_the_command_filter() {
+        let mut context = ActionContextBuilder::<Config>::new()
+            .with_command_filter(|commands: &mut Vec<ActionIssuedCommand<Config>>| {
+                commands.retain(|issued| *issued.command != "dropped")
+            })
+            .add_recipe(|b| {
+                b.with_name("save")
+                    .add_key_down_input('s')
+                    .issue_command("dropped")
+                    .issue_command("save")
+                    .build()
+            })
+            .build()
+            .unwrap();
+        let handle = context.recipe_handle("save").unwrap();
+        context.trigger(handle, &[]).unwrap();
+        Harness::from_context(context)
+            .expect_commands(vec!["save"])
+            .expect_no_pending();
+    }
+}
@@ -0,0 +1,164 @@
+use context::ActionRecipeItemIdx;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ConcertoError {
+    /// A sequential, unordered, choice, optional or repeated item has no children, so it could never complete.
+    EmptySequence(ActionRecipeItemIdx),
+    /// The recipe rooted at this item would complete without consuming any input.
+    NoInteractiveItem(ActionRecipeItemIdx),
+    /// An unordered or choice item has a child, or an optional or repeated item has a
+    /// first child, that is not a plain input.
+    NonInteractiveBranch {
+        container: ActionRecipeItemIdx,
+        item: ActionRecipeItemIdx,
+    },
+    /// A choice branch expects the same input as an earlier branch, so it is never taken.
+    UnreachableBranch {
+        container: ActionRecipeItemIdx,
+        item: ActionRecipeItemIdx,
+    },
+    /// A branch expects an input that would break a condition or input still in effect
+    /// from `contract` earlier in the recipe, so taking it always aborts the execution.
+    ConflictingBranch {
+        container: ActionRecipeItemIdx,
+        item: ActionRecipeItemIdx,
+        contract: ActionRecipeItemIdx,
+    },
+    /// An item of the wrong kind was found while executing a recipe.
+    UnexpectedItem(ActionRecipeItemIdx),
+    /// An item was registered in the context but is not reachable from any recipe.
+    UnusedItem(ActionRecipeItemIdx),
+    /// Wraps an error reported by or about a named recipe.
+    InRecipe {
+        name: String,
+        error: Box<ConcertoError>,
+    },
+    /// An eliminate item refers to an item that is not part of the same recipe.
+    UnknownItem(ActionRecipeItemIdx),
+    /// An unordered item requires none of its children, or more than it has.
+    InvalidMatchCount(ActionRecipeItemIdx),
+    /// A nest recipe index is not below the number of nest recipes of its recipe.
+    NestRecipeOutOfRange {
+        nest_recipe_idx: usize,
+        nest_recipe_count: usize,
+    },
+    /// An optional item is not directly followed by a plain input, so there is
+    /// nothing that tells whether it was skipped.
+    OptionalWithoutFollowingInput(ActionRecipeItemIdx),
+    /// A repeated item is not directly followed by a plain input, so the repetition
+    /// could never end.
+    RepeatedWithoutFollowingInput(ActionRecipeItemIdx),
+    /// A recipe is placed under a prefix that was not declared before it.
+    UnknownPrefix(String),
+    /// A recipe step refers to a name no earlier step of the recipe was given, or a
+    /// name is given before the sequence has any step.
+    UnknownItemName(String),
+    /// A gesture pattern is empty or has a character other than `L`, `R`, `U` and `D`.
+    InvalidGesture(String),
+    /// The recipe items are shared with a clone of the context, so they can't be changed.
+    SharedRecipeItems,
+    /// The recipe was disabled after it broke while processing input.
+    BrokenRecipe,
+}
+
+impl ConcertoError {
+    pub(crate) fn in_recipe(self, name: &str) -> Self {
+        ConcertoError::InRecipe {
+            name: name.to_owned(),
+            error: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for ConcertoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            ConcertoError::EmptySequence(idx) => {
+                write!(f, "recipe item {:?} is an empty sequence", idx)
+            }
+            ConcertoError::NoInteractiveItem(idx) => write!(
+                f,
+                "recipe rooted at item {:?} completes without any input",
+                idx
+            ),
+            ConcertoError::NonInteractiveBranch { container, item } => write!(
+                f,
+                "recipe item {:?} in unordered, choice, optional or repeated item {:?} is not an input",
+                item, container
+            ),
+            ConcertoError::UnreachableBranch { container, item } => write!(
+                f,
+                "recipe item {:?} in choice item {:?} repeats an earlier branch and is never taken",
+                item, container
+            ),
+            ConcertoError::ConflictingBranch {
+                container,
+                item,
+                contract,
+            } => write!(
+                f,
+                "recipe item {:?} in item {:?} conflicts with earlier item {:?} and is never taken",
+                item, container, contract
+            ),
+            ConcertoError::UnexpectedItem(idx) => {
+                write!(f, "recipe item {:?} is not expected at its position", idx)
+            }
+            ConcertoError::UnusedItem(idx) => {
+                write!(f, "recipe item {:?} is not used by any recipe", idx)
+            }
+            ConcertoError::InRecipe { name, error } => {
+                write!(f, "in recipe \"{}\": {}", name, error)
+            }
+            ConcertoError::UnknownItem(idx) => {
+                write!(f, "recipe item {:?} is not part of the recipe", idx)
+            }
+            ConcertoError::InvalidMatchCount(idx) => write!(
+                f,
+                "unordered recipe item {:?} requires a number of matches it can't have",
+                idx
+            ),
+            ConcertoError::NestRecipeOutOfRange {
+                nest_recipe_idx,
+                nest_recipe_count,
+            } => write!(
+                f,
+                "nest recipe index {} is out of range, the recipe has {} nest recipes",
+                nest_recipe_idx, nest_recipe_count
+            ),
+            ConcertoError::OptionalWithoutFollowingInput(idx) => write!(
+                f,
+                "optional recipe item {:?} is not followed by an input",
+                idx
+            ),
+            ConcertoError::RepeatedWithoutFollowingInput(idx) => write!(
+                f,
+                "repeated recipe item {:?} is not followed by an input",
+                idx
+            ),
+            ConcertoError::UnknownPrefix(name) => {
+                write!(f, "prefix \"{}\" is not declared", name)
+            }
+            ConcertoError::UnknownItemName(name) => {
+                write!(f, "no earlier recipe item is named \"{}\"", name)
+            }
+            ConcertoError::InvalidGesture(pattern) => {
+                write!(f, "gesture pattern \"{}\" is not a sequence of strokes", pattern)
+            }
+            ConcertoError::SharedRecipeItems => {
+                write!(f, "recipe items are shared with a clone of the context")
+            }
+            ConcertoError::BrokenRecipe => write!(f, "recipe was disabled after it broke"),
+        }
+    }
+}
+
+impl Error for ConcertoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConcertoError::InRecipe { error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
@@ -1,72 +1,454 @@
+use compiled::{ActionCompiledRecipe, ActionCompiledState, ActionCompiledWait};
 use context::ActionEnvironmentTrackingState;
+use context::ActionEnvironmentView;
+use context::ActionExecutionId;
 use context::ActionRecipeItemIdx;
 use context::ActionRecipeItemStore;
+use error::ConcertoError;
 use fixedbitset::FixedBitSet;
 use recipe::ActionNestRecipeCommand;
-use recipe::{ActionCondition, ActionInput};
-use recipe::{ActionRecipe, ActionRecipeItem};
+use recipe::ActionUpdateRate;
+use recipe::{
+    is_axis_beyond, ActionCondition, ActionCursorMotion, ActionInput, ActionPinch, ActionStylus,
+    ActionTouch, ActionTouchPhase,
+};
+use recipe::{ActionKeyCharacterMap, ActionRecipe, ActionRecipeItem, ActionRecipeRepetition};
+use shared::{MaybeSendSync, Shared};
 use smallvec::SmallVec;
 use ActionConfiguration;
 
+#[derive(Clone)]
 enum ActionExecutionFrame {
     Sequential(Option<usize>),
-    Unordered(FixedBitSet),
+    // the children still pending and the number of children that have to match.
+    Unordered(FixedBitSet, usize),
     Choice(Option<usize>),
+    // position in the repeated item's body and the number of completed iterations.
+    Repeated(Option<usize>, usize),
 }
 
 use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+impl fmt::Debug for ActionExecutionFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            ActionExecutionFrame::Sequential(pos) => write!(f, "Sequential({:?})", pos),
+            ActionExecutionFrame::Unordered(remaining, required) => f
+                .debug_tuple("Unordered")
+                .field(&remaining.ones().collect::<Vec<_>>())
+                .field(required)
+                .finish(),
+            ActionExecutionFrame::Choice(choice) => write!(f, "Choice({:?})", choice),
+            ActionExecutionFrame::Repeated(pos, iteration) => {
+                write!(f, "Repeated({:?}, {})", pos, iteration)
+            }
+        }
+    }
+}
+
+impl ActionExecutionFrame {
+    fn unordered(len: usize, required: usize) -> Self {
+        let mut bitset = FixedBitSet::with_capacity(len);
+        bitset.set_range(.., true);
+        ActionExecutionFrame::Unordered(bitset, required)
+    }
+
+    fn for_compiled_state(state: &ActionCompiledState) -> Self {
+        match state.wait {
+            ActionCompiledWait::Single
+            | ActionCompiledWait::Optional
+            | ActionCompiledWait::Repeated(_) => ActionExecutionFrame::Sequential(None),
+            ActionCompiledWait::Unordered(required) => {
+                ActionExecutionFrame::unordered(state.inputs.len(), required)
+            }
+            ActionCompiledWait::Choice => ActionExecutionFrame::Choice(None),
+        }
+    }
+
+    fn is_pending(&self, seq_idx: usize) -> bool {
+        match self {
+            ActionExecutionFrame::Sequential(state_pos)
+            | ActionExecutionFrame::Repeated(state_pos, _) => state_pos.is_none(),
+            ActionExecutionFrame::Unordered(state_set, _) => state_set.contains(seq_idx),
+            ActionExecutionFrame::Choice(state_choice) => state_choice.is_none(),
+        }
+    }
+
+    fn is_satisfied(&self) -> bool {
+        match self {
+            ActionExecutionFrame::Sequential(state_pos)
+            | ActionExecutionFrame::Repeated(state_pos, _) => state_pos.is_some(),
+            ActionExecutionFrame::Unordered(state_set, required) => {
+                state_set.len() - state_set.count_ones(..) >= *required
+            }
+            ActionExecutionFrame::Choice(state_choice) => state_choice.is_some(),
+        }
+    }
+
+    fn mark_used(&mut self, seq_idx: usize) {
+        match self {
+            ActionExecutionFrame::Sequential(state_pos)
+            | ActionExecutionFrame::Repeated(state_pos, _) => *state_pos = Some(seq_idx),
+            ActionExecutionFrame::Unordered(state_set, _) => state_set.set(seq_idx, false),
+            ActionExecutionFrame::Choice(state_choice) => *state_choice = Some(seq_idx),
+        }
+    }
+}
+
+/// Position of an execution within a compiled recipe: the wait state it is in
+/// (`None` before the entry items ran) and the progress made on that wait.
+#[derive(Clone, Debug)]
+struct ActionCompiledCursor {
+    state: Option<usize>,
+    item: ActionRecipeItemIdx,
+    frame: ActionExecutionFrame,
+    // the repeated items entered so far, innermost last, with their completed iterations.
+    iterations: SmallVec<[(ActionRecipeItemIdx, usize); 2]>,
+}
+
+/// The recipe that issued a command and the execution of it that did, which is
+/// `None` for the commands the context issues on its own.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct ActionCommandSource {
+    pub(crate) recipe_idx: usize,
+    pub(crate) execution_id: Option<ActionExecutionId>,
+}
+
+/// Issued commands, each tagged with where it came from.
+pub(crate) type ActionCommandList<C> = Vec<(
+    ActionCommandSource,
+    Shared<<C as ActionConfiguration>::Command>,
+)>;
 
 pub(crate) struct ActionExecutionCtx<C: ActionConfiguration> {
     recipe_idx: usize,
     backtrace: SmallVec<[(ActionRecipeItemIdx, ActionExecutionFrame); 3]>,
+    compiled: Option<ActionCompiledCursor>,
     stored_contracts: ActionExecutionContractStore<C>,
+    deadline: Option<Duration>,
+    started_at: Duration,
+    // ignored inputs kept for the recipe's input buffer, oldest first.
+    buffered_inputs: Vec<(Duration, ActionInput<C>)>,
 }
 
 enum ActionExecutionContract<C: ActionConfiguration> {
-    Input(ActionInput<C>),
-    Condition(ActionCondition<C>),
-    Effect(C::Command),
+    // shares the recipe's own input when it was matched exactly.
+    Input(Shared<ActionInput<C>>),
+    // the condition itself is looked up from the recipe item that stored it.
+    Condition,
+    // the end commands for a normal end and for an abort.
+    Effect(Shared<C::Command>, Shared<C::Command>),
     NestRecipe(usize),
     NestRecipeDisable(usize),
+    // the update generator is looked up from the recipe item that stored it.
+    Updates(ActionUpdateState),
+}
+
+/// The rate of an update generator in effect, when it last issued a command, and
+/// whether a matched input has gone without one since.
+#[derive(Copy, Clone, Debug)]
+struct ActionUpdateState {
+    rate: ActionUpdateRate,
+    issued_at: Option<Duration>,
+    pending: bool,
+}
+
+impl ActionUpdateState {
+    // `OncePerTick` has no deadline, as it waits for the next time step, whenever it comes.
+    fn deadline(&self) -> Option<Duration> {
+        match (self.rate, self.issued_at) {
+            (ActionUpdateRate::AtMostEvery(interval), Some(issued_at)) if self.pending => {
+                Some(issued_at + interval)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<C: ActionConfiguration> Clone for ActionExecutionContract<C> {
+    fn clone(&self) -> Self {
+        match self {
+            ActionExecutionContract::Input(input) => ActionExecutionContract::Input(input.clone()),
+            ActionExecutionContract::Condition => ActionExecutionContract::Condition,
+            ActionExecutionContract::Effect(effect_end, effect_abort_end) => {
+                ActionExecutionContract::Effect(effect_end.clone(), effect_abort_end.clone())
+            }
+            ActionExecutionContract::NestRecipe(idx) => ActionExecutionContract::NestRecipe(*idx),
+            ActionExecutionContract::NestRecipeDisable(idx) => {
+                ActionExecutionContract::NestRecipeDisable(*idx)
+            }
+            ActionExecutionContract::Updates(state) => ActionExecutionContract::Updates(*state),
+        }
+    }
+}
+
+impl<C: ActionConfiguration> fmt::Debug for ActionExecutionContract<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            ActionExecutionContract::Input(input) => write!(f, "Input({:?})", input),
+            ActionExecutionContract::Condition => write!(f, "Condition"),
+            ActionExecutionContract::Effect(..) => write!(f, "Effect(..)"),
+            ActionExecutionContract::NestRecipe(idx) => write!(f, "NestRecipe({})", idx),
+            ActionExecutionContract::NestRecipeDisable(idx) => {
+                write!(f, "NestRecipeDisable({})", idx)
+            }
+            ActionExecutionContract::Updates(state) => write!(f, "Updates({:?})", state),
+        }
+    }
+}
+
+/// Number of contracts an execution keeps in a sorted inline vector before switching
+/// to a `BTreeMap`, unless configured otherwise.
+pub(crate) const DEFAULT_INLINE_CONTRACT_LIMIT: usize = 8;
+
+/// Contracts keyed by the item that stored them, in item order. Most executions only
+/// hold a handful, so they live in a sorted inline vector until there are more than
+/// the configured limit.
+enum ActionExecutionContractMap<C: ActionConfiguration> {
+    Inline(SmallVec<[(ActionRecipeItemIdx, ActionExecutionContract<C>); 4]>),
+    Tree(BTreeMap<ActionRecipeItemIdx, ActionExecutionContract<C>>),
+}
+
+enum ActionExecutionContractIter<'a, C: ActionConfiguration> {
+    Inline(std::slice::Iter<'a, (ActionRecipeItemIdx, ActionExecutionContract<C>)>),
+    Tree(std::collections::btree_map::Iter<'a, ActionRecipeItemIdx, ActionExecutionContract<C>>),
+}
+
+impl<'a, C: ActionConfiguration> Iterator for ActionExecutionContractIter<'a, C> {
+    type Item = (&'a ActionRecipeItemIdx, &'a ActionExecutionContract<C>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ActionExecutionContractIter::Inline(iter) => iter.next().map(|(k, v)| (k, v)),
+            ActionExecutionContractIter::Tree(iter) => iter.next(),
+        }
+    }
+}
+
+impl<C: ActionConfiguration> Clone for ActionExecutionContractMap<C> {
+    fn clone(&self) -> Self {
+        match self {
+            ActionExecutionContractMap::Inline(contracts) => {
+                ActionExecutionContractMap::Inline(contracts.clone())
+            }
+            ActionExecutionContractMap::Tree(contracts) => {
+                ActionExecutionContractMap::Tree(contracts.clone())
+            }
+        }
+    }
+}
+
+impl<C: ActionConfiguration> fmt::Debug for ActionExecutionContractMap<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<C: ActionConfiguration> ActionExecutionContractMap<C> {
+    fn new(inline_limit: usize) -> Self {
+        if inline_limit == 0 {
+            ActionExecutionContractMap::Tree(BTreeMap::new())
+        } else {
+            ActionExecutionContractMap::Inline(SmallVec::new())
+        }
+    }
+
+    fn iter(&self) -> ActionExecutionContractIter<'_, C> {
+        match self {
+            ActionExecutionContractMap::Inline(contracts) => {
+                ActionExecutionContractIter::Inline(contracts.iter())
+            }
+            ActionExecutionContractMap::Tree(contracts) => {
+                ActionExecutionContractIter::Tree(contracts.iter())
+            }
+        }
+    }
+
+    fn insert(
+        &mut self,
+        item: ActionRecipeItemIdx,
+        contract: ActionExecutionContract<C>,
+        inline_limit: usize,
+    ) {
+        let contracts = match self {
+            ActionExecutionContractMap::Inline(contracts) => contracts,
+            ActionExecutionContractMap::Tree(contracts) => {
+                contracts.insert(item, contract);
+                return;
+            }
+        };
+        match contracts.binary_search_by(|(k, _)| k.cmp(&item)) {
+            Ok(pos) => contracts[pos].1 = contract,
+            Err(_) if contracts.len() >= inline_limit => {
+                let mut tree = contracts.drain(..).collect::<BTreeMap<_, _>>();
+                tree.insert(item, contract);
+                *self = ActionExecutionContractMap::Tree(tree);
+            }
+            Err(pos) => contracts.insert(pos, (item, contract)),
+        }
+    }
+
+    fn get_mut(&mut self, item: &ActionRecipeItemIdx) -> Option<&mut ActionExecutionContract<C>> {
+        match self {
+            ActionExecutionContractMap::Inline(contracts) => contracts
+                .binary_search_by(|(k, _)| k.cmp(item))
+                .ok()
+                .map(move |pos| &mut contracts[pos].1),
+            ActionExecutionContractMap::Tree(contracts) => contracts.get_mut(item),
+        }
+    }
+
+    fn remove(&mut self, item: &ActionRecipeItemIdx) -> Option<ActionExecutionContract<C>> {
+        match self {
+            ActionExecutionContractMap::Inline(contracts) => contracts
+                .binary_search_by(|(k, _)| k.cmp(item))
+                .ok()
+                .map(|pos| contracts.remove(pos).1),
+            ActionExecutionContractMap::Tree(contracts) => contracts.remove(item),
+        }
+    }
+}
+
+/// An input matched by the execution, kept after its contract is eliminated.
+struct ActionMatchedInput<C: ActionConfiguration> {
+    item: ActionRecipeItemIdx,
+    choice: Option<(ActionRecipeItemIdx, usize)>,
+    repetition: Option<(ActionRecipeItemIdx, usize)>,
+    input: Shared<ActionInput<C>>,
+    time: Duration,
+}
+
+impl<C: ActionConfiguration> Clone for ActionMatchedInput<C> {
+    fn clone(&self) -> Self {
+        ActionMatchedInput {
+            item: self.item,
+            choice: self.choice,
+            repetition: self.repetition,
+            input: self.input.clone(),
+            time: self.time,
+        }
+    }
+}
+
+impl<C: ActionConfiguration> fmt::Debug for ActionMatchedInput<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("ActionMatchedInput")
+            .field("item", &self.item)
+            .field("choice", &self.choice)
+            .field("repetition", &self.repetition)
+            .field("input", &self.input)
+            .field("time", &self.time)
+            .finish()
+    }
 }
 
 struct ActionExecutionContractStore<C: ActionConfiguration> {
-    contracts: BTreeMap<ActionRecipeItemIdx, ActionExecutionContract<C>>,
+    execution_id: ActionExecutionId,
+    contracts: ActionExecutionContractMap<C>,
+    // the items holding effect contracts and their start commands, in the order the
+    // effects started, as the contracts themselves are kept in item order.
+    effect_order: SmallVec<[(ActionRecipeItemIdx, Shared<C::Command>); 2]>,
+    inline_limit: usize,
+    matched_inputs: SmallVec<[ActionMatchedInput<C>; 4]>,
+    current_time: Duration,
+    // set on the copies `ActionExecutionCtx::peek_input` and `ActionContext::simulate`
+    // work on, so generators aren't called.
+    dry_run: bool,
+}
+
+impl<C: ActionConfiguration> Clone for ActionExecutionContractStore<C> {
+    fn clone(&self) -> Self {
+        ActionExecutionContractStore {
+            execution_id: self.execution_id,
+            contracts: self.contracts.clone(),
+            effect_order: self.effect_order.clone(),
+            inline_limit: self.inline_limit,
+            matched_inputs: self.matched_inputs.clone(),
+            current_time: self.current_time,
+            dry_run: self.dry_run,
+        }
+    }
 }
 
 impl<C: ActionConfiguration> ActionExecutionContractStore<C> {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(inline_limit: usize, execution_id: ActionExecutionId) -> Self {
         ActionExecutionContractStore {
-            contracts: BTreeMap::new(),
+            execution_id,
+            contracts: ActionExecutionContractMap::new(inline_limit),
+            effect_order: SmallVec::new(),
+            inline_limit,
+            matched_inputs: SmallVec::new(),
+            current_time: Duration::default(),
+            dry_run: false,
         }
     }
 
-    pub(crate) fn add_input(&mut self, item: ActionRecipeItemIdx, input_contract: ActionInput<C>) {
-        self.contracts
-            .insert(item, ActionExecutionContract::Input(input_contract));
+    fn insert(&mut self, item: ActionRecipeItemIdx, contract: ActionExecutionContract<C>) {
+        self.contracts.insert(item, contract, self.inline_limit);
     }
 
-    pub(crate) fn add_condition(
+    /// `choice` is the choice item and branch position when the input selected a branch,
+    /// `repetition` the innermost repeated item and iteration the input was matched in.
+    pub(crate) fn add_input(
         &mut self,
         item: ActionRecipeItemIdx,
-        condition_contract: ActionCondition<C>,
+        input_contract: Shared<ActionInput<C>>,
+        choice: Option<(ActionRecipeItemIdx, usize)>,
+        repetition: Option<(ActionRecipeItemIdx, usize)>,
     ) {
-        self.contracts
-            .insert(item, ActionExecutionContract::Condition(condition_contract));
+        self.matched_inputs.push(ActionMatchedInput {
+            item,
+            choice,
+            repetition,
+            input: input_contract.clone(),
+            time: self.current_time,
+        });
+        self.insert(item, ActionExecutionContract::Input(input_contract));
+    }
+
+    /// Records an input read from the environment, which stores no contract.
+    pub(crate) fn add_captured_input(
+        &mut self,
+        item: ActionRecipeItemIdx,
+        input: Shared<ActionInput<C>>,
+    ) {
+        self.matched_inputs.push(ActionMatchedInput {
+            item,
+            choice: None,
+            repetition: None,
+            input,
+            time: self.current_time,
+        });
+    }
+
+    fn last_matched_at(&self) -> Option<Duration> {
+        self.matched_inputs.last().map(|matched| matched.time)
+    }
+
+    pub(crate) fn add_condition(&mut self, item: ActionRecipeItemIdx) {
+        self.insert(item, ActionExecutionContract::Condition);
     }
 
     pub(crate) fn add_effect(
         &mut self,
         item: ActionRecipeItemIdx,
-        effect_end_contract: C::Command,
+        effect_start: Shared<C::Command>,
+        effect_end_contract: Shared<C::Command>,
+        effect_abort_end_contract: Shared<C::Command>,
     ) {
-        self.contracts
-            .insert(item, ActionExecutionContract::Effect(effect_end_contract));
+        self.effect_order
+            .retain(|(effect_item, _)| *effect_item != item);
+        self.effect_order.push((item, effect_start));
+        self.insert(
+            item,
+            ActionExecutionContract::Effect(effect_end_contract, effect_abort_end_contract),
+        );
     }
 
     pub(crate) fn add_nest_recipe(&mut self, item: ActionRecipeItemIdx, nest_recipe: usize) {
-        self.contracts
-            .insert(item, ActionExecutionContract::NestRecipe(nest_recipe));
+        self.insert(item, ActionExecutionContract::NestRecipe(nest_recipe));
     }
 
     pub(crate) fn add_nest_recipe_disabled(
@@ -74,30 +456,44 @@ impl<C: ActionConfiguration> ActionExecutionContractStore<C> {
         item: ActionRecipeItemIdx,
         nest_recipe: usize,
     ) {
-        self.contracts.insert(
+        self.insert(
             item,
             ActionExecutionContract::NestRecipeDisable(nest_recipe),
         );
     }
 
+    fn source(&self, recipe_id: usize) -> ActionCommandSource {
+        ActionCommandSource {
+            recipe_idx: recipe_id,
+            execution_id: Some(self.execution_id),
+        }
+    }
+
     fn internal_eliminate_contract(
-        &mut self,
-        recipe_id: usize,
+        source: ActionCommandSource,
         contract: ActionExecutionContract<C>,
-        command_list: &mut Vec<C::Command>,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        aborted: bool,
     ) -> bool {
         match contract {
-            ActionExecutionContract::Effect(effect_end) => {
-                command_list.push(effect_end);
+            ActionExecutionContract::Effect(effect_end, effect_abort_end) => {
+                let effect_end = if aborted {
+                    effect_abort_end
+                } else {
+                    effect_end
+                };
+                command_list.push((source, effect_end));
                 true
             }
             ActionExecutionContract::NestRecipe(id) => {
-                nest_recipe_command_list.push(ActionNestRecipeCommand::Abort(recipe_id, id));
+                nest_recipe_command_list
+                    .push(ActionNestRecipeCommand::Abort(source.recipe_idx, id));
                 false
             }
             ActionExecutionContract::NestRecipeDisable(id) => {
-                nest_recipe_command_list.push(ActionNestRecipeCommand::Enable(recipe_id, id));
+                nest_recipe_command_list
+                    .push(ActionNestRecipeCommand::Enable(source.recipe_idx, id));
                 false
             }
             _ => false,
@@ -108,42 +504,86 @@ impl<C: ActionConfiguration> ActionExecutionContractStore<C> {
         &mut self,
         recipe_id: usize,
         item: &ActionRecipeItemIdx,
-        command_list: &mut Vec<C::Command>,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
     ) -> bool {
         if let Some(contract) = self.contracts.remove(item) {
-            self.internal_eliminate_contract(
-                recipe_id,
+            if let ActionExecutionContract::Effect(..) = contract {
+                self.effect_order
+                    .retain(|(effect_item, _)| effect_item != item);
+            }
+            Self::internal_eliminate_contract(
+                self.source(recipe_id),
                 contract,
                 command_list,
                 nest_recipe_command_list,
+                false,
             )
         } else {
             false
         }
     }
 
+    /// Ends the effect contracts as if the execution had completed, latest started
+    /// first, keeping the rest.
+    pub(crate) fn commit_effects(
+        &mut self,
+        recipe_id: usize,
+        command_list: &mut ActionCommandList<C>,
+    ) {
+        let source = self.source(recipe_id);
+        for (item, _) in self.effect_order.drain(..).rev() {
+            if let Some(ActionExecutionContract::Effect(effect_end, _)) =
+                self.contracts.remove(&item)
+            {
+                command_list.push((source, effect_end));
+            }
+        }
+    }
+
     pub(crate) fn eliminate_all(
         &mut self,
         recipe_id: usize,
-        command_list: &mut Vec<C::Command>,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        aborted: bool,
     ) -> bool {
-        use std::mem::swap;
-
-        let mut contracts = BTreeMap::new();
-        swap(&mut self.contracts, &mut contracts);
         let mut new_command = false;
-        for (_k, contract) in contracts {
-            if self.internal_eliminate_contract(
-                recipe_id,
+        let source = self.source(recipe_id);
+        // effects end in the reverse order they started, before any other contract.
+        let contracts = &mut self.contracts;
+        let effects = self
+            .effect_order
+            .drain(..)
+            .rev()
+            .filter_map(|(item, _)| contracts.remove(&item))
+            .collect::<SmallVec<[_; 2]>>();
+        let mut eliminate = |contract| {
+            if Self::internal_eliminate_contract(
+                source,
                 contract,
                 command_list,
                 nest_recipe_command_list,
+                aborted,
             ) {
                 new_command = true;
             }
+        };
+        effects.into_iter().for_each(&mut eliminate);
+        match &mut self.contracts {
+            ActionExecutionContractMap::Inline(contracts) => {
+                contracts
+                    .drain(..)
+                    .for_each(|(_k, contract)| eliminate(contract));
+            }
+            ActionExecutionContractMap::Tree(contracts) => {
+                std::mem::take(contracts)
+                    .into_iter()
+                    .for_each(|(_k, contract)| eliminate(contract));
+            }
         }
+        self.contracts = ActionExecutionContractMap::new(self.inline_limit);
+        self.matched_inputs.clear();
         new_command
     }
 }
@@ -158,36 +598,265 @@ impl<'a, C: ActionConfiguration> ActionRecipeExecutionInfo<'a, C> {
     }
     pub fn cursor_coordinate(&self) -> Option<&C::Target> {
         for (_idx, contract) in self.stored_contracts.contracts.iter() {
-            match contract {
-                ActionExecutionContract::Input(expected_input) => match expected_input {
-                    ActionInput::CursorCoordinate(target) => return Some(target),
-                    _ => {}
-                },
-                _ => {}
+            if let ActionExecutionContract::Input(input) = contract {
+                if let ActionInput::CursorCoordinate(target) = &**input {
+                    return Some(target);
+                }
             }
         }
         None
     }
+
+    /// The time between the first and the last arrival among the latest inputs the
+    /// execution matched for `items`.
+    pub(crate) fn arrival_spread(&self, items: &[ActionRecipeItemIdx]) -> Duration {
+        let times = items.iter().filter_map(|&item_idx| {
+            self.stored_contracts
+                .matched_inputs
+                .iter()
+                .rev()
+                .find(|matched| matched.item == item_idx)
+                .map(|matched| matched.time)
+        });
+        match (times.clone().min(), times.max()) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::default(),
+        }
+    }
+
+    /// The targets of every matched cursor coordinate and stylus sample, in the order
+    /// they arrived, e.g. the whole path of a drag or stroke.
+    pub fn cursor_path(&self) -> impl Iterator<Item = &'a C::Target> + 'a {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .filter_map(|matched| match &*matched.input {
+                ActionInput::CursorCoordinate(target) | ActionInput::Stylus(target, _) => {
+                    Some(target)
+                }
+                _ => None,
+            })
+    }
+
+    /// Every input the execution matched so far, in the order they arrived, including
+    /// those whose contracts were already eliminated.
+    pub fn matched_inputs(
+        &self,
+    ) -> impl Iterator<Item = (ActionRecipeItemIdx, &'a ActionInput<C>)> + 'a {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .map(|matched| (matched.item, &*matched.input))
+    }
+
+    /// Like `matched_inputs`, with the context time each input was matched at.
+    pub fn timed_inputs(&self) -> impl Iterator<Item = (Duration, &'a ActionInput<C>)> + 'a {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .map(|matched| (matched.time, &*matched.input))
+    }
+
+    /// The context time of the input being processed.
+    pub fn current_time(&self) -> Duration {
+        self.stored_contracts.current_time
+    }
+
+    /// The execution being run, the same one the issued commands are attributed to.
+    pub fn execution_id(&self) -> ActionExecutionId {
+        self.stored_contracts.execution_id
+    }
+
+    /// The key of the most recently matched key input, e.g. the one accepted by a key filter.
+    pub fn matched_key(&self) -> Option<&'a C::KeyKind> {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .rev()
+            .find_map(|matched| match &*matched.input {
+                ActionInput::KeyDown(key)
+                | ActionInput::KeyUp(key)
+                | ActionInput::KeyRepeat(key) => Some(key),
+                _ => None,
+            })
+    }
+
+    /// The value of the most recently matched axis input.
+    pub fn matched_axis_value(&self) -> Option<f32> {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .rev()
+            .find_map(|matched| match &*matched.input {
+                ActionInput::Axis(_, value) => Some(*value),
+                _ => None,
+            })
+    }
+
+    /// The target and state of the most recently matched stylus sample.
+    pub fn matched_stylus(&self) -> Option<(&'a C::Target, ActionStylus)> {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .rev()
+            .find_map(|matched| match &*matched.input {
+                ActionInput::Stylus(target, stylus) => Some((target, *stylus)),
+                _ => None,
+            })
+    }
+
+    /// The text of the matched `Character` inputs, a backspace (`'\u{8}'`) removing
+    /// the character before it.
+    pub fn captured_text(&self) -> String {
+        let mut text = String::new();
+        for matched in self.stored_contracts.matched_inputs.iter() {
+            match &*matched.input {
+                ActionInput::Character('\u{8}') => {
+                    text.pop();
+                }
+                ActionInput::Character(c) => text.push(*c),
+                _ => {}
+            }
+        }
+        text
+    }
+
+    /// How the first two fingers whose touches the execution matched moved, from
+    /// where they were when the second one touched down to the latest positions it
+    /// matched.
+    pub fn matched_pinch(&self) -> Option<ActionPinch> {
+        let mut fingers: SmallVec<[(ActionTouch, ActionTouch); 2]> = SmallVec::new();
+        for matched in self.stored_contracts.matched_inputs.iter() {
+            match &*matched.input {
+                ActionInput::TouchStart(touch) if fingers.len() < 2 => {
+                    if let Some(first) = fingers.first_mut() {
+                        first.0 = first.1;
+                    }
+                    fingers.push((*touch, *touch));
+                }
+                ActionInput::TouchMove(touch) | ActionInput::TouchEnd(touch) => {
+                    if let Some(finger) = fingers.iter_mut().find(|(start, _)| start.id == touch.id)
+                    {
+                        finger.1 = *touch;
+                    }
+                }
+                _ => {}
+            }
+        }
+        match fingers[..] {
+            [(a_start, a_now), (b_start, b_now)] => {
+                Some(ActionPinch::between((a_start, b_start), (a_now, b_now)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The branch taken by the most recently matched choice: its position among the
+    /// alternatives and the input that selected it.
+    pub fn chosen_branch(&self) -> Option<(usize, &ActionInput<C>)> {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .rev()
+            .find_map(|matched| matched.choice.map(|(_, branch)| (branch, &*matched.input)))
+    }
+
+    fn last_repetition(&self) -> Option<(ActionRecipeItemIdx, usize)> {
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .rev()
+            .find_map(|matched| matched.repetition)
+    }
+
+    /// The number of iterations of the most recently repeated item that matched an input.
+    pub fn iteration_count(&self) -> usize {
+        self.last_repetition()
+            .map(|(_, iteration)| iteration + 1)
+            .unwrap_or(0)
+    }
+
+    /// The inputs matched during the given iteration, counted from zero, of the most
+    /// recently repeated item.
+    pub fn iteration_inputs(
+        &self,
+        iteration: usize,
+    ) -> impl Iterator<Item = (ActionRecipeItemIdx, &'a ActionInput<C>)> + 'a {
+        let repetition = self
+            .last_repetition()
+            .map(|(repeated_item, _)| (repeated_item, iteration));
+        self.stored_contracts
+            .matched_inputs
+            .iter()
+            .filter(move |matched| repetition.is_some() && matched.repetition == repetition)
+            .map(|matched| (matched.item, &*matched.input))
+    }
+}
+
+impl<C: ActionConfiguration> Clone for ActionExecutionCtx<C> {
+    fn clone(&self) -> Self {
+        ActionExecutionCtx {
+            recipe_idx: self.recipe_idx,
+            backtrace: self.backtrace.clone(),
+            compiled: self.compiled.clone(),
+            stored_contracts: self.stored_contracts.clone(),
+            deadline: self.deadline,
+            started_at: self.started_at,
+            buffered_inputs: self.buffered_inputs.clone(),
+        }
+    }
+}
+
+impl<C: ActionConfiguration> fmt::Debug for ActionExecutionCtx<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("ActionExecutionCtx")
+            .field("recipe_idx", &self.recipe_idx)
+            .field("backtrace", &self.backtrace)
+            .field("compiled", &self.compiled)
+            .field("contracts", &self.stored_contracts.contracts)
+            .field("matched_inputs", &self.stored_contracts.matched_inputs)
+            .field("deadline", &self.deadline)
+            .field("started_at", &self.started_at)
+            .field("buffered_inputs", &self.buffered_inputs)
+            .finish()
+    }
 }
 
 impl<C: ActionConfiguration> ActionExecutionCtx<C> {
     fn new(
         recipe_idx: usize,
+        execution_id: ActionExecutionId,
         recipe: &ActionRecipe<C>,
         recipe_items: &ActionRecipeItemStore<C>,
-    ) -> Self {
+    ) -> Result<Self, ConcertoError> {
         let mut ctx = ActionExecutionCtx {
             recipe_idx,
             backtrace: SmallVec::new(),
-            stored_contracts: ActionExecutionContractStore::new(),
+            compiled: None,
+            stored_contracts: ActionExecutionContractStore::new(
+                recipe.inline_contract_limit,
+                execution_id,
+            ),
+            deadline: None,
+            started_at: Duration::default(),
+            buffered_inputs: Vec::new(),
         };
 
+        if recipe.compiled.is_some() {
+            ctx.compiled = Some(ActionCompiledCursor {
+                state: None,
+                item: recipe.root_item,
+                frame: ActionExecutionFrame::Sequential(None),
+                iterations: SmallVec::new(),
+            });
+            return Ok(ctx);
+        }
         ctx.backtrace
             .push(Self::prepare_new_frame_for_compound_item(
-                recipe_items.get(recipe.root_item),
+                recipe_items.get(recipe.root_item)?,
                 recipe.root_item,
-            ));
-        ctx
+            )?);
+        Ok(ctx)
     }
 
     #[allow(dead_code)]
@@ -195,36 +864,102 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
         &recipes[self.recipe_idx]
     }
 
+    /// The compound item the execution is currently waiting in.
+    pub(crate) fn current_item(&self) -> Option<ActionRecipeItemIdx> {
+        if let Some(cursor) = &self.compiled {
+            return Some(cursor.item);
+        }
+        self.backtrace.last().map(|(item_idx, _)| *item_idx)
+    }
+
+    pub(crate) fn set_dry_run(&mut self) {
+        self.stored_contracts.dry_run = true;
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    pub(crate) fn set_deadline(&mut self, deadline: Option<Duration>) {
+        self.deadline = deadline;
+    }
+
+    /// The times after `since` at which a dwell of the recipe would be due, counted
+    /// from the last matched input.
+    pub(crate) fn dwell_deadlines<'a>(
+        &self,
+        recipe: &'a ActionRecipe<C>,
+        since: Duration,
+    ) -> impl Iterator<Item = Duration> + 'a {
+        let last_matched_at = self.stored_contracts.last_matched_at();
+        recipe
+            .dwell_durations
+            .iter()
+            .filter_map(move |duration| last_matched_at.map(|time| time + *duration))
+            .filter(move |&deadline| deadline > since)
+    }
+
+    /// The start commands of the effects that haven't ended yet, in the order they
+    /// started.
+    pub(crate) fn active_effects(&self) -> impl Iterator<Item = &C::Command> + '_ {
+        self.stored_contracts
+            .effect_order
+            .iter()
+            .map(|(_, effect_start)| &**effect_start)
+    }
+
+    pub(crate) fn started_at(&self) -> Duration {
+        self.started_at
+    }
+
+    pub(crate) fn set_started_at(&mut self, started_at: Duration) {
+        self.started_at = started_at;
+    }
+
     fn stored_contracts_conflict(
         input: &ActionInput<C>,
         stored_contracts: &ActionExecutionContractStore<C>,
-    ) -> bool {
-        for (_idx, contract) in stored_contracts.contracts.iter() {
+        recipe_items: &ActionRecipeItemStore<C>,
+        env: &ActionEnvironmentTrackingState<C>,
+    ) -> Result<bool, ConcertoError> {
+        for (idx, contract) in stored_contracts.contracts.iter() {
             match contract {
                 ActionExecutionContract::Input(expected_input) => {
-                    match Self::check_input_match_input(expected_input, input) {
-                        ExecutionContextResult::Abort => return true,
-                        _ => {}
+                    if let ExecutionContextResult::Abort =
+                        Self::check_input_match_input(expected_input, input)
+                    {
+                        return Ok(true);
                     }
                 }
-                ActionExecutionContract::Condition(condition) => {
-                    match Self::check_input_match_condition(condition, input) {
-                        ExecutionContextResult::Abort => return true,
-                        _ => {}
+                ActionExecutionContract::Condition => {
+                    let condition = match recipe_items.get(*idx)? {
+                        ActionRecipeItem::StartCondition(condition) => condition,
+                        _ => return Err(ConcertoError::UnexpectedItem(*idx)),
+                    };
+                    // custom and composite conditions can't always be judged from the input alone.
+                    if condition.depends_on_environment()
+                        && !Self::check_condition_match_environment(condition, env)
+                    {
+                        return Ok(true);
+                    }
+                    if let ExecutionContextResult::Abort =
+                        Self::check_input_match_condition(condition, input)
+                    {
+                        return Ok(true);
                     }
                 }
                 _ => {}
             }
         }
 
-        false
+        Ok(false)
     }
 
-    pub(crate) fn make_input_filter_with_cursor_coordinate_filter<F>(
+    pub(crate) fn make_input_filter_with_cursor_coordinate_filter<'f, F>(
         filter: F,
-    ) -> impl Fn(&ActionInput<C>) -> ExecutionContextResult
+    ) -> impl Fn(&ActionInput<C>) -> ExecutionContextResult + MaybeSendSync + 'f
     where
-        F: Fn(&C::Target) -> bool + 'static,
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
     {
         move |input: &ActionInput<C>| match input {
             ActionInput::CursorCoordinate(target) => {
@@ -238,13 +973,184 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
         }
     }
 
-    fn check_input_match_input(
+    pub(crate) fn make_input_filter_with_stylus_filter<'f, F>(
+        filter: F,
+    ) -> impl Fn(&ActionInput<C>) -> ExecutionContextResult + MaybeSendSync + 'f
+    where
+        F: Fn(&C::Target, &ActionStylus) -> bool + MaybeSendSync + 'f,
+    {
+        move |input: &ActionInput<C>| match input {
+            ActionInput::Stylus(target, stylus) if (filter)(target, stylus) => {
+                ExecutionContextResult::Used
+            }
+            _ => ExecutionContextResult::Ignore,
+        }
+    }
+
+    pub(crate) fn make_input_filter_with_matched_touch<'f>(
+        ending: bool,
+    ) -> impl Fn(ActionRecipeExecutionInfo<C>, &ActionInput<C>) -> ExecutionContextResult
+           + MaybeSendSync
+           + 'f {
+        move |info: ActionRecipeExecutionInfo<C>, input: &ActionInput<C>| {
+            let touch = match input {
+                ActionInput::TouchMove(touch) if !ending => touch,
+                ActionInput::TouchEnd(touch) if ending => touch,
+                _ => return ExecutionContextResult::Ignore,
+            };
+            let matched = info.matched_inputs().any(|(_, input)| match input {
+                ActionInput::TouchStart(start) => start.id == touch.id,
+                _ => false,
+            });
+            if matched {
+                ExecutionContextResult::Used
+            } else {
+                ExecutionContextResult::Ignore
+            }
+        }
+    }
+
+    pub(crate) fn make_input_filter_with_cursor_distance_filter<'f, P, M>(
+        threshold: f64,
+        position: P,
+        metric: M,
+    ) -> impl Fn(ActionRecipeExecutionInfo<C>, &ActionInput<C>) -> ExecutionContextResult
+           + MaybeSendSync
+           + 'f
+    where
+        P: Fn(&C::Target) -> Option<C::CursorPos> + MaybeSendSync + 'f,
+        M: Fn(&C::CursorPos, &C::CursorPos) -> f64 + MaybeSendSync + 'f,
+    {
+        move |exec_info: ActionRecipeExecutionInfo<C>, input: &ActionInput<C>| {
+            let target = match input {
+                ActionInput::CursorCoordinate(target) => target,
+                _ => return ExecutionContextResult::Ignore,
+            };
+            let origin = exec_info
+                .matched_inputs()
+                .find_map(|(_, matched)| match matched {
+                    ActionInput::CursorCoordinate(origin) => Some(origin),
+                    _ => None,
+                });
+            let moved_beyond = match (origin.and_then(&position), (position)(target)) {
+                (Some(origin), Some(current)) => (metric)(&origin, &current) > threshold,
+                _ => false,
+            };
+            if moved_beyond {
+                ExecutionContextResult::Used
+            } else {
+                ExecutionContextResult::Ignore
+            }
+        }
+    }
+
+    pub(crate) fn make_input_filter_with_cursor_motion_filter<'f, P, F>(
+        position: P,
+        predicate: F,
+    ) -> impl Fn(ActionRecipeExecutionInfo<C>, &ActionInput<C>) -> ExecutionContextResult
+           + MaybeSendSync
+           + 'f
+    where
+        P: Fn(&C::Target) -> Option<C::CursorPos> + MaybeSendSync + 'f,
+        F: Fn(&ActionCursorMotion<C::CursorPos>) -> bool + MaybeSendSync + 'f,
+    {
+        move |exec_info: ActionRecipeExecutionInfo<C>, input: &ActionInput<C>| {
+            let target = match input {
+                ActionInput::CursorCoordinate(target) => target,
+                _ => return ExecutionContextResult::Ignore,
+            };
+            let previous = exec_info
+                .timed_inputs()
+                .filter_map(|(time, matched)| match matched {
+                    ActionInput::CursorCoordinate(previous) => Some((time, previous)),
+                    _ => None,
+                })
+                .last();
+            let motion = match previous {
+                Some((time, previous)) => match ((position)(previous), (position)(target)) {
+                    (Some(from), Some(to)) => ActionCursorMotion {
+                        from,
+                        to,
+                        elapsed: exec_info.current_time().saturating_sub(time),
+                    },
+                    _ => return ExecutionContextResult::Abort,
+                },
+                None => return ExecutionContextResult::Abort,
+            };
+            if (predicate)(&motion) {
+                ExecutionContextResult::Used
+            } else {
+                ExecutionContextResult::Abort
+            }
+        }
+    }
+
+    pub(crate) fn make_input_filter_with_focus_coordinate_filter<'f, F>(
+        filter: F,
+    ) -> impl Fn(&ActionInput<C>) -> ExecutionContextResult + MaybeSendSync + 'f
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        move |input: &ActionInput<C>| match input {
+            ActionInput::FocusCoordinate(target) => {
+                if (filter)(target) {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Abort
+                }
+            }
+            _ => ExecutionContextResult::Ignore,
+        }
+    }
+
+    pub(crate) fn make_input_filter_with_key_filter<'f, F>(
+        filter: F,
+        key_down: bool,
+    ) -> impl Fn(&ActionInput<C>) -> ExecutionContextResult + MaybeSendSync + 'f
+    where
+        F: Fn(&C::KeyKind) -> bool + MaybeSendSync + 'f,
+    {
+        move |input: &ActionInput<C>| match input {
+            ActionInput::KeyDown(key) | ActionInput::KeyUp(key) if (filter)(key) => {
+                if matches!(input, ActionInput::KeyDown(_)) == key_down {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Abort
+                }
+            }
+            _ => ExecutionContextResult::Ignore,
+        }
+    }
+
+    pub(crate) fn make_input_filter_with_touch_filter<'f, F>(
+        filter: F,
+        phase: ActionTouchPhase,
+    ) -> impl Fn(&ActionInput<C>) -> ExecutionContextResult + MaybeSendSync + 'f
+    where
+        F: Fn(&ActionTouch) -> bool + MaybeSendSync + 'f,
+    {
+        move |input: &ActionInput<C>| {
+            let (input_phase, touch) = match input {
+                ActionInput::TouchStart(touch) => (ActionTouchPhase::Start, touch),
+                ActionInput::TouchMove(touch) => (ActionTouchPhase::Move, touch),
+                ActionInput::TouchEnd(touch) => (ActionTouchPhase::End, touch),
+                _ => return ExecutionContextResult::Ignore,
+            };
+            if input_phase == phase && (filter)(touch) {
+                ExecutionContextResult::Used
+            } else {
+                ExecutionContextResult::Ignore
+            }
+        }
+    }
+
+    pub(crate) fn check_input_match_input(
         expected_input: &ActionInput<C>,
         input: &ActionInput<C>,
     ) -> ExecutionContextResult {
         match (expected_input, input) {
             (ActionInput::CursorCoordinate(v1), ActionInput::CursorCoordinate(v2)) => {
-                if v1 == v2 {
+                if C::target_matches(v1, v2) {
                     ExecutionContextResult::Used
                 } else {
                     ExecutionContextResult::Abort
@@ -252,7 +1158,7 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
             }
             (ActionInput::CursorCoordinate(_v1), _) => ExecutionContextResult::Ignore,
             (ActionInput::FocusCoordinate(v1), ActionInput::FocusCoordinate(v2)) => {
-                if v1 == v2 {
+                if C::target_matches(v1, v2) {
                     ExecutionContextResult::Used
                 } else {
                     ExecutionContextResult::Abort
@@ -289,45 +1195,220 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
                 }
             }
             (ActionInput::KeyUp(_v1), _) => ExecutionContextResult::Ignore,
+            (ActionInput::KeyRepeat(v1), ActionInput::KeyRepeat(v2)) => {
+                if v1 == v2 {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Ignore
+                }
+            }
+            (ActionInput::KeyRepeat(v1), ActionInput::KeyUp(v2)) => {
+                if v1 == v2 {
+                    ExecutionContextResult::Abort
+                } else {
+                    ExecutionContextResult::Ignore
+                }
+            }
+            (ActionInput::KeyRepeat(_v1), _) => ExecutionContextResult::Ignore,
+            (ActionInput::Axis(a1, v1), ActionInput::Axis(a2, v2)) => {
+                if a1 == a2 && v1 == v2 {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Ignore
+                }
+            }
+            (ActionInput::Axis(..), _) => ExecutionContextResult::Ignore,
+            (ActionInput::Stylus(t1, s1), ActionInput::Stylus(t2, s2)) => {
+                if t1 == t2 && s1 == s2 {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Ignore
+                }
+            }
+            (ActionInput::Stylus(..), _) => ExecutionContextResult::Ignore,
+            (ActionInput::Character(c1), ActionInput::Character(c2)) => {
+                if c1 == c2 {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Ignore
+                }
+            }
+            (ActionInput::Character(_), _) => ExecutionContextResult::Ignore,
+            (ActionInput::TouchStart(t1), ActionInput::TouchStart(t2))
+            | (ActionInput::TouchMove(t1), ActionInput::TouchMove(t2))
+            | (ActionInput::TouchEnd(t1), ActionInput::TouchEnd(t2)) => {
+                if t1 == t2 {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Ignore
+                }
+            }
+            (ActionInput::TouchStart(_), _)
+            | (ActionInput::TouchMove(_), _)
+            | (ActionInput::TouchEnd(_), _) => ExecutionContextResult::Ignore,
         }
     }
 
-    fn check_input_match_condition(
+    pub(crate) fn check_input_match_condition(
         condition: &ActionCondition<C>,
         input: &ActionInput<C>,
     ) -> ExecutionContextResult {
+        match Self::condition_value_after_input(condition, input) {
+            Some(false) => ExecutionContextResult::Abort,
+            _ => ExecutionContextResult::Ignore,
+        }
+    }
+
+    // whether the input alone decides that the condition holds or not afterwards.
+    fn condition_value_after_input(
+        condition: &ActionCondition<C>,
+        input: &ActionInput<C>,
+    ) -> Option<bool> {
         match (condition, input) {
-            (ActionCondition::KeyPressed(b_k, false), ActionInput::KeyDown(k)) => {
-                if b_k == k {
-                    ExecutionContextResult::Abort
-                } else {
-                    ExecutionContextResult::Ignore
+            (ActionCondition::KeyPressed(b_k, s), ActionInput::KeyDown(k)) if b_k == k => Some(*s),
+            (ActionCondition::KeyPressed(b_k, s), ActionInput::KeyUp(k)) if b_k == k => Some(!*s),
+            (ActionCondition::ModifierActive(b_k), ActionInput::KeyDown(k)) if b_k == k => {
+                Some(true)
+            }
+            (ActionCondition::FocusIn(filter), ActionInput::FocusCoordinate(target))
+            | (ActionCondition::CursorOver(filter), ActionInput::CursorCoordinate(target)) => {
+                Some(filter.matches(target))
+            }
+            (ActionCondition::AxisBeyond(b_a, t), ActionInput::Axis(a, v)) if b_a == a => {
+                Some(is_axis_beyond(*v, *t))
+            }
+            (ActionCondition::Not(inner), _) => {
+                Self::condition_value_after_input(inner, input).map(|value| !value)
+            }
+            (ActionCondition::All(conditions), _) => {
+                let mut value = Some(true);
+                for condition in conditions {
+                    match Self::condition_value_after_input(condition, input) {
+                        Some(false) => return Some(false),
+                        Some(true) => {}
+                        None => value = None,
+                    }
                 }
+                value
             }
-            (ActionCondition::KeyPressed(b_k, true), ActionInput::KeyUp(k)) => {
-                if b_k == k {
-                    ExecutionContextResult::Abort
-                } else {
-                    ExecutionContextResult::Ignore
+            (ActionCondition::Any(conditions), _) => {
+                let mut value = Some(false);
+                for condition in conditions {
+                    match Self::condition_value_after_input(condition, input) {
+                        Some(true) => return Some(true),
+                        Some(false) => {}
+                        None => value = None,
+                    }
                 }
+                value
             }
-            _ => ExecutionContextResult::Ignore,
+            _ => None,
         }
     }
 
     fn check_interactive_item_match_input(
+        item_idx: ActionRecipeItemIdx,
         item: &ActionRecipeItem<C>,
         input: &ActionInput<C>,
-    ) -> ExecutionContextResult {
+        stored_contracts: &ActionExecutionContractStore<C>,
+        env: &ActionEnvironmentTrackingState<C>,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
         debug_assert!(item.is_interactive());
         match item {
             ActionRecipeItem::StartInput(expected_input) => {
-                Self::check_input_match_input(expected_input, input)
+                Ok(Self::check_input_match_input(expected_input, input))
             }
-            ActionRecipeItem::StartFilteredInput(filter) => (filter)(&input),
-            _ => {
-                unreachable!();
+            ActionRecipeItem::StartFilteredInput(filter) => Ok((filter)(input)),
+            ActionRecipeItem::StartCursorTransition(filter, entered) => Ok(match input {
+                ActionInput::CursorCoordinate(target)
+                    if filter.matches(target) == *entered
+                        && env
+                            .previous_cursor_target()
+                            .is_some_and(|previous| filter.matches(previous))
+                            != *entered =>
+                {
+                    ExecutionContextResult::Used
+                }
+                _ => ExecutionContextResult::Ignore,
+            }),
+            ActionRecipeItem::StartAxisInput(axis, threshold, beyond) => Ok(match input {
+                ActionInput::Axis(a, v)
+                    if a == axis && is_axis_beyond(*v, *threshold) == *beyond =>
+                {
+                    ExecutionContextResult::Used
+                }
+                _ => ExecutionContextResult::Ignore,
+            }),
+            ActionRecipeItem::StartCharacterInput(translate) => {
+                Ok(if Self::input_character(translate, input).is_some() {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Ignore
+                })
+            }
+            ActionRecipeItem::StartDwell(duration) => Ok(match input {
+                ActionInput::CursorCoordinate(_)
+                    if stored_contracts
+                        .last_matched_at()
+                        .is_some_and(|time| time + *duration <= stored_contracts.current_time) =>
+                {
+                    ExecutionContextResult::Used
+                }
+                _ => ExecutionContextResult::Ignore,
+            }),
+            ActionRecipeItem::StartHeldKeyUp(key, min_hold, on_early_release) => Ok(match input {
+                ActionInput::KeyUp(released) if released == key => {
+                    match env.released_key_hold(released) {
+                        Some(held) if held < *min_hold => (*on_early_release).into(),
+                        _ => ExecutionContextResult::Used,
+                    }
+                }
+                ActionInput::KeyDown(pressed) if pressed == key => ExecutionContextResult::Abort,
+                _ => ExecutionContextResult::Ignore,
+            }),
+            ActionRecipeItem::StartIdle(duration) => Ok(
+                if stored_contracts
+                    .last_matched_at()
+                    .is_some_and(|time| time + *duration <= stored_contracts.current_time)
+                {
+                    ExecutionContextResult::Used
+                } else {
+                    ExecutionContextResult::Ignore
+                },
+            ),
+            ActionRecipeItem::StartExecutionFilteredInput(filter) => Ok((filter)(
+                ActionRecipeExecutionInfo::new(stored_contracts),
+                input,
+            )),
+            _ => Err(ConcertoError::UnexpectedItem(item_idx)),
+        }
+    }
+
+    fn matched_input_contract(
+        item: &ActionRecipeItem<C>,
+        input: &ActionInput<C>,
+    ) -> Shared<ActionInput<C>> {
+        match item {
+            ActionRecipeItem::StartInput(expected_input) => expected_input.clone(),
+            ActionRecipeItem::StartCharacterInput(translate) => {
+                match Self::input_character(translate, input) {
+                    Some(c) => Shared::new(ActionInput::Character(c)),
+                    None => Shared::new(input.clone()),
+                }
             }
+            _ => Shared::new(input.clone()),
+        }
+    }
+
+    fn input_character(
+        translate: &Option<ActionKeyCharacterMap<C>>,
+        input: &ActionInput<C>,
+    ) -> Option<char> {
+        match (translate, input) {
+            (None, ActionInput::Character(c)) => Some(*c),
+            (Some(translate), ActionInput::KeyDown(key))
+            | (Some(translate), ActionInput::KeyRepeat(key)) => (translate)(key),
+            _ => None,
         }
     }
 
@@ -336,13 +1417,28 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
         env: &ActionEnvironmentTrackingState<C>,
     ) -> bool {
         match condition_item {
-            ActionCondition::KeyPressed(k, s) => {
-                if env.is_key_pressed(k) != *s {
-                    return false;
-                }
+            ActionCondition::KeyPressed(k, s) => env.is_key_pressed(k) == *s,
+            ActionCondition::ModifierActive(k) => env.is_key_pressed(k) || env.is_key_armed(k),
+            ActionCondition::FocusIn(filter) => {
+                env.focused_target().is_some_and(|t| filter.matches(t))
+            }
+            ActionCondition::CursorOver(filter) => {
+                env.cursor_target().is_some_and(|t| filter.matches(t))
             }
+            ActionCondition::AxisBeyond(a, t) => {
+                env.axis_value(a).is_some_and(|v| is_axis_beyond(v, *t))
+            }
+            ActionCondition::Custom(predicate) => {
+                predicate.matches(&ActionEnvironmentView::new(env))
+            }
+            ActionCondition::Not(inner) => !Self::check_condition_match_environment(inner, env),
+            ActionCondition::All(conditions) => conditions
+                .iter()
+                .all(|condition| Self::check_condition_match_environment(condition, env)),
+            ActionCondition::Any(conditions) => conditions
+                .iter()
+                .any(|condition| Self::check_condition_match_environment(condition, env)),
         }
-        true
     }
 
     fn check_condition_item_match_environment(
@@ -350,30 +1446,42 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
         recipe_item: &ActionRecipeItem<C>,
         stored_contracts: &mut ActionExecutionContractStore<C>,
         env: &ActionEnvironmentTrackingState<C>,
-    ) -> ExecutionContextResult {
+    ) -> Result<ExecutionContextResult, ConcertoError> {
         debug_assert!(recipe_item.is_condition());
         match recipe_item {
             ActionRecipeItem::StartCondition(condition) => {
                 if !Self::check_condition_match_environment(condition, env) {
-                    return ExecutionContextResult::Abort;
+                    return Ok(ExecutionContextResult::Abort);
                 }
-                stored_contracts.add_condition(recipe_item_idx, condition.clone());
+                stored_contracts.add_condition(recipe_item_idx);
             }
+            ActionRecipeItem::CheckExecution(predicate) => {
+                if !(predicate)(ActionRecipeExecutionInfo::new(stored_contracts)) {
+                    return Ok(ExecutionContextResult::Abort);
+                }
+            }
+            ActionRecipeItem::CaptureCursorTarget => match env.cursor_target() {
+                Some(target) => stored_contracts.add_captured_input(
+                    recipe_item_idx,
+                    Shared::new(ActionInput::CursorCoordinate(target.clone())),
+                ),
+                None => return Ok(ExecutionContextResult::Abort),
+            },
             _ => {
-                unreachable!();
+                return Err(ConcertoError::UnexpectedItem(recipe_item_idx));
             }
         }
-        ExecutionContextResult::Used
+        Ok(ExecutionContextResult::Used)
     }
 
     fn put_noninteractive_item_into_effect(
         recipe_id: usize,
         recipe_item_idx: ActionRecipeItemIdx,
         recipe_item: &ActionRecipeItem<C>,
-        command_list: &mut Vec<C::Command>,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
         stored_contracts: &mut ActionExecutionContractStore<C>,
-    ) {
+    ) -> Result<(), ConcertoError> {
         debug_assert!(recipe_item.is_noninteractive());
         match recipe_item {
             ActionRecipeItem::EliminateItem(item_idx) => {
@@ -384,18 +1492,43 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
                     nest_recipe_command_list,
                 );
             }
+            ActionRecipeItem::Checkpoint => {
+                stored_contracts.commit_effects(recipe_id, command_list);
+            }
             ActionRecipeItem::StartEffect(effect) => {
                 let cmd = effect.effect_start().clone();
-                command_list.push(cmd);
-                stored_contracts.add_effect(recipe_item_idx, effect.effect_end().clone());
+                command_list.push((stored_contracts.source(recipe_id), cmd.clone()));
+                stored_contracts.add_effect(
+                    recipe_item_idx,
+                    cmd,
+                    effect.effect_end().clone(),
+                    effect.effect_abort_end().clone(),
+                );
             }
+            ActionRecipeItem::StartEffectOf(_) | ActionRecipeItem::DoCommandOf(_)
+                if stored_contracts.dry_run => {}
             ActionRecipeItem::StartEffectOf(effect_gen) => {
                 let (effect_start, effect_end) = {
                     let exec_info = ActionRecipeExecutionInfo::new(stored_contracts);
-                    (effect_gen)(exec_info)
+                    effect_gen.with(|effect_gen| (effect_gen)(exec_info))
+                };
+                let effect_start = Shared::new(effect_start);
+                command_list.push((stored_contracts.source(recipe_id), effect_start.clone()));
+                let effect_end = Shared::new(effect_end);
+                stored_contracts.add_effect(
+                    recipe_item_idx,
+                    effect_start,
+                    effect_end.clone(),
+                    effect_end,
+                );
+            }
+            ActionRecipeItem::StartUpdatesOf(_, rate) => {
+                let state = ActionUpdateState {
+                    rate: *rate,
+                    issued_at: None,
+                    pending: false,
                 };
-                command_list.push(effect_start);
-                stored_contracts.add_effect(recipe_item_idx, effect_end);
+                stored_contracts.insert(recipe_item_idx, ActionExecutionContract::Updates(state));
             }
             ActionRecipeItem::StartNestRecipe(idx) => {
                 nest_recipe_command_list.push(ActionNestRecipeCommand::Enable(recipe_id, *idx));
@@ -407,157 +1540,373 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
             }
             ActionRecipeItem::DoCommand(cmd) => {
                 let cmd = cmd.command().clone();
-                command_list.push(cmd);
+                command_list.push((stored_contracts.source(recipe_id), cmd));
             }
             ActionRecipeItem::DoCommandOf(cmd_gen) => {
                 let exec_info = ActionRecipeExecutionInfo::new(stored_contracts);
-                let cmd = (cmd_gen)(exec_info);
-                command_list.push(cmd);
+                let cmd = cmd_gen.with(|cmd_gen| (cmd_gen)(exec_info));
+                command_list.push((stored_contracts.source(recipe_id), Shared::new(cmd)));
             }
-            _ => unreachable!(),
+            _ => return Err(ConcertoError::UnexpectedItem(recipe_item_idx)),
         }
+        Ok(())
     }
 
     fn prepare_new_frame_for_compound_item(
         recipe_item: &ActionRecipeItem<C>,
         recipe_item_idx: ActionRecipeItemIdx,
-    ) -> (ActionRecipeItemIdx, ActionExecutionFrame) {
+    ) -> Result<(ActionRecipeItemIdx, ActionExecutionFrame), ConcertoError> {
         debug_assert!(recipe_item.is_compound());
         let frame = match recipe_item {
-            ActionRecipeItem::Sequential(_) => ActionExecutionFrame::Sequential(None),
-            ActionRecipeItem::Unordered(r) => ActionExecutionFrame::Unordered({
-                let mut bitset = FixedBitSet::with_capacity(r.len());
-                bitset.set_range(.., true);
-                bitset
-            }),
+            ActionRecipeItem::Sequential(_) | ActionRecipeItem::Optional(_) => {
+                ActionExecutionFrame::Sequential(None)
+            }
+            ActionRecipeItem::Unordered(r, required) => {
+                ActionExecutionFrame::unordered(r.len(), *required)
+            }
             ActionRecipeItem::Choice(_) => ActionExecutionFrame::Choice(None),
-            _ => panic!("Primitive action item occured where only composite action item can occur"),
+            ActionRecipeItem::Repeated(..) => ActionExecutionFrame::Repeated(None, 0),
+            _ => return Err(ConcertoError::UnexpectedItem(recipe_item_idx)),
+        };
+
+        Ok((recipe_item_idx, frame))
+    }
+
+    fn current_repetition(&self, outer: usize) -> Option<(ActionRecipeItemIdx, usize)> {
+        match &self.compiled {
+            Some(cursor) => cursor.iterations.iter().rev().nth(outer).cloned(),
+            None => self
+                .backtrace
+                .iter()
+                .rev()
+                .filter_map(|(item_idx, frame)| match frame {
+                    ActionExecutionFrame::Repeated(_, iteration) => Some((*item_idx, *iteration)),
+                    _ => None,
+                })
+                .nth(outer),
+        }
+    }
+
+    // The input that ends the repeated item on top of the frame stack and its position
+    // in the parent, if the repetition is between two iterations and allowed to end.
+    fn repetition_terminator(
+        &self,
+        recipe_items: &ActionRecipeItemStore<C>,
+    ) -> Result<Option<(ActionRecipeItemIdx, usize)>, ConcertoError> {
+        let (repeated_idx, iteration) = match self.backtrace.last() {
+            Some((item_idx, ActionExecutionFrame::Repeated(None, iteration))) => {
+                (*item_idx, *iteration)
+            }
+            _ => return Ok(None),
+        };
+        if let ActionRecipeItem::Repeated(_, ActionRecipeRepetition::OneOrMore) =
+            recipe_items.get(repeated_idx)?
+        {
+            if iteration == 0 {
+                return Ok(None);
+            }
+        }
+        let (parent_idx, parent_frame) = self
+            .backtrace
+            .iter()
+            .rev()
+            .nth(1)
+            .ok_or(ConcertoError::UnexpectedItem(repeated_idx))?;
+        let terminator_pos = match parent_frame {
+            ActionExecutionFrame::Sequential(Some(pos))
+            | ActionExecutionFrame::Repeated(Some(pos), _) => pos + 1,
+            _ => return Err(ConcertoError::UnexpectedItem(repeated_idx)),
         };
+        let terminator_idx = *recipe_items
+            .get(*parent_idx)?
+            .compound_sequence(*parent_idx)?
+            .get(terminator_pos)
+            .ok_or(ConcertoError::RepeatedWithoutFollowingInput(repeated_idx))?;
+        Ok(Some((terminator_idx, terminator_pos)))
+    }
 
-        (recipe_item_idx, frame)
+    // Ends the contracts kept by the body of a repeated item once an iteration is over.
+    fn eliminate_iteration_contracts(
+        recipe_idx: usize,
+        recipe_items: &ActionRecipeItemStore<C>,
+        item_idx: ActionRecipeItemIdx,
+        stored_contracts: &mut ActionExecutionContractStore<C>,
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+    ) -> Result<(), ConcertoError> {
+        // backwards, so that effects end in the reverse order they started.
+        for &child_idx in recipe_items
+            .get(item_idx)?
+            .compound_sequence(item_idx)?
+            .iter()
+            .rev()
+        {
+            if recipe_items.get(child_idx)?.is_compound() {
+                Self::eliminate_iteration_contracts(
+                    recipe_idx,
+                    recipe_items,
+                    child_idx,
+                    stored_contracts,
+                    command_list,
+                    nest_recipe_command_list,
+                )?;
+            }
+            stored_contracts.eliminate(
+                recipe_idx,
+                &child_idx,
+                command_list,
+                nest_recipe_command_list,
+            );
+        }
+        Ok(())
     }
 
     fn process_input_1(
         &mut self,
         input: &ActionInput<C>,
         recipe_items: &ActionRecipeItemStore<C>,
-        _recipe: &ActionRecipe<C>,
-    ) -> ExecutionContextResult {
-        if Self::stored_contracts_conflict(input, &mut self.stored_contracts) {
-            return ExecutionContextResult::Abort;
+        recipe: &ActionRecipe<C>,
+        env: &ActionEnvironmentTrackingState<C>,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        if Self::stored_contracts_conflict(input, &self.stored_contracts, recipe_items, env)? {
+            return Ok(ExecutionContextResult::Abort);
+        }
+        if self.compiled.is_some() {
+            let compiled = Self::compiled_recipe(recipe)?;
+            return self.process_compiled_input_1(input, recipe_items, recipe, compiled, env);
         }
 
+        let repetition = self.current_repetition(0);
+        let outer_repetition = self.current_repetition(1);
+        let terminator = self.repetition_terminator(recipe_items)?;
         let last_frame_depth = self.backtrace.len() - 1;
         let last_frame = self
             .backtrace
             .get_mut(last_frame_depth)
             .expect("Broken execution context data!");
 
-        let seq = recipe_items.get(last_frame.0);
+        let seq = recipe_items.get(last_frame.0)?;
         debug_assert!(seq.is_compound());
-        let seq_items = seq.compound_sequence();
-        match &mut last_frame.1 {
-            ActionExecutionFrame::Sequential(state_pos) => {
-                let next = state_pos.map(|x| x + 1).unwrap_or(0);
-                debug_assert!(next < seq_items.len());
-                let seq_next_item_idx = seq_items[next];
-                let seq_next_item = recipe_items.get(seq_next_item_idx);
-                debug_assert!(seq_next_item.is_interactive());
-                match Self::check_interactive_item_match_input(seq_next_item, input) {
-                    ExecutionContextResult::Done => {
-                        unreachable!();
-                    }
-                    ExecutionContextResult::Used => {
-                        if self.recipe_idx == 0 {
-                            debug!(target: "concerto", "process_input_1: recipe_id = {}, seq = {:?}, next = {}, used", self.recipe_idx, (last_frame.0), next);
+        let seq_items = seq.compound_sequence(last_frame.0)?;
+        let mut new_frame = None;
+        let mut leave_repetition = None;
+        let result = match &mut last_frame.1 {
+            ActionExecutionFrame::Sequential(state_pos)
+            | ActionExecutionFrame::Repeated(state_pos, _) => {
+                let mut next = state_pos.map(|x| x + 1).unwrap_or(0);
+                let mut seq_next_item_idx = *seq_items
+                    .get(next)
+                    .ok_or(ConcertoError::UnexpectedItem(last_frame.0))?;
+                let mut seq_next_item = recipe_items.get(seq_next_item_idx)?;
+                if let ActionRecipeItem::Optional(optional_seq) = seq_next_item {
+                    let optional_idx = seq_next_item_idx;
+                    let first_item_idx = *optional_seq
+                        .first()
+                        .ok_or(ConcertoError::EmptySequence(optional_idx))?;
+                    let first_item = recipe_items.get(first_item_idx)?;
+                    match Self::check_interactive_item_match_input(
+                        first_item_idx,
+                        first_item,
+                        input,
+                        &self.stored_contracts,
+                        env,
+                    )? {
+                        ExecutionContextResult::Used => {
+                            self.stored_contracts.add_input(
+                                first_item_idx,
+                                Self::matched_input_contract(first_item, input),
+                                None,
+                                repetition,
+                            );
+                            *state_pos = Some(next);
+                            new_frame =
+                                Some((optional_idx, ActionExecutionFrame::Sequential(Some(0))));
+                        }
+                        ExecutionContextResult::Abort => return Ok(ExecutionContextResult::Abort),
+                        _ => {
+                            // otherwise the input may be the one that skips the optional item.
+                            next += 1;
+                            seq_next_item_idx = *seq_items.get(next).ok_or(
+                                ConcertoError::OptionalWithoutFollowingInput(optional_idx),
+                            )?;
+                            seq_next_item = recipe_items.get(seq_next_item_idx)?;
                         }
-                        self.stored_contracts
-                            .add_input(seq_next_item_idx, input.clone());
-                        *state_pos = Some(next);
-                        return ExecutionContextResult::Used;
-                    }
-                    ExecutionContextResult::Ignore => {
-                        return ExecutionContextResult::Ignore;
                     }
-                    ExecutionContextResult::Abort => {
-                        if next != 0 {
-                            debug!(target: "concerto", "process_input_1: recipe_id = {}, seq = {:?}, next = {}, aborted", self.recipe_idx, (last_frame.0), next);
+                }
+                if new_frame.is_some() {
+                    ExecutionContextResult::Used
+                } else {
+                    debug_assert!(seq_next_item.is_interactive());
+                    match Self::check_interactive_item_match_input(
+                        seq_next_item_idx,
+                        seq_next_item,
+                        input,
+                        &self.stored_contracts,
+                        env,
+                    )? {
+                        ExecutionContextResult::Done => {
+                            unreachable!();
+                        }
+                        ExecutionContextResult::Used => {
+                            if self.recipe_idx == 0 {
+                                debug!(target: "concerto", "process_input_1: recipe = {}, seq = {:?}, next = {}, used", recipe.label(self.recipe_idx), (last_frame.0), next);
+                            }
+                            self.stored_contracts.add_input(
+                                seq_next_item_idx,
+                                Self::matched_input_contract(seq_next_item, input),
+                                None,
+                                repetition,
+                            );
+                            *state_pos = Some(next);
+                            ExecutionContextResult::Used
+                        }
+                        ExecutionContextResult::Ignore => match terminator {
+                            // between iterations the input may be the one that ends them.
+                            Some((terminator_idx, terminator_pos)) => {
+                                let terminator_item = recipe_items.get(terminator_idx)?;
+                                let result = Self::check_interactive_item_match_input(
+                                    terminator_idx,
+                                    terminator_item,
+                                    input,
+                                    &self.stored_contracts,
+                                    env,
+                                )?;
+                                if let ExecutionContextResult::Used = result {
+                                    self.stored_contracts.add_input(
+                                        terminator_idx,
+                                        Self::matched_input_contract(terminator_item, input),
+                                        None,
+                                        outer_repetition,
+                                    );
+                                    leave_repetition = Some(terminator_pos);
+                                }
+                                result
+                            }
+                            None => ExecutionContextResult::Ignore,
+                        },
+                        ExecutionContextResult::Abort => {
+                            if next != 0 {
+                                debug!(target: "concerto", "process_input_1: recipe = {}, seq = {:?}, next = {}, aborted", recipe.label(self.recipe_idx), (last_frame.0), next);
+                            }
+                            ExecutionContextResult::Abort
                         }
-                        return ExecutionContextResult::Abort;
                     }
                 }
             }
-            ActionExecutionFrame::Unordered(state_set) => {
+            ActionExecutionFrame::Unordered(state_set, _) => {
                 debug_assert!(state_set.len() == seq_items.len());
                 let mut update_item = None;
                 'unordered_loop: for seq_idx in state_set.ones() {
-                    let seq_next_item_idx = seq_items[seq_idx];
-                    let seq_next_item = recipe_items.get(seq_next_item_idx);
+                    let seq_next_item_idx = *seq_items
+                        .get(seq_idx)
+                        .ok_or(ConcertoError::UnexpectedItem(last_frame.0))?;
+                    let seq_next_item = recipe_items.get(seq_next_item_idx)?;
                     debug_assert!(seq_next_item.is_interactive());
-                    match Self::check_interactive_item_match_input(seq_next_item, input) {
+                    match Self::check_interactive_item_match_input(
+                        seq_next_item_idx,
+                        seq_next_item,
+                        input,
+                        &self.stored_contracts,
+                        env,
+                    )? {
                         ExecutionContextResult::Done => {
                             unreachable!();
                         }
                         ExecutionContextResult::Used => {
-                            self.stored_contracts
-                                .add_input(seq_next_item_idx, input.clone());
+                            self.stored_contracts.add_input(
+                                seq_next_item_idx,
+                                Self::matched_input_contract(seq_next_item, input),
+                                None,
+                                repetition,
+                            );
                             update_item = Some(seq_idx);
                             break 'unordered_loop;
                         }
                         ExecutionContextResult::Ignore => {}
                         ExecutionContextResult::Abort => {
-                            return ExecutionContextResult::Abort;
+                            return Ok(ExecutionContextResult::Abort);
                         }
                     }
                 }
                 if let Some(update_item) = update_item {
-                    debug!(target: "concerto", "process_input_1: recipe_id = {}, seq = {:?}, unordered = {}, used", self.recipe_idx, (last_frame.0), update_item);
+                    debug!(target: "concerto", "process_input_1: recipe = {}, seq = {:?}, unordered = {}, used", recipe.label(self.recipe_idx), (last_frame.0), update_item);
                     state_set.set(update_item, false);
-                    return ExecutionContextResult::Used;
+                    ExecutionContextResult::Used
                 } else {
-                    return ExecutionContextResult::Ignore;
+                    ExecutionContextResult::Ignore
                 }
             }
             ActionExecutionFrame::Choice(state_choice) => {
                 debug_assert!(state_choice.is_none());
                 let mut update_item = None;
-                'choice_loop: for seq_idx in 0..(seq_items.len()) {
-                    let seq_next_item_idx = seq_items[seq_idx];
-                    let seq_next_item = recipe_items.get(seq_next_item_idx);
+                'choice_loop: for (seq_idx, &seq_next_item_idx) in seq_items.iter().enumerate() {
+                    let seq_next_item = recipe_items.get(seq_next_item_idx)?;
                     debug_assert!(seq_next_item.is_interactive());
-                    match Self::check_interactive_item_match_input(seq_next_item, input) {
+                    match Self::check_interactive_item_match_input(
+                        seq_next_item_idx,
+                        seq_next_item,
+                        input,
+                        &self.stored_contracts,
+                        env,
+                    )? {
                         ExecutionContextResult::Done => {
                             unreachable!();
                         }
                         ExecutionContextResult::Used => {
-                            self.stored_contracts
-                                .add_input(seq_next_item_idx, input.clone());
+                            self.stored_contracts.add_input(
+                                seq_next_item_idx,
+                                Self::matched_input_contract(seq_next_item, input),
+                                Some((last_frame.0, seq_idx)),
+                                repetition,
+                            );
                             update_item = Some(seq_idx);
                             break 'choice_loop;
                         }
                         ExecutionContextResult::Ignore => {}
                         ExecutionContextResult::Abort => {
-                            return ExecutionContextResult::Abort;
+                            return Ok(ExecutionContextResult::Abort);
                         }
                     }
                 }
                 if let Some(update_item) = update_item {
                     *state_choice = Some(update_item);
-                    return ExecutionContextResult::Used;
+                    ExecutionContextResult::Used
                 } else {
-                    return ExecutionContextResult::Ignore;
+                    ExecutionContextResult::Ignore
                 }
             }
+        };
+        if let Some(new_frame) = new_frame {
+            self.backtrace.push(new_frame);
+        }
+        if let Some(terminator_pos) = leave_repetition {
+            self.backtrace.pop();
+            if let Some((_, parent_frame)) = self.backtrace.last_mut() {
+                parent_frame.mark_used(terminator_pos);
+            }
         }
+        Ok(result)
     }
 
     fn process_input_2(
         &mut self,
         recipe_items: &ActionRecipeItemStore<C>,
-        command_list: &mut Vec<C::Command>,
+        recipe: &ActionRecipe<C>,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
         env: &ActionEnvironmentTrackingState<C>,
-    ) -> ExecutionContextResult {
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        if self.compiled.is_some() {
+            let compiled = Self::compiled_recipe(recipe)?;
+            return self.process_compiled_input_2(
+                recipe_items,
+                recipe,
+                compiled,
+                command_list,
+                nest_recipe_command_list,
+                env,
+            );
+        }
         '_frame_loop: while !self.backtrace.is_empty() {
             let last_frame_depth = self.backtrace.len() - 1;
             let mut new_frame = None;
@@ -567,34 +1916,34 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
                     .get_mut(last_frame_depth)
                     .expect("Broken execution context data!");
 
-                let seq = recipe_items.get(last_frame.0);
+                let seq = recipe_items.get(last_frame.0)?;
                 debug_assert!(seq.is_compound());
-                let seq_items = seq.compound_sequence();
+                let seq_items = seq.compound_sequence(last_frame.0)?;
                 match &mut last_frame.1 {
-                    ActionExecutionFrame::Sequential(state_pos) => {
+                    ActionExecutionFrame::Sequential(state_pos)
+                    | ActionExecutionFrame::Repeated(state_pos, _) => {
                         let mut next = state_pos.map(|x| x + 1).unwrap_or(0);
                         'sequential_loop: while next < seq_items.len() {
                             let seq_next_item_idx = seq_items[next];
-                            let seq_next_item = recipe_items.get(seq_next_item_idx);
+                            let seq_next_item = recipe_items.get(seq_next_item_idx)?;
                             if seq_next_item.is_interactive() {
-                                debug!(target: "concerto", "process_input_2: recipe_id = {}, seq = {:?}, next = {}, stopped here", self.recipe_idx, last_frame.0, next);
-                                return ExecutionContextResult::Used;
+                                debug!(target: "concerto", "process_input_2: recipe = {}, seq = {:?}, next = {}, stopped here", recipe.label(self.recipe_idx), last_frame.0, next);
+                                return Ok(ExecutionContextResult::Used);
                             } else if seq_next_item.is_condition() {
-                                match Self::check_condition_item_match_environment(
-                                    seq_next_item_idx,
-                                    seq_next_item,
-                                    &mut self.stored_contracts,
-                                    &env,
-                                ) {
-                                    ExecutionContextResult::Abort => {
-                                        return ExecutionContextResult::Abort
-                                    }
-                                    _ => {}
+                                if let ExecutionContextResult::Abort =
+                                    Self::check_condition_item_match_environment(
+                                        seq_next_item_idx,
+                                        seq_next_item,
+                                        &mut self.stored_contracts,
+                                        env,
+                                    )?
+                                {
+                                    return Ok(ExecutionContextResult::Abort);
                                 }
                                 *state_pos = Some(next);
                                 next += 1;
                             } else if seq_next_item.is_noninteractive() {
-                                debug!(target: "concerto", "process_input_2: recipe_id = {}, seq = {:?}, next = {}, non-interactive", self.recipe_idx, last_frame.0, next);
+                                debug!(target: "concerto", "process_input_2: recipe = {}, seq = {:?}, next = {}, non-interactive", recipe.label(self.recipe_idx), last_frame.0, next);
                                 Self::put_noninteractive_item_into_effect(
                                     self.recipe_idx,
                                     seq_next_item_idx,
@@ -602,129 +1951,746 @@ impl<C: ActionConfiguration> ActionExecutionCtx<C> {
                                     command_list,
                                     nest_recipe_command_list,
                                     &mut self.stored_contracts,
-                                );
+                                )?;
                                 *state_pos = Some(next);
                                 next += 1;
+                            } else if let ActionRecipeItem::Optional(_) = seq_next_item {
+                                debug!(target: "concerto", "process_input_2: recipe = {}, seq = {:?}, next = {}, optional, stopped here", recipe.label(self.recipe_idx), last_frame.0, next);
+                                return Ok(ExecutionContextResult::Used);
                             } else {
                                 debug_assert!(seq_next_item.is_compound());
-                                debug!(target: "concerto", "process_input_2: recipe_id = {}, seq = {:?}, next = {}, compound", self.recipe_idx, last_frame.0, next);
+                                debug!(target: "concerto", "process_input_2: recipe = {}, seq = {:?}, next = {}, compound", recipe.label(self.recipe_idx), last_frame.0, next);
                                 new_frame = Some(Self::prepare_new_frame_for_compound_item(
                                     seq_next_item,
                                     seq_next_item_idx,
-                                ));
+                                )?);
                                 *state_pos = Some(next);
                                 break 'sequential_loop;
                             }
                         }
                     }
-                    ActionExecutionFrame::Unordered(state_set) => {
+                    ActionExecutionFrame::Unordered(state_set, required) => {
                         debug_assert!(state_set.len() == seq_items.len());
 
-                        if let Some(first_unused) = state_set.ones().next() {
-                            debug!(target: "concerto", "process_input_2: recipe_id = {}, seq = {:?}, unordered, first unmatch({}) stopped here", self.recipe_idx, last_frame.0, first_unused);
+                        let matched = state_set.len() - state_set.count_ones(..);
+                        if let Some(first_unused) =
+                            state_set.ones().next().filter(|_| matched < *required)
+                        {
+                            debug!(target: "concerto", "process_input_2: recipe = {}, seq = {:?}, unordered, first unmatch({}) stopped here", recipe.label(self.recipe_idx), last_frame.0, first_unused);
                             debug_assert!(state_set
                                 .ones()
                                 .map(|seq_idx| recipe_items.get(seq_items[seq_idx]))
-                                .all(|x| x.is_interactive()));
-                            return ExecutionContextResult::Used;
+                                .all(|x| x.map(|x| x.is_interactive()).unwrap_or(false)));
+                            return Ok(ExecutionContextResult::Used);
                         } else {
-                            debug!(target: "concerto", "process_input_2: recipe_id = {}, seq = {:?}, unordered, finished", self.recipe_idx, last_frame.0);
+                            debug!(target: "concerto", "process_input_2: recipe = {}, seq = {:?}, unordered, finished", recipe.label(self.recipe_idx), last_frame.0);
                         }
                     }
                     ActionExecutionFrame::Choice(state_choice) => {
                         if state_choice.is_none() {
                             debug_assert!((0..(seq_items.len()))
                                 .map(|seq_idx| recipe_items.get(seq_items[seq_idx]))
-                                .all(|x| x.is_interactive()));
-                            return ExecutionContextResult::Used;
+                                .all(|x| x.map(|x| x.is_interactive()).unwrap_or(false)));
+                            return Ok(ExecutionContextResult::Used);
                         }
                     }
                 }
             }
             if let Some(new_frame) = new_frame {
                 self.backtrace.push(new_frame);
+            } else if let Some((
+                repeated_idx,
+                ActionExecutionFrame::Repeated(state_pos, iteration),
+            )) = self.backtrace.last_mut()
+            {
+                // the iteration is over, wait for the next one or the input that ends them.
+                debug!(target: "concerto", "process_input_2: recipe = {}, seq = {:?}, iteration {} finished", recipe.label(self.recipe_idx), repeated_idx, iteration);
+                *state_pos = None;
+                *iteration += 1;
+                Self::eliminate_iteration_contracts(
+                    self.recipe_idx,
+                    recipe_items,
+                    *repeated_idx,
+                    &mut self.stored_contracts,
+                    command_list,
+                    nest_recipe_command_list,
+                )?;
             } else {
                 self.backtrace.pop();
             }
         }
-        ExecutionContextResult::Done
+        Ok(ExecutionContextResult::Done)
+    }
+
+    fn compiled_recipe(recipe: &ActionRecipe<C>) -> Result<&ActionCompiledRecipe, ConcertoError> {
+        recipe
+            .compiled
+            .as_deref()
+            .ok_or(ConcertoError::UnexpectedItem(recipe.root_item))
+    }
+
+    fn process_compiled_input_1(
+        &mut self,
+        input: &ActionInput<C>,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        compiled: &ActionCompiledRecipe,
+        env: &ActionEnvironmentTrackingState<C>,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        let repetition = self.current_repetition(0);
+        let outer_repetition = self.current_repetition(1);
+        let cursor = self
+            .compiled
+            .as_mut()
+            .expect("Broken execution context data!");
+        let state_idx = cursor.state.expect("Broken execution context data!");
+        let state = compiled
+            .states
+            .get(state_idx)
+            .ok_or(ConcertoError::UnexpectedItem(cursor.item))?;
+        // the edge past a repeated item only opens once the repetition is allowed to end.
+        let may_skip = match state.wait {
+            ActionCompiledWait::Optional
+            | ActionCompiledWait::Repeated(ActionRecipeRepetition::ZeroOrMore) => true,
+            ActionCompiledWait::Repeated(ActionRecipeRepetition::OneOrMore) => {
+                repetition.is_some_and(|(_, iteration)| iteration > 0)
+            }
+            _ => false,
+        };
+
+        let mut update_item = None;
+        'edge_loop: for (seq_idx, &seq_next_item_idx) in state.inputs.iter().enumerate() {
+            if !cursor.frame.is_pending(seq_idx) {
+                continue;
+            }
+            let is_skip = seq_idx == 1
+                && matches!(
+                    state.wait,
+                    ActionCompiledWait::Optional | ActionCompiledWait::Repeated(_)
+                );
+            if is_skip && !may_skip {
+                continue;
+            }
+            let seq_next_item = recipe_items.get(seq_next_item_idx)?;
+            match Self::check_interactive_item_match_input(
+                seq_next_item_idx,
+                seq_next_item,
+                input,
+                &self.stored_contracts,
+                env,
+            )? {
+                ExecutionContextResult::Done => {
+                    unreachable!();
+                }
+                ExecutionContextResult::Used => {
+                    let choice = match state.wait {
+                        ActionCompiledWait::Choice => Some((state.container, seq_idx)),
+                        _ => None,
+                    };
+                    let repetition = match state.wait {
+                        ActionCompiledWait::Repeated(_) if is_skip => outer_repetition,
+                        _ => repetition,
+                    };
+                    self.stored_contracts.add_input(
+                        seq_next_item_idx,
+                        Self::matched_input_contract(seq_next_item, input),
+                        choice,
+                        repetition,
+                    );
+                    update_item = Some(seq_idx);
+                    break 'edge_loop;
+                }
+                ExecutionContextResult::Ignore => {}
+                ExecutionContextResult::Abort => {
+                    return Ok(ExecutionContextResult::Abort);
+                }
+            }
+        }
+        if let Some(update_item) = update_item {
+            debug!(target: "concerto", "process_input_1: recipe = {}, state = {}, edge = {}, used", recipe.label(self.recipe_idx), state_idx, update_item);
+            let is_skip = update_item == 1
+                && matches!(
+                    state.wait,
+                    ActionCompiledWait::Optional | ActionCompiledWait::Repeated(_)
+                );
+            if is_skip {
+                // the item was skipped or has ended, continue as if the next state matched.
+                if let ActionCompiledWait::Repeated(_) = state.wait {
+                    cursor.iterations.pop();
+                }
+                let skip_to = state
+                    .skip_to
+                    .ok_or(ConcertoError::UnexpectedItem(state.container))?;
+                let skip_state = compiled
+                    .states
+                    .get(skip_to)
+                    .ok_or(ConcertoError::UnexpectedItem(state.container))?;
+                cursor.state = Some(skip_to);
+                cursor.item = skip_state.container;
+                cursor.frame = ActionExecutionFrame::for_compiled_state(skip_state);
+                cursor.frame.mark_used(0);
+            } else {
+                cursor.frame.mark_used(update_item);
+            }
+            Ok(ExecutionContextResult::Used)
+        } else {
+            Ok(ExecutionContextResult::Ignore)
+        }
+    }
+
+    fn process_compiled_input_2(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        compiled: &ActionCompiledRecipe,
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        env: &ActionEnvironmentTrackingState<C>,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        loop {
+            let cursor = self
+                .compiled
+                .as_mut()
+                .expect("Broken execution context data!");
+            let (items, next_state, repeat) = match cursor.state {
+                None => (&compiled.entry, 0, None),
+                Some(state_idx) => {
+                    if !cursor.frame.is_satisfied() {
+                        debug!(target: "concerto", "process_input_2: recipe = {}, state = {}, stopped here", recipe.label(self.recipe_idx), state_idx);
+                        return Ok(ExecutionContextResult::Used);
+                    }
+                    let state = compiled
+                        .states
+                        .get(state_idx)
+                        .ok_or(ConcertoError::UnexpectedItem(cursor.item))?;
+                    (&state.then, state_idx + 1, state.repeat)
+                }
+            };
+            for &item_idx in items.iter() {
+                let item = recipe_items.get(item_idx)?;
+                if item.is_condition() {
+                    if let ExecutionContextResult::Abort =
+                        Self::check_condition_item_match_environment(
+                            item_idx,
+                            item,
+                            &mut self.stored_contracts,
+                            env,
+                        )?
+                    {
+                        return Ok(ExecutionContextResult::Abort);
+                    }
+                } else {
+                    Self::put_noninteractive_item_into_effect(
+                        self.recipe_idx,
+                        item_idx,
+                        item,
+                        command_list,
+                        nest_recipe_command_list,
+                        &mut self.stored_contracts,
+                    )?;
+                }
+            }
+            if let Some(repeat_state) = repeat {
+                // the iteration is over, wait for the next one or the input that ends them.
+                let state = compiled
+                    .states
+                    .get(repeat_state)
+                    .ok_or(ConcertoError::UnexpectedItem(cursor.item))?;
+                if let Some((_, iteration)) = cursor.iterations.last_mut() {
+                    *iteration += 1;
+                }
+                Self::eliminate_iteration_contracts(
+                    self.recipe_idx,
+                    recipe_items,
+                    state.container,
+                    &mut self.stored_contracts,
+                    command_list,
+                    nest_recipe_command_list,
+                )?;
+                cursor.state = Some(repeat_state);
+                cursor.item = state.container;
+                cursor.frame = ActionExecutionFrame::for_compiled_state(state);
+                continue;
+            }
+            match compiled.states.get(next_state) {
+                Some(state) => {
+                    if let ActionCompiledWait::Repeated(_) = state.wait {
+                        cursor.iterations.push((state.container, 0));
+                    }
+                    cursor.state = Some(next_state);
+                    cursor.item = state.container;
+                    cursor.frame = ActionExecutionFrame::for_compiled_state(state);
+                }
+                None => return Ok(ExecutionContextResult::Done),
+            }
+        }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn process_input(
         &mut self,
         input: &ActionInput<C>,
         recipe_items: &ActionRecipeItemStore<C>,
         recipe: &ActionRecipe<C>,
-        command_list: &mut Vec<C::Command>,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
         env: &ActionEnvironmentTrackingState<C>,
-    ) -> ExecutionContextResult {
-        match self.process_input_1(input, recipe_items, recipe) {
+        now: Duration,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        self.stored_contracts.current_time = now;
+        if !recipe.admits(recipe_items, input)? {
+            return Ok(ExecutionContextResult::Ignore);
+        }
+        match self.process_input_1(input, recipe_items, recipe, env)? {
             ExecutionContextResult::Done => {
                 unreachable!();
             }
-            ExecutionContextResult::Used => {}
+            ExecutionContextResult::Used => {
+                self.issue_updates(recipe_items, command_list, true)?;
+            }
+            ExecutionContextResult::Ignore if recipe.strictness.rejects(input) => {
+                debug!(target: "concerto", "process_input: recipe = {}, unexpected input, aborted", recipe.label(self.recipe_idx));
+                return Ok(ExecutionContextResult::Abort);
+            }
             ExecutionContextResult::Ignore => {
-                return ExecutionContextResult::Ignore;
+                if let Some(window) = recipe.input_buffer {
+                    self.buffered_inputs
+                        .retain(|(time, _)| *time + window >= now);
+                    self.buffered_inputs.push((now, input.clone()));
+                }
+                return Ok(ExecutionContextResult::Ignore);
             }
             ExecutionContextResult::Abort => {
-                return ExecutionContextResult::Abort;
+                return Ok(ExecutionContextResult::Abort);
+            }
+        }
+        match self.process_input_2(
+            recipe_items,
+            recipe,
+            command_list,
+            nest_recipe_command_list,
+            env,
+        )? {
+            ExecutionContextResult::Used if !self.buffered_inputs.is_empty() => self
+                .replay_buffered_inputs(
+                    recipe_items,
+                    recipe,
+                    command_list,
+                    nest_recipe_command_list,
+                    env,
+                ),
+            result => Ok(result),
+        }
+    }
+
+    /// Issues a command from each update generator in effect that its rate allows,
+    /// either for the input the execution just `matched`, or for those it matched
+    /// earlier and the rate held back.
+    fn issue_updates(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        command_list: &mut ActionCommandList<C>,
+        matched: bool,
+    ) -> Result<bool, ConcertoError> {
+        if self.stored_contracts.dry_run {
+            return Ok(false);
+        }
+        let now = self.stored_contracts.current_time;
+        let update_items = self
+            .stored_contracts
+            .contracts
+            .iter()
+            .filter_map(|(item_idx, contract)| match contract {
+                ActionExecutionContract::Updates(state) if matched || state.pending => {
+                    Some(*item_idx)
+                }
+                _ => None,
+            })
+            .collect::<SmallVec<[_; 2]>>();
+        let mut issued = false;
+        for item_idx in update_items {
+            match self.stored_contracts.contracts.get_mut(&item_idx) {
+                Some(ActionExecutionContract::Updates(state)) => {
+                    if !state.rate.allows(state.issued_at, now) {
+                        state.pending = true;
+                        continue;
+                    }
+                    state.issued_at = Some(now);
+                    state.pending = false;
+                }
+                _ => continue,
+            }
+            let update_gen = match recipe_items.get(item_idx)? {
+                ActionRecipeItem::StartUpdatesOf(update_gen, _) => update_gen,
+                _ => return Err(ConcertoError::UnexpectedItem(item_idx)),
+            };
+            let exec_info = ActionRecipeExecutionInfo::new(&self.stored_contracts);
+            let cmd = update_gen.with(|update_gen| (update_gen)(exec_info));
+            command_list.push((
+                self.stored_contracts.source(self.recipe_idx),
+                Shared::new(cmd),
+            ));
+            issued = true;
+        }
+        Ok(issued)
+    }
+
+    /// Issues the updates held back by their rate that it allows by `now`.
+    pub(crate) fn flush_updates(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        command_list: &mut ActionCommandList<C>,
+        now: Duration,
+    ) -> Result<bool, ConcertoError> {
+        self.stored_contracts.current_time = now;
+        self.issue_updates(recipe_items, command_list, false)
+    }
+
+    /// When the updates held back by their rate may be issued.
+    pub(crate) fn update_deadlines(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.stored_contracts
+            .contracts
+            .iter()
+            .filter_map(|(_, contract)| match contract {
+                ActionExecutionContract::Updates(state) => state.deadline(),
+                _ => None,
+            })
+    }
+
+    /// Feeds the buffered inputs still within the window to the step just reached,
+    /// starting over whenever one of them moves the execution on.
+    fn replay_buffered_inputs(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        env: &ActionEnvironmentTrackingState<C>,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        let now = self.stored_contracts.current_time;
+        if let Some(window) = recipe.input_buffer {
+            self.buffered_inputs
+                .retain(|(time, _)| *time + window >= now);
+        }
+        let mut position = 0;
+        while position < self.buffered_inputs.len() {
+            let input = self.buffered_inputs[position].1.clone();
+            match self.process_input_1(&input, recipe_items, recipe, env)? {
+                ExecutionContextResult::Used => {
+                    self.issue_updates(recipe_items, command_list, true)?;
+                    self.buffered_inputs.remove(position);
+                    match self.process_input_2(
+                        recipe_items,
+                        recipe,
+                        command_list,
+                        nest_recipe_command_list,
+                        env,
+                    )? {
+                        ExecutionContextResult::Used => position = 0,
+                        result => return Ok(result),
+                    }
+                }
+                _ => position += 1,
             }
         }
-        return self.process_input_2(recipe_items, command_list, nest_recipe_command_list, env);
+        Ok(ExecutionContextResult::Used)
     }
 
+    /// Ends every contract still in effect, `aborted` telling whether the execution
+    /// was aborted rather than completed, in which case the recipe's abort commands
+    /// are issued first.
     pub(crate) fn clean_up(
         &mut self,
-        command_list: &mut Vec<C::Command>,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        aborted: bool,
     ) -> bool {
-        self.stored_contracts
-            .eliminate_all(self.recipe_idx, command_list, nest_recipe_command_list)
+        let mut new_command = false;
+        if aborted {
+            for &item_idx in recipe.on_abort.iter() {
+                let command = match recipe_items.get(item_idx) {
+                    Ok(ActionRecipeItem::DoCommand(cmd)) => cmd.command().clone(),
+                    Ok(ActionRecipeItem::DoCommandOf(_)) if self.stored_contracts.dry_run => {
+                        continue
+                    }
+                    Ok(ActionRecipeItem::DoCommandOf(cmd_gen)) => {
+                        let exec_info = ActionRecipeExecutionInfo::new(&self.stored_contracts);
+                        Shared::new(cmd_gen.with(|cmd_gen| (cmd_gen)(exec_info)))
+                    }
+                    _ => continue,
+                };
+                command_list.push((self.stored_contracts.source(self.recipe_idx), command));
+                new_command = true;
+            }
+        }
+        if self.stored_contracts.eliminate_all(
+            self.recipe_idx,
+            command_list,
+            nest_recipe_command_list,
+            aborted,
+        ) {
+            new_command = true;
+        }
+        new_command
     }
 
-    pub(crate) fn start_execution_with_input(
-        input: &ActionInput<C>,
+    /// Runs the commands and effects of the recipe as if its inputs had occurred,
+    /// offering `args` to the generators as the matched inputs. Optional and
+    /// zero-or-more items are skipped, and choices take their first branch.
+    pub(crate) fn trigger(
+        args: &[ActionInput<C>],
         recipe_items: &ActionRecipeItemStore<C>,
         recipe: &ActionRecipe<C>,
         recipe_idx: usize,
-        command_list: &mut Vec<C::Command>,
+        execution_id: ActionExecutionId,
+        command_list: &mut ActionCommandList<C>,
+        now: Duration,
+    ) -> Result<(), ConcertoError> {
+        let mut exec_ctx = ActionExecutionCtx::new(recipe_idx, execution_id, recipe, recipe_items)?;
+        exec_ctx.stored_contracts.current_time = now;
+        for arg in args {
+            exec_ctx
+                .stored_contracts
+                .add_captured_input(recipe.root_item, Shared::new(arg.clone()));
+        }
+        // a completed execution disables its nest recipes again, so they are never enabled.
+        let mut nest_recipe_command_list = Vec::new();
+        exec_ctx.trigger_item(
+            recipe_items,
+            recipe.root_item,
+            command_list,
+            &mut nest_recipe_command_list,
+        )?;
+        exec_ctx.clean_up(
+            recipe_items,
+            recipe,
+            command_list,
+            &mut nest_recipe_command_list,
+            false,
+        );
+        Ok(())
+    }
+
+    fn trigger_item(
+        &mut self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        item_idx: ActionRecipeItemIdx,
+        command_list: &mut ActionCommandList<C>,
         nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+    ) -> Result<(), ConcertoError> {
+        let item = recipe_items.get(item_idx)?;
+        let seq: &[ActionRecipeItemIdx] = match item {
+            ActionRecipeItem::Sequential(seq)
+            | ActionRecipeItem::Repeated(seq, ActionRecipeRepetition::OneOrMore) => seq,
+            ActionRecipeItem::Unordered(seq, required) => &seq[..*required],
+            ActionRecipeItem::Choice(seq) => &seq[..seq.len().min(1)],
+            _ if item.is_noninteractive() => {
+                return Self::put_noninteractive_item_into_effect(
+                    self.recipe_idx,
+                    item_idx,
+                    item,
+                    command_list,
+                    nest_recipe_command_list,
+                    &mut self.stored_contracts,
+                );
+            }
+            _ => return Ok(()),
+        };
+        for &seq_item_idx in seq {
+            self.trigger_item(
+                recipe_items,
+                seq_item_idx,
+                command_list,
+                nest_recipe_command_list,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Tells what `process_input` would return for `input`, working on a copy of the
+    /// execution that issues no commands and calls no generators.
+    pub(crate) fn peek_input(
+        &self,
+        input: &ActionInput<C>,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
         env: &ActionEnvironmentTrackingState<C>,
-    ) -> (ExecutionContextResult, Option<Self>) {
-        let mut exec_ctx = ActionExecutionCtx::new(recipe_idx, recipe, recipe_items);
-        let mut temporary_nest_recipe_command_list = Vec::new();
-        let result1 = exec_ctx.process_input_2(
+        now: Duration,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        let mut exec_ctx = self.clone();
+        exec_ctx.stored_contracts.dry_run = true;
+        exec_ctx.process_input(
+            input,
             recipe_items,
-            command_list,
-            &mut temporary_nest_recipe_command_list,
+            recipe,
+            &mut Vec::new(),
+            &mut Vec::new(),
             env,
-        );
-        match result1 {
-            ExecutionContextResult::Done => {
-                panic!("You have a recipe that completes itself without any input!")
+            now,
+        )
+    }
+
+    /// Like `peek_input`, for an execution `start_execution_with_input` would start.
+    pub(crate) fn peek_start_with_input(
+        input: &ActionInput<C>,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        recipe_idx: usize,
+        execution_id: ActionExecutionId,
+        env: &ActionEnvironmentTrackingState<C>,
+        now: Duration,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        let mut exec_ctx = ActionExecutionCtx::new(recipe_idx, execution_id, recipe, recipe_items)?;
+        exec_ctx.stored_contracts.dry_run = true;
+        exec_ctx
+            .start_with_input(
+                input,
+                recipe_items,
+                recipe,
+                &mut Vec::new(),
+                &mut Vec::new(),
+                env,
+                now,
+            )
+            .map(|(result, _)| result)
+    }
+
+    /// Completes a recipe `accelerator_key` found to be a single chord on its key,
+    /// checking its conditions and issuing its commands without an execution.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn complete_accelerated(
+        input: &ActionInput<C>,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        recipe_idx: usize,
+        execution_id: ActionExecutionId,
+        command_list: &mut ActionCommandList<C>,
+        env: &ActionEnvironmentTrackingState<C>,
+    ) -> Result<ExecutionContextResult, ConcertoError> {
+        let seq = match recipe_items.get(recipe.root_item)? {
+            ActionRecipeItem::Sequential(seq) => seq,
+            _ => return Err(ConcertoError::UnexpectedItem(recipe.root_item)),
+        };
+        let mut key_matched = false;
+        for &item_idx in seq.iter() {
+            match recipe_items.get(item_idx)? {
+                ActionRecipeItem::StartInput(_) => key_matched = true,
+                ActionRecipeItem::StartCondition(condition) => {
+                    if !Self::check_condition_match_environment(condition, env) {
+                        return Ok(ExecutionContextResult::Ignore);
+                    }
+                    // a condition before the key also has to admit the key itself.
+                    if !key_matched {
+                        if let ExecutionContextResult::Abort =
+                            Self::check_input_match_condition(condition, input)
+                        {
+                            return Ok(ExecutionContextResult::Ignore);
+                        }
+                    }
+                }
+                _ => {}
             }
-            ExecutionContextResult::Ignore | ExecutionContextResult::Abort => {
-                return (ExecutionContextResult::Ignore, None);
+        }
+        let source = ActionCommandSource {
+            recipe_idx,
+            execution_id: Some(execution_id),
+        };
+        for &item_idx in seq.iter() {
+            if let ActionRecipeItem::DoCommand(cmd) = recipe_items.get(item_idx)? {
+                command_list.push((source, cmd.command().clone()));
             }
-            ExecutionContextResult::Used => {}
         }
-        let result2 = exec_ctx.process_input(
+        Ok(ExecutionContextResult::Done)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn start_execution_with_input(
+        input: &ActionInput<C>,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        recipe_idx: usize,
+        execution_id: ActionExecutionId,
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        env: &ActionEnvironmentTrackingState<C>,
+        now: Duration,
+        dry_run: bool,
+    ) -> Result<(ExecutionContextResult, Option<Self>), ConcertoError> {
+        let mut exec_ctx = ActionExecutionCtx::new(recipe_idx, execution_id, recipe, recipe_items)?;
+        exec_ctx.stored_contracts.dry_run = dry_run;
+        exec_ctx.start_with_input(
             input,
             recipe_items,
             recipe,
             command_list,
-            &mut temporary_nest_recipe_command_list,
+            nest_recipe_command_list,
             env,
-        );
-        match result2 {
-            ExecutionContextResult::Done => (ExecutionContextResult::Done, None),
-            ExecutionContextResult::Ignore | ExecutionContextResult::Abort => {
-                (ExecutionContextResult::Ignore, None)
+            now,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn start_with_input(
+        self,
+        input: &ActionInput<C>,
+        recipe_items: &ActionRecipeItemStore<C>,
+        recipe: &ActionRecipe<C>,
+        command_list: &mut ActionCommandList<C>,
+        nest_recipe_command_list: &mut Vec<ActionNestRecipeCommand>,
+        env: &ActionEnvironmentTrackingState<C>,
+        now: Duration,
+    ) -> Result<(ExecutionContextResult, Option<Self>), ConcertoError> {
+        let mut exec_ctx = self;
+        exec_ctx.stored_contracts.current_time = now;
+        // nest recipe commands are only kept if the execution actually starts.
+        let nest_recipe_command_mark = nest_recipe_command_list.len();
+        let result = exec_ctx
+            .process_input_2(
+                recipe_items,
+                recipe,
+                command_list,
+                nest_recipe_command_list,
+                env,
+            )
+            .and_then(|result1| match result1 {
+                ExecutionContextResult::Done => {
+                    Err(ConcertoError::NoInteractiveItem(recipe.root_item))
+                }
+                ExecutionContextResult::Ignore | ExecutionContextResult::Abort => {
+                    Ok(ExecutionContextResult::Ignore)
+                }
+                ExecutionContextResult::Used => exec_ctx.process_input(
+                    input,
+                    recipe_items,
+                    recipe,
+                    command_list,
+                    nest_recipe_command_list,
+                    env,
+                    now,
+                ),
+            });
+        match result {
+            Ok(ExecutionContextResult::Used) => Ok((ExecutionContextResult::Used, Some(exec_ctx))),
+            Ok(ExecutionContextResult::Done) => {
+                // the execution completed on its first input, so its effects end right away.
+                exec_ctx.clean_up(
+                    recipe_items,
+                    recipe,
+                    command_list,
+                    nest_recipe_command_list,
+                    false,
+                );
+                nest_recipe_command_list.truncate(nest_recipe_command_mark);
+                Ok((ExecutionContextResult::Done, None))
             }
-            ExecutionContextResult::Used => {
-                nest_recipe_command_list.extend(temporary_nest_recipe_command_list.into_iter());
-                (ExecutionContextResult::Used, Some(exec_ctx))
+            result => {
+                nest_recipe_command_list.truncate(nest_recipe_command_mark);
+                result.map(|result2| match result2 {
+                    ExecutionContextResult::Done => (ExecutionContextResult::Done, None),
+                    _ => (ExecutionContextResult::Ignore, None),
+                })
             }
         }
     }
@@ -737,3 +2703,78 @@ pub(crate) enum ExecutionContextResult {
     Ignore,
     Abort,
 }
+
+#[cfg(test)]
+mod tests {
+    use testing::Harness;
+    use ActionConfiguration;
+    use ActionContextBuilder;
+    use ActionInput::{KeyDown, KeyUp};
+
+    struct Config;
+
+    impl ActionConfiguration for Config {
+        type Target = ();
+        type KeyKind = char;
+        type CursorPos = ();
+        type Command = &'static str;
+        type EnvExt = ();
+    }
+
+    fn nested_effects<'f>() -> ActionContextBuilder<'f, Config> {
+        ActionContextBuilder::new().add_recipe(|b| {
+            b.add_key_down_input('a')
+                .issue_effect_with_abort("outer", "/outer", "!outer")
+                .add_key_down_input('b')
+                .issue_effect_with_abort("inner", "/inner", "!inner")
+                .add_key_down_input('c')
+                .issue_command("done")
+                .build()
+        })
+    }
+
+    #[test]
+    fn effects_end_last_started_first_on_completion() {
+        Harness::new(nested_effects())
+            .feed(vec![KeyDown('a'), KeyDown('b'), KeyDown('c')])
+            .expect_commands(vec!["outer", "inner", "done", "/inner", "/outer"])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn effects_end_last_started_first_on_abort() {
+        let mut context = Harness::new(nested_effects())
+            .feed(vec![KeyDown('a'), KeyDown('b')])
+            .expect_commands(vec!["outer", "inner"])
+            .into_context();
+        context.notify_focus_lost();
+        Harness::from_context(context)
+            .expect_commands(vec!["!inner", "!outer"])
+            .expect_no_pending();
+    }
+
+    #[test]
+    fn effects_end_last_started_first_after_repetition() {
+        let builder = ActionContextBuilder::new().add_recipe(|b| {
+            b.add_key_down_input('a')
+                .issue_effect("outer", "/outer")
+                .repeatedly(|b| {
+                    b.add_key_down_input('b')
+                        .issue_effect("inner", "/inner")
+                        .add_key_up_input('b')
+                })
+                .add_key_down_input('c')
+                .issue_effect("last", "/last")
+                .add_key_up_input('c')
+                .build()
+        });
+        Harness::<Config>::new(builder)
+            .feed(vec![KeyDown('a'), KeyDown('b'), KeyUp('b')])
+            .expect_commands(vec!["outer", "inner", "/inner"])
+            .feed(vec![KeyDown('b'), KeyUp('b')])
+            .expect_commands(vec!["inner", "/inner"])
+            .feed(vec![KeyDown('c'), KeyUp('c')])
+            .expect_commands(vec!["last", "/last", "/outer"])
+            .expect_no_pending();
+    }
+}
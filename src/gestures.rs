@@ -0,0 +1,106 @@
+use error::ConcertoError;
+use recipe::{ActionInput, ActionRecipe, ActionRecipeBuilder};
+use shared::{MaybeSendSync, Shared};
+use ActionConfiguration;
+
+/// A straight movement of the cursor, in screen coordinates where y grows downwards.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ActionStroke {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl ActionStroke {
+    /// Parses one of `L`, `R`, `U` and `D`.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'L' => Some(ActionStroke::Left),
+            'R' => Some(ActionStroke::Right),
+            'U' => Some(ActionStroke::Up),
+            'D' => Some(ActionStroke::Down),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            ActionStroke::Left => 'L',
+            ActionStroke::Right => 'R',
+            ActionStroke::Up => 'U',
+            ActionStroke::Down => 'D',
+        }
+    }
+}
+
+/// Quantizes a cursor path into strokes. A stroke is taken every time the cursor
+/// has moved at least `min_distance` from where the last one was taken, in the
+/// dominant direction of that move; repeated directions are merged.
+pub fn quantize_path<I>(points: I, min_distance: f32) -> Vec<ActionStroke>
+where
+    I: IntoIterator<Item = (f32, f32)>,
+{
+    let mut strokes = Vec::new();
+    let mut points = points.into_iter();
+    let mut anchor = match points.next() {
+        Some(point) => point,
+        None => return strokes,
+    };
+    for point in points {
+        let (dx, dy) = (point.0 - anchor.0, point.1 - anchor.1);
+        if dx.hypot(dy) < min_distance {
+            continue;
+        }
+        let stroke = match (dx.abs() >= dy.abs(), dx > 0.0, dy > 0.0) {
+            (true, true, _) => ActionStroke::Right,
+            (true, false, _) => ActionStroke::Left,
+            (false, _, true) => ActionStroke::Down,
+            (false, _, false) => ActionStroke::Up,
+        };
+        if strokes.last() != Some(&stroke) {
+            strokes.push(stroke);
+        }
+        anchor = point;
+    }
+    strokes
+}
+
+/// Issues `command` once `button` is pressed, the cursor traces `pattern` (a string
+/// of strokes such as `"DR"`) and `button` is released. `position` gives the screen
+/// coordinates of the targets reported by cursor inputs; those without any are
+/// skipped. Gestures sharing a button run side by side, and only the one whose
+/// pattern matches the path completes.
+pub fn gesture<'a, 'f, C, P>(
+    builder: ActionRecipeBuilder<'a, 'f, C>,
+    button: C::KeyKind,
+    pattern: &str,
+    min_distance: f32,
+    position: P,
+    command: C::Command,
+) -> Result<ActionRecipe<C>, ConcertoError>
+where
+    C: ActionConfiguration,
+    P: Fn(&C::Target) -> Option<(f32, f32)> + MaybeSendSync + 'f,
+{
+    let strokes = pattern
+        .chars()
+        .map(ActionStroke::from_char)
+        .collect::<Option<Vec<_>>>()
+        .filter(|strokes| !strokes.is_empty())
+        .ok_or_else(|| ConcertoError::InvalidGesture(pattern.to_owned()))?;
+    let position = Shared::new(position);
+    builder
+        .add_key_down_input(button.clone())
+        .optionally_repeatedly(|b| b.add_cursor_coordinate_filtered_input(|_| true))
+        .add_key_up_input(button)
+        .check_execution(move |info| {
+            let path = info.matched_inputs().filter_map(|(_, input)| match input {
+                ActionInput::CursorCoordinate(target) => (position)(target),
+                _ => None,
+            });
+            quantize_path(path, min_distance) == strokes
+        })
+        .issue_command(command)
+        .build()
+}
@@ -0,0 +1,155 @@
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use shared::KeySet;
+use ActionConfiguration;
+use ActionContext;
+use ActionInput;
+
+const MODIFIER_FLAGS: [KeyModifiers; 6] = [
+    KeyModifiers::SHIFT,
+    KeyModifiers::CONTROL,
+    KeyModifiers::ALT,
+    KeyModifiers::SUPER,
+    KeyModifiers::HYPER,
+    KeyModifiers::META,
+];
+
+pub trait CrosstermInputMapper<C: ActionConfiguration> {
+    fn map_key_code(&mut self, code: KeyCode) -> Option<C::KeyKind>;
+
+    /// Called with a single modifier flag at a time.
+    fn map_modifier(&mut self, modifier: KeyModifiers) -> Option<C::KeyKind>;
+
+    fn map_mouse_button(&mut self, button: MouseButton) -> Option<C::KeyKind>;
+
+    /// Hit-tests a terminal cell; `None` means no target and the position is dropped.
+    fn resolve_cell_target(&mut self, column: u16, row: u16) -> Option<C::Target>;
+}
+
+pub struct CrosstermAdapter<C: ActionConfiguration, M> {
+    mapper: M,
+    report_releases: bool,
+    pressed_keys: KeySet<C::KeyKind>,
+    inputs: Vec<ActionInput<C>>,
+}
+
+impl<C: ActionConfiguration, M: CrosstermInputMapper<C>> CrosstermAdapter<C, M> {
+    /// Creates an adapter for terminals that only report key presses: every press
+    /// (and every auto-repeat) becomes a full tap, with the reported modifiers held
+    /// down around it.
+    pub fn new(mapper: M) -> Self {
+        CrosstermAdapter {
+            mapper,
+            report_releases: false,
+            pressed_keys: KeySet::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Creates an adapter for terminals with the keyboard enhancement
+    /// `REPORT_EVENT_TYPES` flag pushed, forwarding real press and release events
+    /// and dropping auto-repeats.
+    pub fn with_release_reporting(mapper: M) -> Self {
+        CrosstermAdapter {
+            report_releases: true,
+            ..CrosstermAdapter::new(mapper)
+        }
+    }
+
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    fn press(&mut self, key: C::KeyKind) {
+        if self.pressed_keys.insert(key.clone()) {
+            self.inputs.push(ActionInput::KeyDown(key));
+        }
+    }
+
+    fn release(&mut self, key: C::KeyKind) {
+        if self.pressed_keys.remove(&key) {
+            self.inputs.push(ActionInput::KeyUp(key));
+        }
+    }
+
+    fn release_all(&mut self) {
+        let pressed_keys = ::std::mem::take(&mut self.pressed_keys);
+        self.inputs
+            .extend(pressed_keys.into_iter().map(ActionInput::KeyUp));
+    }
+
+    // Terminals report modifiers as flags on each event rather than as key events
+    // of their own, so modifier keys are pressed and released by diffing the flags.
+    fn sync_modifiers(&mut self, modifiers: KeyModifiers) {
+        for &flag in MODIFIER_FLAGS.iter() {
+            if let Some(key) = self.mapper.map_modifier(flag) {
+                if modifiers.contains(flag) {
+                    self.press(key);
+                } else {
+                    self.release(key);
+                }
+            }
+        }
+    }
+
+    fn translate_key_event(&mut self, event: &KeyEvent) {
+        let key = self.mapper.map_key_code(event.code);
+        if self.report_releases {
+            self.sync_modifiers(event.modifiers);
+            match (key, event.kind) {
+                (Some(key), KeyEventKind::Press) => self.press(key),
+                (Some(key), KeyEventKind::Release) => self.release(key),
+                _ => {}
+            }
+        } else if event.kind != KeyEventKind::Release {
+            self.sync_modifiers(event.modifiers);
+            if let Some(key) = key {
+                self.press(key.clone());
+                self.release(key);
+            }
+            self.sync_modifiers(KeyModifiers::NONE);
+        }
+    }
+
+    fn translate_mouse_event(&mut self, event: &MouseEvent) {
+        self.sync_modifiers(event.modifiers);
+        let button = match event.kind {
+            MouseEventKind::Down(button)
+            | MouseEventKind::Up(button)
+            | MouseEventKind::Drag(button) => Some(button),
+            MouseEventKind::Moved => None,
+            _ => return,
+        };
+        if let Some(target) = self.mapper.resolve_cell_target(event.column, event.row) {
+            self.inputs.push(ActionInput::CursorCoordinate(target));
+        }
+        if let Some(key) = button.and_then(|button| self.mapper.map_mouse_button(button)) {
+            match event.kind {
+                MouseEventKind::Down(_) => self.press(key),
+                MouseEventKind::Up(_) => self.release(key),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn translate_event(&mut self, event: &Event) -> &[ActionInput<C>] {
+        self.inputs.clear();
+        match event {
+            Event::Key(event) => self.translate_key_event(event),
+            Event::Mouse(event) => self.translate_mouse_event(event),
+            Event::FocusLost => self.release_all(),
+            _ => {}
+        }
+        &self.inputs
+    }
+
+    pub fn handle_event(&mut self, context: &mut ActionContext<C>, event: &Event) -> bool {
+        self.translate_event(event);
+        context.process_inputs(&self.inputs)
+    }
+}
@@ -0,0 +1,199 @@
+use egui::{Event, Id, InputState, Key, Modifiers, PointerButton, Pos2, Response, TouchPhase};
+use shared::KeySet;
+use ActionConfiguration;
+use ActionContext;
+use ActionInput;
+use ActionTouch;
+
+const MODIFIER_FLAGS: [Modifiers; 4] = [
+    Modifiers::ALT,
+    Modifiers::CTRL,
+    Modifiers::SHIFT,
+    Modifiers::MAC_CMD,
+];
+
+const POINTER_BUTTONS: [PointerButton; 5] = [
+    PointerButton::Primary,
+    PointerButton::Secondary,
+    PointerButton::Middle,
+    PointerButton::Extra1,
+    PointerButton::Extra2,
+];
+
+pub trait EguiInputMapper<C: ActionConfiguration> {
+    /// Resolves the pointer position to a target, given the tracked widget under the
+    /// pointer if any; `None` means no target and the position is dropped.
+    fn resolve_target(&mut self, widget: Option<Id>, position: Pos2) -> Option<C::Target>;
+
+    fn map_key(&mut self, key: Key) -> Option<C::KeyKind>;
+
+    /// Called with a single modifier flag at a time.
+    fn map_modifier(&mut self, modifier: Modifiers) -> Option<C::KeyKind>;
+
+    fn map_pointer_button(&mut self, button: PointerButton) -> Option<C::KeyKind>;
+}
+
+/// Turns the input events of an egui frame into `ActionInput`s, in the order they
+/// happened. Keys whose events were missed, e.g. across focus changes, are brought
+/// in line with the input state of the frame afterwards.
+pub struct EguiAdapter<C: ActionConfiguration, M> {
+    mapper: M,
+    hovered_widget: Option<Id>,
+    last_pointer: Option<(Option<Id>, Pos2)>,
+    modifiers: Modifiers,
+    pressed_keys: KeySet<C::KeyKind>,
+    inputs: Vec<ActionInput<C>>,
+}
+
+impl<C: ActionConfiguration, M: EguiInputMapper<C>> EguiAdapter<C, M> {
+    pub fn new(mapper: M) -> Self {
+        EguiAdapter {
+            mapper,
+            hovered_widget: None,
+            last_pointer: None,
+            modifiers: Modifiers::NONE,
+            pressed_keys: KeySet::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    /// Offers a widget for target resolution during the current frame. When several
+    /// tracked widgets contain the pointer, the one tracked last wins.
+    pub fn track_widget(&mut self, response: &Response) {
+        if response.contains_pointer() {
+            self.hovered_widget = Some(response.id);
+        }
+    }
+
+    fn press(&mut self, key: C::KeyKind) {
+        if self.pressed_keys.insert(key.clone()) {
+            self.inputs.push(ActionInput::KeyDown(key));
+        }
+    }
+
+    fn release(&mut self, key: C::KeyKind) {
+        if self.pressed_keys.remove(&key) {
+            self.inputs.push(ActionInput::KeyUp(key));
+        }
+    }
+
+    fn translate_event(&mut self, event: &Event) {
+        let (key, pressed) = match *event {
+            Event::ModifiersChanged(modifiers) => {
+                for &flag in MODIFIER_FLAGS.iter() {
+                    if modifiers.contains(flag) != self.modifiers.contains(flag) {
+                        if let Some(key) = self.mapper.map_modifier(flag) {
+                            if modifiers.contains(flag) {
+                                self.press(key);
+                            } else {
+                                self.release(key);
+                            }
+                        }
+                    }
+                }
+                self.modifiers = modifiers;
+                return;
+            }
+            // auto-repeat only reports a key that is already pressed.
+            Event::Key { repeat: true, .. } => return,
+            Event::Key { key, pressed, .. } => (self.mapper.map_key(key), pressed),
+            Event::PointerButton {
+                button, pressed, ..
+            } => (self.mapper.map_pointer_button(button), pressed),
+            Event::Touch { id, phase, pos, .. } => {
+                let touch = ActionTouch {
+                    id: id.0,
+                    x: pos.x,
+                    y: pos.y,
+                };
+                self.inputs.push(match phase {
+                    TouchPhase::Start => ActionInput::TouchStart(touch),
+                    TouchPhase::Move => ActionInput::TouchMove(touch),
+                    TouchPhase::End | TouchPhase::Cancel => ActionInput::TouchEnd(touch),
+                });
+                return;
+            }
+            _ => return,
+        };
+        match (key, pressed) {
+            (Some(key), true) => self.press(key),
+            (Some(key), false) => self.release(key),
+            (None, _) => {}
+        }
+    }
+
+    fn desired_keys(&mut self, input: &InputState) -> KeySet<C::KeyKind> {
+        let mut desired_keys = KeySet::new();
+        if !input.focused {
+            return desired_keys;
+        }
+        for &flag in MODIFIER_FLAGS.iter() {
+            if input.modifiers.contains(flag) {
+                desired_keys.extend(self.mapper.map_modifier(flag));
+            }
+        }
+        for &key in input.keys_down.iter() {
+            desired_keys.extend(self.mapper.map_key(key));
+        }
+        for &button in POINTER_BUTTONS.iter() {
+            if input.pointer.button_down(button) {
+                desired_keys.extend(self.mapper.map_pointer_button(button));
+            }
+        }
+        desired_keys
+    }
+
+    /// Call once per frame, after the widgets of the frame have been tracked.
+    pub fn translate_input(&mut self, input: &InputState) -> &[ActionInput<C>] {
+        self.inputs.clear();
+
+        let widget = self.hovered_widget.take();
+        if let Some(position) = input.pointer.hover_pos() {
+            if self.last_pointer != Some((widget, position)) {
+                self.last_pointer = Some((widget, position));
+                if let Some(target) = self.mapper.resolve_target(widget, position) {
+                    self.inputs.push(ActionInput::CursorCoordinate(target));
+                }
+            }
+        }
+
+        if input.focused {
+            for event in input.events.iter() {
+                self.translate_event(event);
+            }
+        }
+
+        self.modifiers = input.modifiers;
+        let desired_keys = self.desired_keys(input);
+        let released = self
+            .pressed_keys
+            .difference(&desired_keys)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in released {
+            self.release(key);
+        }
+        let pressed = desired_keys
+            .difference(&self.pressed_keys)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in pressed {
+            self.press(key);
+        }
+
+        &self.inputs
+    }
+
+    pub fn handle_input(&mut self, context: &mut ActionContext<C>, input: &InputState) -> bool {
+        self.translate_input(input);
+        context.process_inputs(&self.inputs)
+    }
+}
@@ -0,0 +1,192 @@
+use gilrs::{Axis, Button, Event, EventType, GamepadId};
+use shared::KeyMap;
+use std::collections::{HashMap, HashSet};
+use ActionConfiguration;
+use ActionContext;
+use ActionInput;
+
+pub trait GilrsInputMapper<C: ActionConfiguration> {
+    fn map_button(&mut self, gamepad: GamepadId, button: Button) -> Option<C::KeyKind>;
+
+    /// Digital key standing for `axis` pushed past its threshold, in the positive
+    /// direction if `positive` is set and in the negative direction otherwise.
+    fn map_axis_direction(
+        &mut self,
+        gamepad: GamepadId,
+        axis: Axis,
+        positive: bool,
+    ) -> Option<C::KeyKind>;
+
+    /// Key identifying `axis` in `ActionInput::Axis` inputs carrying its raw value.
+    /// By default axes are only reported through `map_axis_direction`.
+    fn map_axis(&mut self, _gamepad: GamepadId, _axis: Axis) -> Option<C::KeyKind> {
+        None
+    }
+}
+
+/// Axis value magnitudes at which an axis direction is pressed and released again.
+/// Keeping `release` below `press` stops a stick resting near the threshold from
+/// chattering between the two states.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AxisThreshold {
+    pub press: f32,
+    pub release: f32,
+}
+
+impl Default for AxisThreshold {
+    fn default() -> Self {
+        AxisThreshold {
+            press: 0.5,
+            release: 0.3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum AxisDirection {
+    Negative,
+    Neutral,
+    Positive,
+}
+
+pub struct GilrsAdapter<C: ActionConfiguration, M> {
+    mapper: M,
+    default_threshold: AxisThreshold,
+    axis_thresholds: HashMap<Axis, AxisThreshold>,
+    axis_directions: HashMap<(GamepadId, Axis), AxisDirection>,
+    pressed_keys: KeyMap<C::KeyKind, HashSet<GamepadId>>,
+    inputs: Vec<ActionInput<C>>,
+}
+
+impl<C: ActionConfiguration, M: GilrsInputMapper<C>> GilrsAdapter<C, M> {
+    pub fn new(mapper: M) -> Self {
+        GilrsAdapter {
+            mapper,
+            default_threshold: AxisThreshold::default(),
+            axis_thresholds: HashMap::new(),
+            axis_directions: HashMap::new(),
+            pressed_keys: KeyMap::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    pub fn set_default_axis_threshold(&mut self, threshold: AxisThreshold) {
+        self.default_threshold = threshold;
+    }
+
+    pub fn set_axis_threshold(&mut self, axis: Axis, threshold: AxisThreshold) {
+        self.axis_thresholds.insert(axis, threshold);
+    }
+
+    fn axis_threshold(&self, axis: Axis) -> AxisThreshold {
+        self.axis_thresholds
+            .get(&axis)
+            .cloned()
+            .unwrap_or(self.default_threshold)
+    }
+
+    // Several gamepads may map onto the same key, which then stays down until the
+    // last of them lets go.
+    fn press(&mut self, gamepad: GamepadId, key: Option<C::KeyKind>) {
+        if let Some(key) = key {
+            let holders = self.pressed_keys.get_or_default(key.clone());
+            if holders.is_empty() {
+                self.inputs.push(ActionInput::KeyDown(key));
+            }
+            holders.insert(gamepad);
+        }
+    }
+
+    fn release(&mut self, gamepad: GamepadId, key: Option<C::KeyKind>) {
+        if let Some(key) = key {
+            let now_released = match self.pressed_keys.get_mut(&key) {
+                Some(holders) => holders.remove(&gamepad) && holders.is_empty(),
+                None => false,
+            };
+            if now_released {
+                self.pressed_keys.remove(&key);
+                self.inputs.push(ActionInput::KeyUp(key));
+            }
+        }
+    }
+
+    fn release_gamepad(&mut self, gamepad: GamepadId) {
+        let keys = self
+            .pressed_keys
+            .iter()
+            .filter(|(_, holders)| holders.contains(&gamepad))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in keys {
+            self.release(gamepad, Some(key));
+        }
+        self.axis_directions
+            .retain(|&(axis_gamepad, _), _| axis_gamepad != gamepad);
+    }
+
+    fn update_axis(&mut self, gamepad: GamepadId, axis: Axis, value: f32) {
+        if let Some(key) = self.mapper.map_axis(gamepad, axis) {
+            self.inputs.push(ActionInput::Axis(key, value));
+        }
+        let threshold = self.axis_threshold(axis);
+        let old_direction = self
+            .axis_directions
+            .get(&(gamepad, axis))
+            .cloned()
+            .unwrap_or(AxisDirection::Neutral);
+        let new_direction = match old_direction {
+            AxisDirection::Positive if value >= threshold.release => AxisDirection::Positive,
+            AxisDirection::Negative if value <= -threshold.release => AxisDirection::Negative,
+            _ if value >= threshold.press => AxisDirection::Positive,
+            _ if value <= -threshold.press => AxisDirection::Negative,
+            _ => AxisDirection::Neutral,
+        };
+        if new_direction == old_direction {
+            return;
+        }
+        self.axis_directions.insert((gamepad, axis), new_direction);
+
+        if old_direction != AxisDirection::Neutral {
+            let positive = old_direction == AxisDirection::Positive;
+            let key = self.mapper.map_axis_direction(gamepad, axis, positive);
+            self.release(gamepad, key);
+        }
+        if new_direction != AxisDirection::Neutral {
+            let positive = new_direction == AxisDirection::Positive;
+            let key = self.mapper.map_axis_direction(gamepad, axis, positive);
+            self.press(gamepad, key);
+        }
+    }
+
+    pub fn translate_event(&mut self, event: &Event) -> &[ActionInput<C>] {
+        self.inputs.clear();
+        let gamepad = event.id;
+        match event.event {
+            EventType::ButtonPressed(button, _) => {
+                let key = self.mapper.map_button(gamepad, button);
+                self.press(gamepad, key);
+            }
+            EventType::ButtonReleased(button, _) => {
+                let key = self.mapper.map_button(gamepad, button);
+                self.release(gamepad, key);
+            }
+            EventType::AxisChanged(axis, value, _) => self.update_axis(gamepad, axis, value),
+            EventType::Disconnected => self.release_gamepad(gamepad),
+            _ => {}
+        }
+        &self.inputs
+    }
+
+    pub fn handle_event(&mut self, context: &mut ActionContext<C>, event: &Event) -> bool {
+        self.translate_event(event);
+        context.process_inputs(&self.inputs)
+    }
+}
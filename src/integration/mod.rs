@@ -0,0 +1,10 @@
+#[cfg(feature = "crossterm")]
+pub mod crossterm;
+#[cfg(feature = "egui")]
+pub mod egui;
+#[cfg(feature = "gilrs")]
+pub mod gilrs;
+#[cfg(feature = "web")]
+pub mod web;
+#[cfg(feature = "winit")]
+pub mod winit;
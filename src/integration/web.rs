@@ -0,0 +1,195 @@
+use shared::KeySet;
+use std::collections::HashMap;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, Event, KeyboardEvent, PointerEvent};
+use ActionConfiguration;
+use ActionContext;
+use ActionInput;
+use ActionTouch;
+
+pub trait WebInputMapper<C: ActionConfiguration> {
+    /// Usually maps `event.code()` for layout-independent bindings or `event.key()`
+    /// for layout-dependent ones.
+    fn map_keyboard_event(&mut self, event: &KeyboardEvent) -> Option<C::KeyKind>;
+
+    /// `button` follows the DOM numbering: 0 primary, 1 auxiliary, 2 secondary, ...
+    fn map_pointer_button(&mut self, event: &PointerEvent, button: i16) -> Option<C::KeyKind>;
+
+    /// Hit-tests a pointer event; `None` means no target and the position is dropped.
+    fn resolve_pointer_target(&mut self, event: &PointerEvent) -> Option<C::Target>;
+}
+
+pub struct WebAdapter<C: ActionConfiguration, M> {
+    mapper: M,
+    capture_pointers: bool,
+    pressed_keys: KeySet<C::KeyKind>,
+    pointer_buttons: HashMap<i32, Vec<C::KeyKind>>,
+    inputs: Vec<ActionInput<C>>,
+}
+
+impl<C: ActionConfiguration, M: WebInputMapper<C>> WebAdapter<C, M> {
+    pub fn new(mapper: M) -> Self {
+        WebAdapter {
+            mapper,
+            capture_pointers: true,
+            pressed_keys: KeySet::new(),
+            pointer_buttons: HashMap::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Whether a pointer pressing a button gets captured by the element the listener
+    /// is installed on, so that its moves and releases keep arriving after it leaves
+    /// the element. Enabled by default.
+    pub fn set_capture_pointers(&mut self, capture_pointers: bool) {
+        self.capture_pointers = capture_pointers;
+    }
+
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    fn press(&mut self, key: C::KeyKind) -> bool {
+        if self.pressed_keys.insert(key.clone()) {
+            self.inputs.push(ActionInput::KeyDown(key));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn release(&mut self, key: C::KeyKind) {
+        if self.pressed_keys.remove(&key) {
+            self.inputs.push(ActionInput::KeyUp(key));
+        }
+    }
+
+    fn release_all(&mut self) {
+        let pressed_keys = ::std::mem::take(&mut self.pressed_keys);
+        self.pointer_buttons.clear();
+        self.inputs
+            .extend(pressed_keys.into_iter().map(ActionInput::KeyUp));
+    }
+
+    fn release_pointer(&mut self, pointer_id: i32) {
+        if let Some(buttons) = self.pointer_buttons.remove(&pointer_id) {
+            for key in buttons {
+                self.release(key);
+            }
+        }
+    }
+
+    fn translate_keyboard_event(&mut self, event: &KeyboardEvent, pressed: bool) {
+        // the browser reports auto-repeat as further keydowns.
+        if event.repeat() {
+            return;
+        }
+        if let Some(key) = self.mapper.map_keyboard_event(event) {
+            if pressed {
+                self.press(key);
+            } else {
+                self.release(key);
+            }
+        }
+    }
+
+    fn translate_touch(&mut self, event: &PointerEvent, kind: &str) {
+        let touch = ActionTouch {
+            id: event.pointer_id() as u64,
+            x: event.client_x() as f32,
+            y: event.client_y() as f32,
+        };
+        self.inputs.push(match kind {
+            "pointerdown" => ActionInput::TouchStart(touch),
+            "pointermove" => ActionInput::TouchMove(touch),
+            "pointerup" | "pointercancel" => ActionInput::TouchEnd(touch),
+            _ => return,
+        });
+    }
+
+    fn translate_pointer_event(&mut self, event: &PointerEvent, kind: &str) {
+        let pointer_id = event.pointer_id();
+        if event.pointer_type() == "touch" {
+            self.translate_touch(event, kind);
+        }
+        match kind {
+            "pointercancel" | "lostpointercapture" => {
+                self.release_pointer(pointer_id);
+                return;
+            }
+            "pointerdown" | "pointerup" | "pointermove" => {}
+            _ => return,
+        }
+
+        if let Some(target) = self.mapper.resolve_pointer_target(event) {
+            self.inputs.push(ActionInput::CursorCoordinate(target));
+        }
+
+        // `button` is -1 for moves that don't change the set of pressed buttons.
+        let button = event.button();
+        if button < 0 {
+            return;
+        }
+        let key = match self.mapper.map_pointer_button(event, button) {
+            Some(key) => key,
+            None => return,
+        };
+        // chorded buttons arrive as pointermoves, so the pressed set decides the direction.
+        let pressed = match kind {
+            "pointerdown" => true,
+            "pointerup" => false,
+            _ => !self.pressed_keys.contains(&key),
+        };
+        if pressed {
+            if self.press(key.clone()) {
+                self.pointer_buttons
+                    .entry(pointer_id)
+                    .or_default()
+                    .push(key);
+            }
+            if self.capture_pointers {
+                if let Some(element) = event
+                    .current_target()
+                    .and_then(|target| target.dyn_into::<Element>().ok())
+                {
+                    let _ = element.set_pointer_capture(pointer_id);
+                }
+            }
+        } else {
+            if let Some(buttons) = self.pointer_buttons.get_mut(&pointer_id) {
+                buttons.retain(|x| *x != key);
+            }
+            self.release(key);
+        }
+    }
+
+    /// Accepts `keydown`, `keyup`, the `pointer*` events, `lostpointercapture` and
+    /// `blur`; anything else is ignored.
+    pub fn translate_event(&mut self, event: &Event) -> &[ActionInput<C>] {
+        self.inputs.clear();
+        let kind = event.type_();
+        match kind.as_str() {
+            "keydown" | "keyup" => {
+                if let Some(event) = event.dyn_ref::<KeyboardEvent>() {
+                    self.translate_keyboard_event(event, kind == "keydown");
+                }
+            }
+            "blur" => self.release_all(),
+            _ => {
+                if let Some(event) = event.dyn_ref::<PointerEvent>() {
+                    self.translate_pointer_event(event, &kind);
+                }
+            }
+        }
+        &self.inputs
+    }
+
+    pub fn handle_event(&mut self, context: &mut ActionContext<C>, event: &Event) -> bool {
+        self.translate_event(event);
+        context.process_inputs(&self.inputs)
+    }
+}
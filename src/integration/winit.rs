@@ -0,0 +1,150 @@
+use shared::KeySet;
+use winit::dpi::PhysicalPosition;
+use winit::event::{DeviceEvent, ElementState, KeyEvent, MouseButton, TouchPhase, WindowEvent};
+use winit::keyboard::PhysicalKey;
+use ActionConfiguration;
+use ActionContext;
+use ActionInput;
+use ActionTouch;
+
+pub trait WinitInputMapper<C: ActionConfiguration> {
+    /// Hit-tests a cursor position; `None` means no target and the move is dropped.
+    fn resolve_cursor_target(&mut self, position: PhysicalPosition<f64>) -> Option<C::Target>;
+
+    fn map_physical_key(&mut self, key: PhysicalKey) -> Option<C::KeyKind>;
+
+    fn map_key_event(&mut self, event: &KeyEvent) -> Option<C::KeyKind> {
+        self.map_physical_key(event.physical_key)
+    }
+
+    fn map_mouse_button(&mut self, button: MouseButton) -> Option<C::KeyKind>;
+
+    fn map_device_button(&mut self, _button: u32) -> Option<C::KeyKind> {
+        None
+    }
+}
+
+pub struct WinitAdapter<C: ActionConfiguration, M> {
+    mapper: M,
+    pressed_keys: KeySet<C::KeyKind>,
+    inputs: Vec<ActionInput<C>>,
+}
+
+impl<C: ActionConfiguration, M: WinitInputMapper<C>> WinitAdapter<C, M> {
+    pub fn new(mapper: M) -> Self {
+        WinitAdapter {
+            mapper,
+            pressed_keys: KeySet::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    // Window and device events may both report the same key, and the OS reports
+    // auto-repeat as further presses, so only actual state transitions are forwarded.
+    fn press(&mut self, key: C::KeyKind) {
+        if self.pressed_keys.insert(key.clone()) {
+            self.inputs.push(ActionInput::KeyDown(key));
+        }
+    }
+
+    fn release(&mut self, key: C::KeyKind) {
+        if self.pressed_keys.remove(&key) {
+            self.inputs.push(ActionInput::KeyUp(key));
+        }
+    }
+
+    fn update_key(&mut self, key: Option<C::KeyKind>, state: ElementState) {
+        match (key, state) {
+            (Some(key), ElementState::Pressed) => self.press(key),
+            (Some(key), ElementState::Released) => self.release(key),
+            (None, _) => {}
+        }
+    }
+
+    fn release_all(&mut self) {
+        let pressed_keys = ::std::mem::take(&mut self.pressed_keys);
+        self.inputs
+            .extend(pressed_keys.into_iter().map(ActionInput::KeyUp));
+    }
+
+    pub fn translate_window_event(&mut self, event: &WindowEvent) -> &[ActionInput<C>] {
+        self.inputs.clear();
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let key = self.mapper.map_key_event(event);
+                self.update_key(key, event.state);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let key = self.mapper.map_mouse_button(*button);
+                self.update_key(key, *state);
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(target) = self.mapper.resolve_cursor_target(*position) {
+                    self.inputs.push(ActionInput::CursorCoordinate(target));
+                }
+            }
+            WindowEvent::Touch(touch) => {
+                let action_touch = ActionTouch {
+                    id: touch.id,
+                    x: touch.location.x as f32,
+                    y: touch.location.y as f32,
+                };
+                self.inputs.push(match touch.phase {
+                    TouchPhase::Started => ActionInput::TouchStart(action_touch),
+                    TouchPhase::Moved => ActionInput::TouchMove(action_touch),
+                    TouchPhase::Ended | TouchPhase::Cancelled => {
+                        ActionInput::TouchEnd(action_touch)
+                    }
+                });
+            }
+            WindowEvent::Focused(false) => {
+                // releases happening while unfocused are never delivered to the window.
+                self.release_all();
+            }
+            _ => {}
+        }
+        &self.inputs
+    }
+
+    pub fn translate_device_event(&mut self, event: &DeviceEvent) -> &[ActionInput<C>] {
+        self.inputs.clear();
+        match event {
+            DeviceEvent::Key(raw) => {
+                let key = self.mapper.map_physical_key(raw.physical_key);
+                self.update_key(key, raw.state);
+            }
+            DeviceEvent::Button { button, state } => {
+                let key = self.mapper.map_device_button(*button);
+                self.update_key(key, *state);
+            }
+            _ => {}
+        }
+        &self.inputs
+    }
+
+    pub fn handle_window_event(
+        &mut self,
+        context: &mut ActionContext<C>,
+        event: &WindowEvent,
+    ) -> bool {
+        self.translate_window_event(event);
+        context.process_inputs(&self.inputs)
+    }
+
+    pub fn handle_device_event(
+        &mut self,
+        context: &mut ActionContext<C>,
+        event: &DeviceEvent,
+    ) -> bool {
+        self.translate_device_event(event);
+        context.process_inputs(&self.inputs)
+    }
+}
@@ -1,35 +1,77 @@
+#[cfg(feature = "crossterm")]
+extern crate crossterm;
+#[cfg(feature = "egui")]
+extern crate egui;
 extern crate fixedbitset;
+#[cfg(feature = "futures")]
+extern crate futures;
+#[cfg(feature = "gilrs")]
+extern crate gilrs;
 extern crate slab;
 extern crate smallvec;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 extern crate vec_drain_where;
+#[cfg(feature = "web")]
+extern crate wasm_bindgen;
+#[cfg(feature = "web")]
+extern crate web_sys;
+#[cfg(feature = "winit")]
+extern crate winit;
 #[macro_use]
 extern crate log;
 
 use std::fmt::Debug;
 
-pub trait ActionConfiguration: 'static {
+pub trait ActionConfiguration {
     type Target: Clone + PartialEq + Debug;
-    type KeyKind: Clone + PartialEq + Ord + Debug;
+    type KeyKind: ActionKey;
     type CursorPos: Clone + PartialEq;
+    /// Application state custom conditions can read, changed through
+    /// `ActionContext::env_mut`. Use `()` if there is none.
+    type EnvExt: Default;
 
-    type Command: Clone;
+    type Command;
+
+    /// Whether a reported target counts as the one a recipe expects, e.g. any
+    /// descendant of an expected panel. Defaults to equality.
+    fn target_matches(expected: &Self::Target, reported: &Self::Target) -> bool {
+        expected == reported
+    }
 }
 
+mod analysis;
+mod compiled;
 mod context;
+mod error;
 mod execution;
+pub mod gestures;
+pub mod integration;
+mod lifecycle;
+pub mod presets;
 mod recipe;
-
+pub mod replay;
+mod shared;
+#[cfg(feature = "futures")]
+pub mod stream;
+pub mod testing;
+mod time;
+mod validation;
+
+pub use analysis::{ActionBindingInput, ActionRecipeBinding, ActionRecipeConflict};
 pub use context::*;
+pub use error::ConcertoError;
+pub use lifecycle::{ActionAbortReason, ActionLifecycleObserver, ActionRecipeMetrics};
 pub use recipe::*;
+pub use shared::{
+    ActionHashedKeys, ActionKey, ActionKeyStorage, ActionOrderedKeys, MaybeSend, MaybeSendSync,
+    Shared,
+};
+pub use time::{ActionSystemClock, ActionTimeSource};
 
 /*
 
 
-enum TargetOrFilter<T> {
-    Target(T),
-    TargetFilter(Box<Fn(T) -> bool>),
-}
-
 pub struct ActionRecipeBuilder<'a, T: ActionConfiguration> {
     context: &'a mut ActionContext<T>,
     conditions: Vec<ActionRecipeCondition<T>>,
@@ -47,21 +89,6 @@ impl<'a> ActionRecipeBuilder<'a, T> {
     pub fn add_condition(&mut self, condition: ActionRecipeCondition<T>) {
         self.conditions.push(condition);
     }
-
-
-    pub fn with_target(&mut self, target: T) -> &mut Self
-        where T: PartialEq
-    {
-        self.target = Some(TargetOrFilter::Target(target));
-        self
-    }
-
-    pub fn with_target_filter<F>(target_filter: F) -> &mut Self
-        where F: Fn(T) -> bool
-    {
-        self.target = Some(TargetOrFilter::TargetFilter(Box::new(target_filter) as _));
-        self
-    }
 }
 
 impl<'a> ActionRecipeBuilder<'a, T> where T: KeyStrokeActionConfiguration + CursorActionConfiguration{
@@ -82,11 +109,4 @@ impl<'a> ActionRecipeBuilder<'a, T> where T: KeyStrokeActionConfiguration + Curs
 
 
 
-pub trait TargetResolver {
-    type Target;
-
-    fn resolve_target(&self, point: Point) -> Self::Target;
-}
-
-
 */
@@ -0,0 +1,162 @@
+use context::ActionRecipeItemIdx;
+use recipe::{ActionRecipe, ActionRecipeLabel};
+use shared::{GeneratorCell, MaybeSend};
+use std::time::Duration;
+use ActionConfiguration;
+
+/// Why an execution ended without completing its recipe.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ActionAbortReason {
+    /// The input contradicted what the recipe expected or had already matched.
+    Input,
+    Timeout,
+    /// Another recipe completed, which resets every other execution.
+    Superseded,
+    /// The recipe was nested in an execution that ended.
+    ParentEnded,
+    /// Executing the recipe failed and it has been disabled.
+    Broken,
+    /// Cancelled through `ActionContext::cancel_all` or `reset`.
+    Cancelled,
+    /// The application lost input focus, see `ActionContext::notify_focus_lost`.
+    FocusLost,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum ActionExecutionEvent {
+    /// The execution consumed its first input and is now waiting in the given item,
+    /// or is about to complete.
+    Started(Option<ActionRecipeItemIdx>),
+    /// The execution consumed another input, likewise.
+    Advanced(Option<ActionRecipeItemIdx>),
+    /// The execution completed, the given time after it started.
+    Completed(Duration),
+    Aborted(ActionAbortReason),
+}
+
+/// Receives the lifecycle of every execution, registered through
+/// `ActionContext::add_lifecycle_observer`. All methods do nothing by default.
+pub trait ActionLifecycleObserver {
+    /// The recipe consumed the first input of a new execution.
+    fn on_started(&mut self, _recipe: ActionRecipeLabel) {}
+
+    /// The execution consumed another input and now waits in `item`, or is about to
+    /// complete if it is `None`.
+    fn on_advanced(&mut self, _recipe: ActionRecipeLabel, _item: Option<ActionRecipeItemIdx>) {}
+
+    fn on_completed(&mut self, _recipe: ActionRecipeLabel) {}
+
+    fn on_aborted(&mut self, _recipe: ActionRecipeLabel, _reason: ActionAbortReason) {}
+}
+
+pub(crate) trait ActionLifecycleObserverObj: ActionLifecycleObserver + MaybeSend {}
+
+impl<O: ActionLifecycleObserver + MaybeSend> ActionLifecycleObserverObj for O {}
+
+pub(crate) type ActionLifecycleObserverList<'f> =
+    GeneratorCell<Vec<Box<dyn ActionLifecycleObserverObj + 'f>>>;
+
+/// Usage counters of a single recipe. Times are measured on the context's clock,
+/// which only moves forward through `ActionContext::advance_time`.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct ActionRecipeMetrics {
+    started: u64,
+    completed: u64,
+    aborted: u64,
+    inputs_consumed: u64,
+    total_time_to_complete: Duration,
+}
+
+impl ActionRecipeMetrics {
+    pub fn started(&self) -> u64 {
+        self.started
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    pub fn aborted(&self) -> u64 {
+        self.aborted
+    }
+
+    pub fn inputs_consumed(&self) -> u64 {
+        self.inputs_consumed
+    }
+
+    pub fn mean_time_to_complete(&self) -> Option<Duration> {
+        if self.completed == 0 {
+            return None;
+        }
+        let mean_nanos = self.total_time_to_complete.as_nanos() / u128::from(self.completed);
+        Some(Duration::from_nanos(mean_nanos as u64))
+    }
+
+    fn record(&mut self, event: ActionExecutionEvent) {
+        match event {
+            ActionExecutionEvent::Started(_) => {
+                self.started += 1;
+                self.inputs_consumed += 1;
+            }
+            ActionExecutionEvent::Advanced(_) => self.inputs_consumed += 1,
+            ActionExecutionEvent::Completed(time_to_complete) => {
+                self.completed += 1;
+                self.total_time_to_complete += time_to_complete;
+            }
+            ActionExecutionEvent::Aborted(_) => self.aborted += 1,
+        }
+    }
+}
+
+/// Updates the recipe metrics with an execution lifecycle event, hands it to the
+/// observers and reports it as a `tracing` event when that feature is enabled, or
+/// through `log` otherwise.
+pub(crate) fn record_execution_event<C: ActionConfiguration>(
+    observers: &ActionLifecycleObserverList,
+    recipe: &mut ActionRecipe<C>,
+    recipe_idx: usize,
+    event: ActionExecutionEvent,
+) {
+    recipe.metrics.record(event);
+    let recipe = recipe.label(recipe_idx);
+    observers.with(|observers| {
+        for observer in observers.iter_mut() {
+            match event {
+                ActionExecutionEvent::Started(_) => observer.on_started(recipe),
+                ActionExecutionEvent::Advanced(item) => observer.on_advanced(recipe, item),
+                ActionExecutionEvent::Completed(_) => observer.on_completed(recipe),
+                ActionExecutionEvent::Aborted(reason) => observer.on_aborted(recipe, reason),
+            }
+        }
+    });
+    #[cfg(feature = "tracing")]
+    match event {
+        ActionExecutionEvent::Started(item) => {
+            tracing::debug!(target: "concerto", recipe = %recipe, item = ?item, "execution started")
+        }
+        ActionExecutionEvent::Advanced(item) => {
+            tracing::trace!(target: "concerto", recipe = %recipe, item = ?item, "execution advanced")
+        }
+        ActionExecutionEvent::Completed(time_to_complete) => {
+            tracing::debug!(target: "concerto", recipe = %recipe, time_to_complete = ?time_to_complete, "execution completed")
+        }
+        ActionExecutionEvent::Aborted(reason) => {
+            tracing::debug!(target: "concerto", recipe = %recipe, reason = ?reason, "execution aborted")
+        }
+    }
+    #[cfg(not(feature = "tracing"))]
+    match event {
+        ActionExecutionEvent::Started(item) => {
+            debug!(target: "concerto", "recipe {} started, at {:?}.", recipe, item)
+        }
+        ActionExecutionEvent::Advanced(item) => {
+            trace!(target: "concerto", "recipe {} advanced, at {:?}.", recipe, item)
+        }
+        ActionExecutionEvent::Completed(time_to_complete) => {
+            debug!(target: "concerto", "recipe {} completed after {:?}.", recipe, time_to_complete)
+        }
+        ActionExecutionEvent::Aborted(reason) => {
+            debug!(target: "concerto", "recipe {} aborted: {:?}.", recipe, reason)
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use error::ConcertoError;
+use recipe::{ActionInput, ActionPinch, ActionRecipe, ActionRecipeBuilder, ActionUpdateRate};
+use shared::{MaybeSend, MaybeSendSync};
+use std::time::Duration;
+use ActionConfiguration;
+
+/// Issues `command` once `button`, a pointer button reported as a key, is pressed and
+/// released with the cursor staying over a target accepted by `target_filter`.
+pub fn click<'a, 'f, C, F>(
+    builder: ActionRecipeBuilder<'a, 'f, C>,
+    button: C::KeyKind,
+    target_filter: F,
+    command: C::Command,
+) -> Result<ActionRecipe<C>, ConcertoError>
+where
+    C: ActionConfiguration,
+    F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+{
+    builder
+        .keep_cursor_over(target_filter)
+        .add_key_down_input(button.clone())
+        .add_key_up_input(button)
+        .issue_command(command)
+        .build()
+}
+
+/// Issues `effect_start` once `button` is pressed over a target accepted by
+/// `target_filter`, wherever the cursor moves afterwards, and `effect_end` once it is
+/// released, or when the drag is aborted.
+pub fn drag<'a, 'f, C, F>(
+    builder: ActionRecipeBuilder<'a, 'f, C>,
+    button: C::KeyKind,
+    target_filter: F,
+    effect_start: C::Command,
+    effect_end: C::Command,
+) -> Result<ActionRecipe<C>, ConcertoError>
+where
+    C: ActionConfiguration,
+    F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+{
+    builder
+        .check_cursor_over(target_filter)
+        .add_key_down_input(button.clone())
+        .issue_effect(effect_start, effect_end)
+        .add_key_up_input(button)
+        .build()
+}
+
+/// Issues `command` once `button` is clicked twice over a target accepted by
+/// `target_filter`, the second release coming within `interval` of the first press.
+/// A click recipe on the same button resets it after the first click unless that
+/// recipe is `repeatable`.
+pub fn double_click<'a, 'f, C, F>(
+    builder: ActionRecipeBuilder<'a, 'f, C>,
+    button: C::KeyKind,
+    target_filter: F,
+    interval: Duration,
+    command: C::Command,
+) -> Result<ActionRecipe<C>, ConcertoError>
+where
+    C: ActionConfiguration,
+    F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+{
+    builder
+        .with_timeout(interval)
+        .keep_cursor_over(target_filter)
+        .add_key_down_input(button.clone())
+        .add_key_up_input(button.clone())
+        .add_key_down_input(button.clone())
+        .add_key_up_input(button)
+        .issue_command(command)
+        .build()
+}
+
+/// Drags from a target accepted by `source_filter` with `button` held, and drops on
+/// one accepted by `drop_filter`. `command_generator` receives the source and drop
+/// targets.
+pub fn drag_and_drop<'a, 'f, C, F, G, H>(
+    builder: ActionRecipeBuilder<'a, 'f, C>,
+    button: C::KeyKind,
+    source_filter: F,
+    drop_filter: G,
+    mut command_generator: H,
+) -> Result<ActionRecipe<C>, ConcertoError>
+where
+    C: ActionConfiguration,
+    F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    G: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    H: FnMut(&C::Target, &C::Target) -> C::Command + MaybeSend + 'f,
+{
+    builder
+        .check_cursor_over(source_filter)
+        .add_key_down_input(button.clone())
+        .capture_cursor_target()
+        .optionally_repeatedly(|b| b.add_cursor_coordinate_filtered_input(|_| true))
+        .add_key_up_input(button)
+        .check_cursor_over(drop_filter)
+        .capture_cursor_target()
+        .issue_command_with(move |info| {
+            let mut targets = info.matched_inputs().filter_map(|(_, input)| match input {
+                ActionInput::CursorCoordinate(target) => Some(target),
+                _ => None,
+            });
+            // both targets were captured, around any cursor moves in between.
+            let source = targets.next().expect("source target was captured");
+            let target = targets.last().unwrap_or(source);
+            (command_generator)(source, target)
+        })
+        .build()
+}
+
+/// Follows the first two fingers to touch down, issuing the command `update_generator`
+/// makes from their pinch as they move, no more often than `rate` allows, and the one
+/// `commit_generator` makes once one of them lifts. Other fingers are ignored. Fails
+/// if the first finger lifted before the second touched down.
+pub fn pinch<'a, 'f, C, F, G>(
+    builder: ActionRecipeBuilder<'a, 'f, C>,
+    rate: ActionUpdateRate,
+    mut update_generator: F,
+    mut commit_generator: G,
+) -> Result<ActionRecipe<C>, ConcertoError>
+where
+    C: ActionConfiguration,
+    F: FnMut(ActionPinch) -> C::Command + MaybeSend + 'f,
+    G: FnMut(ActionPinch) -> C::Command + MaybeSend + 'f,
+{
+    builder
+        .add_touch_start_filtered_input(|_| true)
+        .optionally_repeatedly(|b| b.add_matched_touch_move_input())
+        .add_touch_start_filtered_input(|_| true)
+        .check_condition_with(|env| env.touches().len() >= 2)
+        .check_execution(|info| info.matched_pinch().is_some())
+        // the pinch stays known from here on, matched inputs are never forgotten.
+        .issue_updates_with_rate(rate, move |info| {
+            (update_generator)(info.matched_pinch().unwrap_or_default())
+        })
+        .optionally_repeatedly(|b| b.add_matched_touch_move_input())
+        .add_matched_touch_end_input()
+        .issue_command_with(move |info| {
+            (commit_generator)(info.matched_pinch().unwrap_or_default())
+        })
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pinch;
+    use recipe::{ActionTouch, ActionUpdateRate};
+    use testing::Harness;
+    use ActionConfiguration;
+    use ActionContextBuilder;
+    use ActionInput::{TouchEnd, TouchMove, TouchStart};
+
+    struct Config;
+
+    impl ActionConfiguration for Config {
+        type Target = ();
+        type KeyKind = char;
+        type CursorPos = ();
+        type Command = String;
+        type EnvExt = ();
+    }
+
+    fn touch(id: u64, x: f32) -> ActionTouch {
+        ActionTouch { id, x, y: 0.0 }
+    }
+
+    #[test]
+    fn pinch_ignores_other_fingers() {
+        let builder = ActionContextBuilder::<Config>::new().add_recipe(|b| {
+            pinch(
+                b,
+                ActionUpdateRate::EveryInput,
+                |pinch| format!("scale {}", pinch.scale),
+                |pinch| format!("commit {}", pinch.scale),
+            )
+        });
+        Harness::new(builder)
+            .feed(vec![TouchStart(touch(1, 0.0)), TouchStart(touch(2, 10.0))])
+            .feed(vec![TouchStart(touch(3, 50.0)), TouchMove(touch(3, 60.0))])
+            .feed(vec![TouchEnd(touch(3, 60.0))])
+            .expect_no_commands()
+            .feed(vec![TouchMove(touch(2, 20.0)), TouchEnd(touch(1, 0.0))])
+            .expect_commands(vec![
+                "scale 2".to_string(),
+                "scale 2".to_string(),
+                "commit 2".to_string(),
+            ])
+            .expect_no_pending();
+    }
+}
@@ -1,10 +1,24 @@
+use analysis::may_start_with_cursor_input;
+use compiled::ActionCompiledRecipe;
 use context::ActionContextBuilder;
+use context::ActionEnvironmentView;
+use context::ActionExecutionId;
 use context::ActionRecipeItemIdx;
+use context::ActionRecipeItemStore;
+use error::ConcertoError;
 use execution::ActionExecutionCtx;
 use execution::ActionRecipeExecutionInfo;
 use execution::ExecutionContextResult;
+use execution::DEFAULT_INLINE_CONTRACT_LIMIT;
+use lifecycle::ActionRecipeMetrics;
+use shared::{GeneratorCell, MaybeSend, MaybeSendSync, Shared};
 use smallvec::SmallVec;
+use std::fmt;
 use std::marker::PhantomData;
+use std::mem;
+use std::ops;
+use std::time::Duration;
+use validation::validate_recipe;
 
 use ActionConfiguration;
 
@@ -12,39 +26,315 @@ pub struct ActionRecipe<C: ActionConfiguration> {
     pub(crate) root_item: ActionRecipeItemIdx,
     pub(crate) is_nested: bool,
     pub(crate) is_enabled: bool,
+    pub(crate) is_broken: bool,
     pub(crate) nest_recipes: Vec<usize>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) name: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) category: Option<String>,
+    pub(crate) metrics: ActionRecipeMetrics,
+    pub(crate) compiled: Option<Shared<ActionCompiledRecipe>>,
+    pub(crate) inline_contract_limit: usize,
+    pub(crate) strictness: ActionRecipeStrictness,
+    pub(crate) consumes_inputs: bool,
+    pub(crate) is_repeatable: bool,
+    pub(crate) input_buffer: Option<Duration>,
+    pub(crate) prefix: Option<usize>,
+    pub(crate) arms_prefix: Option<usize>,
+    pub(crate) on_abort: Vec<ActionRecipeItemIdx>,
+    pub(crate) target_guard: Option<ActionRecipeItemIdx>,
+    pub(crate) dwell_durations: Vec<Duration>,
+    pub(crate) starts_with_cursor: bool,
     phantom: PhantomData<C>,
 }
 
-pub struct ActionRecipeCommand<C: ActionConfiguration>(C::Command);
+/// How a running execution treats inputs that none of its expected items match.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ActionRecipeStrictness {
+    /// Unexpected inputs are ignored.
+    #[default]
+    Lenient,
+    /// An unexpected key input aborts the execution, other inputs are ignored.
+    StrictKeys,
+    /// Any unexpected input aborts the execution.
+    Strict,
+}
+
+impl ActionRecipeStrictness {
+    pub(crate) fn rejects<C: ActionConfiguration>(self, input: &ActionInput<C>) -> bool {
+        match self {
+            ActionRecipeStrictness::Lenient => false,
+            ActionRecipeStrictness::StrictKeys => {
+                matches!(
+                    input,
+                    ActionInput::KeyDown(_) | ActionInput::KeyUp(_) | ActionInput::KeyRepeat(_)
+                )
+            }
+            ActionRecipeStrictness::Strict => true,
+        }
+    }
+}
+
+/// What a filter passed to `add_filtered_input` decides about an input.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ActionMatchDecision {
+    /// The input matches the item.
+    Match,
+    /// The input is not meant for the item, which keeps waiting.
+    Ignore,
+    /// The input breaks the item, aborting the execution.
+    Reject,
+}
+
+impl From<ActionMatchDecision> for ExecutionContextResult {
+    fn from(decision: ActionMatchDecision) -> Self {
+        match decision {
+            ActionMatchDecision::Match => ExecutionContextResult::Used,
+            ActionMatchDecision::Ignore => ExecutionContextResult::Ignore,
+            ActionMatchDecision::Reject => ExecutionContextResult::Abort,
+        }
+    }
+}
+
+/// How often an update generator added with `issue_updates_with_rate` issues a
+/// command. Updates skipped to keep the rate are made up for by a single one as soon
+/// as the rate allows it again, on an input or when the time advances.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ActionUpdateRate {
+    /// For every matched input.
+    #[default]
+    EveryInput,
+    /// At most once per context time step, i.e. between two `advance_time` calls.
+    OncePerTick,
+    /// At most once in the given span of context time.
+    AtMostEvery(Duration),
+}
+
+impl ActionUpdateRate {
+    /// Whether an update issued at `issued_at` lets another one be issued at `now`.
+    pub(crate) fn allows(self, issued_at: Option<Duration>, now: Duration) -> bool {
+        match (self, issued_at) {
+            (ActionUpdateRate::EveryInput, _) | (_, None) => true,
+            (ActionUpdateRate::OncePerTick, Some(issued_at)) => issued_at < now,
+            (ActionUpdateRate::AtMostEvery(interval), Some(issued_at)) => {
+                issued_at + interval <= now
+            }
+        }
+    }
+}
+
+impl<C: ActionConfiguration> ActionRecipe<C> {
+    /// Whether the recipe sees `input`, i.e. it targets nothing or a target its
+    /// target guard accepts.
+    pub(crate) fn admits(
+        &self,
+        recipe_items: &ActionRecipeItemStore<C>,
+        input: &ActionInput<C>,
+    ) -> Result<bool, ConcertoError> {
+        let guard_idx = match self.target_guard {
+            Some(guard_idx) => guard_idx,
+            None => return Ok(true),
+        };
+        let guard = match recipe_items.get(guard_idx)? {
+            ActionRecipeItem::TargetGuard(guard) => guard,
+            _ => return Err(ConcertoError::UnexpectedItem(guard_idx)),
+        };
+        Ok(match input {
+            ActionInput::CursorCoordinate(target)
+            | ActionInput::FocusCoordinate(target)
+            | ActionInput::Stylus(target, _) => guard.matches(target),
+            _ => true,
+        })
+    }
+
+    pub(crate) fn metadata(&self) -> ActionRecipeMetadata<'_> {
+        ActionRecipeMetadata {
+            name: self.name.as_deref(),
+            description: self.description.as_deref(),
+            category: self.category.as_deref(),
+        }
+    }
+
+    pub(crate) fn label(&self, recipe_idx: usize) -> ActionRecipeLabel<'_> {
+        ActionRecipeLabel {
+            recipe_idx,
+            name: self.name.as_deref(),
+            execution_id: None,
+        }
+    }
+}
+
+/// Identifies a recipe of a context in logs and command provenance, displayed as
+/// its name if it was given one and as `#<index>` otherwise.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ActionRecipeLabel<'a> {
+    recipe_idx: usize,
+    name: Option<&'a str>,
+    execution_id: Option<ActionExecutionId>,
+}
+
+impl<'a> ActionRecipeLabel<'a> {
+    pub(crate) fn named(recipe_idx: usize, name: &'a str) -> Self {
+        ActionRecipeLabel {
+            recipe_idx,
+            name: Some(name),
+            execution_id: None,
+        }
+    }
+
+    pub(crate) fn with_execution_id(mut self, execution_id: Option<ActionExecutionId>) -> Self {
+        self.execution_id = execution_id;
+        self
+    }
+
+    pub fn index(&self) -> usize {
+        self.recipe_idx
+    }
+
+    pub fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+
+    /// The execution that issued the command, for the labels command provenance
+    /// comes with.
+    pub fn execution_id(&self) -> Option<ActionExecutionId> {
+        self.execution_id
+    }
+}
+
+impl<'a> fmt::Display for ActionRecipeLabel<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "#{}", self.recipe_idx),
+        }
+    }
+}
+
+/// The user-facing metadata of a recipe, see `ActionContext::recipe_metadata`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ActionRecipeMetadata<'a> {
+    name: Option<&'a str>,
+    description: Option<&'a str>,
+    category: Option<&'a str>,
+}
+
+impl<'a> ActionRecipeMetadata<'a> {
+    pub fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+
+    pub fn description(&self) -> Option<&'a str> {
+        self.description
+    }
+
+    pub fn category(&self) -> Option<&'a str> {
+        self.category
+    }
+}
+
+pub struct ActionRecipeCommand<C: ActionConfiguration>(Shared<C::Command>);
 
 impl<C: ActionConfiguration> ActionRecipeCommand<C> {
-    pub(crate) fn command(&self) -> &C::Command {
+    pub(crate) fn command(&self) -> &Shared<C::Command> {
         &self.0
     }
 }
 
+impl<C: ActionConfiguration> Clone for ActionRecipe<C> {
+    fn clone(&self) -> Self {
+        ActionRecipe {
+            root_item: self.root_item,
+            is_nested: self.is_nested,
+            is_enabled: self.is_enabled,
+            is_broken: self.is_broken,
+            nest_recipes: self.nest_recipes.clone(),
+            timeout: self.timeout,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            category: self.category.clone(),
+            metrics: self.metrics.clone(),
+            compiled: self.compiled.clone(),
+            inline_contract_limit: self.inline_contract_limit,
+            strictness: self.strictness,
+            consumes_inputs: self.consumes_inputs,
+            is_repeatable: self.is_repeatable,
+            input_buffer: self.input_buffer,
+            prefix: self.prefix,
+            arms_prefix: self.arms_prefix,
+            on_abort: self.on_abort.clone(),
+            target_guard: self.target_guard,
+            dwell_durations: self.dwell_durations.clone(),
+            starts_with_cursor: self.starts_with_cursor,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C: ActionConfiguration> fmt::Debug for ActionRecipe<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("ActionRecipe")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("category", &self.category)
+            .field("root_item", &self.root_item)
+            .field("is_nested", &self.is_nested)
+            .field("is_enabled", &self.is_enabled)
+            .field("is_broken", &self.is_broken)
+            .field("nest_recipes", &self.nest_recipes)
+            .field("timeout", &self.timeout)
+            .field("strictness", &self.strictness)
+            .field("consumes_inputs", &self.consumes_inputs)
+            .field("is_repeatable", &self.is_repeatable)
+            .field("input_buffer", &self.input_buffer)
+            .field("prefix", &self.prefix)
+            .field("arms_prefix", &self.arms_prefix)
+            .field("on_abort", &self.on_abort)
+            .field("target_guard", &self.target_guard)
+            .field("dwell_durations", &self.dwell_durations)
+            .field("starts_with_cursor", &self.starts_with_cursor)
+            .field("is_compiled", &self.compiled.is_some())
+            .finish()
+    }
+}
+
 impl<C: ActionConfiguration> Clone for ActionRecipeCommand<C> {
     fn clone(&self) -> Self {
         ActionRecipeCommand(self.0.clone())
     }
 }
 
-pub struct ActionRecipeEffect<C: ActionConfiguration>(C::Command, C::Command);
+/// Commands able to carry the command that undoes them, as issued by
+/// `issue_undoable_command`.
+pub trait ActionUndoableCommand: Sized {
+    fn with_inverse(command: Self, inverse: Self) -> Self;
+}
+
+/// An effect's start command, the end command issued once it ends normally, and the
+/// one issued instead when the execution is aborted.
+pub struct ActionRecipeEffect<C: ActionConfiguration>(
+    Shared<C::Command>,
+    Shared<C::Command>,
+    Shared<C::Command>,
+);
 
 impl<C: ActionConfiguration> ActionRecipeEffect<C> {
-    pub(crate) fn effect_start(&self) -> &C::Command {
+    pub(crate) fn effect_start(&self) -> &Shared<C::Command> {
         &self.0
     }
 
-    pub(crate) fn effect_end(&self) -> &C::Command {
+    pub(crate) fn effect_end(&self) -> &Shared<C::Command> {
         &self.1
     }
+
+    pub(crate) fn effect_abort_end(&self) -> &Shared<C::Command> {
+        &self.2
+    }
 }
 
 impl<C: ActionConfiguration> Clone for ActionRecipeEffect<C> {
     fn clone(&self) -> Self {
-        ActionRecipeEffect(self.0.clone(), self.1.clone())
+        ActionRecipeEffect(self.0.clone(), self.1.clone(), self.2.clone())
     }
 }
 
@@ -54,77 +344,318 @@ pub(crate) enum ActionNestRecipeCommand {
     Abort(usize, usize),
 }
 
-pub(crate) enum ActionRecipeItem<C: ActionConfiguration> {
-    StartInput(ActionInput<C>),
-    StartFilteredInput(Rc<dyn Fn(&ActionInput<C>) -> ExecutionContextResult>),
-    StartCondition(ActionCondition<C>),
+pub(crate) trait ActionInputFilterFn<C: ActionConfiguration>:
+    Fn(&ActionInput<C>) -> ExecutionContextResult + MaybeSendSync
+{
+}
+
+impl<C: ActionConfiguration, F> ActionInputFilterFn<C> for F where
+    F: Fn(&ActionInput<C>) -> ExecutionContextResult + MaybeSendSync
+{
+}
+
+pub(crate) trait ActionExecutionInputFilterFn<C: ActionConfiguration>:
+    Fn(ActionRecipeExecutionInfo<C>, &ActionInput<C>) -> ExecutionContextResult + MaybeSendSync
+{
+}
+
+impl<C: ActionConfiguration, F> ActionExecutionInputFilterFn<C> for F where
+    F: Fn(ActionRecipeExecutionInfo<C>, &ActionInput<C>) -> ExecutionContextResult + MaybeSendSync
+{
+}
+
+pub(crate) trait ActionEffectGeneratorFn<C: ActionConfiguration>:
+    FnMut(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command) + MaybeSend
+{
+}
+
+impl<C: ActionConfiguration, F> ActionEffectGeneratorFn<C> for F where
+    F: FnMut(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command) + MaybeSend
+{
+}
+
+pub(crate) trait ActionCommandGeneratorFn<C: ActionConfiguration>:
+    FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend
+{
+}
+
+impl<C: ActionConfiguration, F> ActionCommandGeneratorFn<C> for F where
+    F: FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend
+{
+}
+
+pub(crate) trait ActionTargetFilterFn<C: ActionConfiguration>:
+    Fn(&C::Target) -> bool + MaybeSendSync
+{
+}
+
+impl<C: ActionConfiguration, F> ActionTargetFilterFn<C> for F where
+    F: Fn(&C::Target) -> bool + MaybeSendSync
+{
+}
+
+pub(crate) trait ActionEnvironmentPredicateFn<C: ActionConfiguration>:
+    Fn(&ActionEnvironmentView<C>) -> bool + MaybeSendSync
+{
+}
+
+impl<C: ActionConfiguration, F> ActionEnvironmentPredicateFn<C> for F where
+    F: Fn(&ActionEnvironmentView<C>) -> bool + MaybeSendSync
+{
+}
+
+pub(crate) trait ActionExecutionPredicateFn<C: ActionConfiguration>:
+    Fn(ActionRecipeExecutionInfo<C>) -> bool + MaybeSendSync
+{
+}
+
+impl<C: ActionConfiguration, F> ActionExecutionPredicateFn<C> for F where
+    F: Fn(ActionRecipeExecutionInfo<C>) -> bool + MaybeSendSync
+{
+}
+
+pub(crate) trait ActionKeyCharacterFn<C: ActionConfiguration>:
+    Fn(&C::KeyKind) -> Option<char> + MaybeSendSync
+{
+}
+
+impl<C: ActionConfiguration, F> ActionKeyCharacterFn<C> for F where
+    F: Fn(&C::KeyKind) -> Option<char> + MaybeSendSync
+{
+}
+
+pub(crate) type ActionInputFilter<'f, C> = Shared<dyn ActionInputFilterFn<C> + 'f>;
+
+pub(crate) type ActionExecutionInputFilter<'f, C> =
+    Shared<dyn ActionExecutionInputFilterFn<C> + 'f>;
+
+pub(crate) type ActionKeyCharacterMap<'f, C> = Shared<dyn ActionKeyCharacterFn<C> + 'f>;
+
+pub(crate) type ActionExecutionPredicate<'f, C> = Shared<dyn ActionExecutionPredicateFn<C> + 'f>;
+
+pub(crate) type ActionEffectGenerator<'f, C> =
+    GeneratorCell<Box<dyn ActionEffectGeneratorFn<C> + 'f>>;
+
+pub(crate) type ActionCommandGenerator<'f, C> =
+    GeneratorCell<Box<dyn ActionCommandGeneratorFn<C> + 'f>>;
+
+pub(crate) enum ActionRecipeItem<'f, C: ActionConfiguration> {
+    StartInput(Shared<ActionInput<C>>),
+    StartFilteredInput(ActionInputFilter<'f, C>),
+    /// Like `StartFilteredInput`, but the filter also sees what the execution matched.
+    StartExecutionFilteredInput(ActionExecutionInputFilter<'f, C>),
+    /// Matches a cursor input once the duration passed since the last matched input;
+    /// also offered the tracked cursor target as time advances.
+    StartDwell(Duration),
+    /// Matches any input once the duration passed since the last matched input; like
+    /// a dwell, also offered the tracked cursor target as time advances.
+    StartIdle(Duration),
+    /// Matches a cursor input that moves onto (`true`) or off (`false`) the targets
+    /// the filter accepts.
+    StartCursorTransition(ActionTargetFilter<'f, C>, bool),
+    /// Matches a `KeyUp` of the key once it was held for the duration; the decision
+    /// says what an earlier release does.
+    StartHeldKeyUp(C::KeyKind, Duration, ActionMatchDecision),
+    /// Matches a value of the axis that is past (`true`) or short of (`false`) the threshold.
+    StartAxisInput(C::KeyKind, f32, bool),
+    /// Matches a `Character` input or, given a key map, a pressed key the map turns into
+    /// a character; either is recorded as a `Character` input.
+    StartCharacterInput(Option<ActionKeyCharacterMap<'f, C>>),
+    StartCondition(ActionCondition<'f, C>),
+    /// Checked once against what the execution matched, storing no contract.
+    CheckExecution(ActionExecutionPredicate<'f, C>),
+    /// Records the tracked cursor target as a matched cursor input, failing like a
+    /// condition while none is known.
+    CaptureCursorTarget,
     StartEffect(ActionRecipeEffect<C>),
-    StartEffectOf(Box<dyn Fn(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command)>),
+    StartEffectOf(ActionEffectGenerator<'f, C>),
+    /// Issues the generated command for every further input the execution matches,
+    /// until the contract it stores is eliminated.
+    StartUpdatesOf(ActionCommandGenerator<'f, C>, ActionUpdateRate),
     StartNestRecipe(usize),
     DisableNestRecipe(usize),
     EliminateItem(ActionRecipeItemIdx),
+    Checkpoint,
     DoCommand(ActionRecipeCommand<C>),
-    DoCommandOf(Box<dyn Fn(ActionRecipeExecutionInfo<C>) -> C::Command>),
+    DoCommandOf(ActionCommandGenerator<'f, C>),
+    /// The filter of `with_target_filter`, held by the recipe rather than its item tree.
+    TargetGuard(ActionTargetFilter<'f, C>),
     Sequential(SmallVec<[ActionRecipeItemIdx; 3]>),
-    Unordered(SmallVec<[ActionRecipeItemIdx; 3]>),
+    /// Completes once the given number of its children matched.
+    Unordered(SmallVec<[ActionRecipeItemIdx; 3]>, usize),
     Choice(SmallVec<[ActionRecipeItemIdx; 3]>),
+    Optional(SmallVec<[ActionRecipeItemIdx; 3]>),
+    Repeated(SmallVec<[ActionRecipeItemIdx; 3]>, ActionRecipeRepetition),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum ActionRecipeRepetition {
+    ZeroOrMore,
+    OneOrMore,
 }
 
-impl<C: ActionConfiguration> ActionRecipeItem<C> {
+impl<'f, C: ActionConfiguration> ActionRecipeItem<'f, C> {
     pub(crate) fn is_interactive(&self) -> bool {
-        match self {
-            ActionRecipeItem::StartInput(_) => true,
-            ActionRecipeItem::StartFilteredInput(_) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            ActionRecipeItem::StartInput(_)
+                | ActionRecipeItem::StartFilteredInput(_)
+                | ActionRecipeItem::StartExecutionFilteredInput(_)
+                | ActionRecipeItem::StartDwell(_)
+                | ActionRecipeItem::StartIdle(_)
+                | ActionRecipeItem::StartHeldKeyUp(..)
+                | ActionRecipeItem::StartCursorTransition(..)
+                | ActionRecipeItem::StartAxisInput(..)
+                | ActionRecipeItem::StartCharacterInput(_)
+        )
     }
 
     pub(crate) fn is_condition(&self) -> bool {
-        match self {
-            ActionRecipeItem::StartCondition(_) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            ActionRecipeItem::StartCondition(_)
+                | ActionRecipeItem::CheckExecution(_)
+                | ActionRecipeItem::CaptureCursorTarget
+        )
     }
 
     pub(crate) fn is_noninteractive(&self) -> bool {
-        match self {
+        matches!(
+            self,
             ActionRecipeItem::EliminateItem(_)
-            | ActionRecipeItem::DoCommand(_)
-            | ActionRecipeItem::DoCommandOf(_)
-            | ActionRecipeItem::StartEffect(_)
-            | ActionRecipeItem::StartEffectOf(_)
-            | ActionRecipeItem::StartNestRecipe(_)
-            | ActionRecipeItem::DisableNestRecipe(_) => true,
-            _ => false,
-        }
+                | ActionRecipeItem::Checkpoint
+                | ActionRecipeItem::DoCommand(_)
+                | ActionRecipeItem::DoCommandOf(_)
+                | ActionRecipeItem::StartEffect(_)
+                | ActionRecipeItem::StartEffectOf(_)
+                | ActionRecipeItem::StartUpdatesOf(..)
+                | ActionRecipeItem::StartNestRecipe(_)
+                | ActionRecipeItem::DisableNestRecipe(_)
+        )
     }
 
     pub(crate) fn is_compound(&self) -> bool {
-        match self {
+        matches!(
+            self,
             ActionRecipeItem::Sequential(_)
-            | ActionRecipeItem::Unordered(_)
-            | ActionRecipeItem::Choice(_) => true,
-            _ => false,
-        }
+                | ActionRecipeItem::Unordered(..)
+                | ActionRecipeItem::Choice(_)
+                | ActionRecipeItem::Optional(_)
+                | ActionRecipeItem::Repeated(..)
+        )
     }
 
-    pub(crate) fn compound_sequence(&self) -> &[ActionRecipeItemIdx] {
+    pub(crate) fn compound_sequence(
+        &self,
+        item_idx: ActionRecipeItemIdx,
+    ) -> Result<&[ActionRecipeItemIdx], ConcertoError> {
         match self {
-            ActionRecipeItem::Sequential(seq) => &seq,
-            ActionRecipeItem::Unordered(seq) => &seq,
-            ActionRecipeItem::Choice(seq) => &seq,
-            _ => unreachable!(),
+            ActionRecipeItem::Sequential(seq) => Ok(seq),
+            ActionRecipeItem::Unordered(seq, _) => Ok(seq),
+            ActionRecipeItem::Choice(seq) => Ok(seq),
+            ActionRecipeItem::Optional(seq) => Ok(seq),
+            ActionRecipeItem::Repeated(seq, _) => Ok(seq),
+            _ => Err(ConcertoError::UnexpectedItem(item_idx)),
         }
     }
 }
 
-use std::rc::Rc;
-
 pub enum ActionInput<C: ActionConfiguration> {
     CursorCoordinate(C::Target),
     FocusCoordinate(C::Target),
     KeyDown(C::KeyKind),
     KeyUp(C::KeyKind),
+    /// A further press of a key that is already held, see `ActionKeyRepeat::Distinct`.
+    KeyRepeat(C::KeyKind),
+    /// A new value of an analog axis, identified by one of the configuration's keys.
+    Axis(C::KeyKind, f32),
+    /// A stylus sample over the resolved target.
+    Stylus(C::Target, ActionStylus),
+    /// A character of text input, as produced by the keyboard layout or an IME.
+    Character(char),
+    /// A finger touching down.
+    TouchStart(ActionTouch),
+    /// A touching finger moving to a new position.
+    TouchMove(ActionTouch),
+    /// A finger lifting, or its touch being cancelled, at its last position.
+    TouchEnd(ActionTouch),
+}
+
+/// Pressure and tilt of a stylus sample.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct ActionStylus {
+    /// Normalized pressure, from 0.0 to 1.0.
+    pub pressure: f32,
+    /// Tilt from the vertical towards positive x, in degrees.
+    pub tilt_x: f32,
+    /// Tilt from the vertical towards positive y, in degrees.
+    pub tilt_y: f32,
+}
+
+/// A touch point: the finger's identifier, unique among the fingers currently down,
+/// and its position.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct ActionTouch {
+    pub id: u64,
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum ActionTouchPhase {
+    Start,
+    Move,
+    End,
+}
+
+/// How two touching fingers moved since a pinch started; not at all by default.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ActionPinch {
+    /// The distance between the fingers relative to the starting one.
+    pub scale: f32,
+    /// The turn of the line through the fingers, in radians from positive x towards
+    /// positive y, within -pi to pi.
+    pub rotation: f32,
+    /// The point halfway between the fingers.
+    pub center: (f32, f32),
+}
+
+impl Default for ActionPinch {
+    fn default() -> Self {
+        ActionPinch {
+            scale: 1.0,
+            rotation: 0.0,
+            center: (0.0, 0.0),
+        }
+    }
+}
+
+impl ActionPinch {
+    pub(crate) fn between(
+        start: (ActionTouch, ActionTouch),
+        now: (ActionTouch, ActionTouch),
+    ) -> Self {
+        let span = |(a, b): (ActionTouch, ActionTouch)| (b.x - a.x, b.y - a.y);
+        let (start_x, start_y) = span(start);
+        let (now_x, now_y) = span(now);
+        let start_distance = start_x.hypot(start_y);
+        let scale = if start_distance > 0.0 {
+            now_x.hypot(now_y) / start_distance
+        } else {
+            1.0
+        };
+        let mut rotation = now_y.atan2(now_x) - start_y.atan2(start_x);
+        if rotation > ::std::f32::consts::PI {
+            rotation -= 2.0 * ::std::f32::consts::PI;
+        } else if rotation < -::std::f32::consts::PI {
+            rotation += 2.0 * ::std::f32::consts::PI;
+        }
+        ActionPinch {
+            scale,
+            rotation,
+            center: ((now.0.x + now.1.x) / 2.0, (now.0.y + now.1.y) / 2.0),
+        }
+    }
 }
 
 impl<C: ActionConfiguration> Clone for ActionInput<C> {
@@ -134,11 +665,35 @@ impl<C: ActionConfiguration> Clone for ActionInput<C> {
             ActionInput::FocusCoordinate(v) => ActionInput::FocusCoordinate(v.clone()),
             ActionInput::KeyDown(v) => ActionInput::KeyDown(v.clone()),
             ActionInput::KeyUp(v) => ActionInput::KeyUp(v.clone()),
+            ActionInput::KeyRepeat(v) => ActionInput::KeyRepeat(v.clone()),
+            ActionInput::Axis(a, v) => ActionInput::Axis(a.clone(), *v),
+            ActionInput::Stylus(t, s) => ActionInput::Stylus(t.clone(), *s),
+            ActionInput::Character(c) => ActionInput::Character(*c),
+            ActionInput::TouchStart(t) => ActionInput::TouchStart(*t),
+            ActionInput::TouchMove(t) => ActionInput::TouchMove(*t),
+            ActionInput::TouchEnd(t) => ActionInput::TouchEnd(*t),
         }
     }
 }
 
-use std::fmt;
+impl<C: ActionConfiguration> PartialEq for ActionInput<C> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ActionInput::CursorCoordinate(v1), ActionInput::CursorCoordinate(v2)) => v1 == v2,
+            (ActionInput::FocusCoordinate(v1), ActionInput::FocusCoordinate(v2)) => v1 == v2,
+            (ActionInput::KeyDown(v1), ActionInput::KeyDown(v2)) => v1 == v2,
+            (ActionInput::KeyUp(v1), ActionInput::KeyUp(v2)) => v1 == v2,
+            (ActionInput::KeyRepeat(v1), ActionInput::KeyRepeat(v2)) => v1 == v2,
+            (ActionInput::Axis(a1, v1), ActionInput::Axis(a2, v2)) => a1 == a2 && v1 == v2,
+            (ActionInput::Stylus(t1, s1), ActionInput::Stylus(t2, s2)) => t1 == t2 && s1 == s2,
+            (ActionInput::Character(c1), ActionInput::Character(c2)) => c1 == c2,
+            (ActionInput::TouchStart(t1), ActionInput::TouchStart(t2)) => t1 == t2,
+            (ActionInput::TouchMove(t1), ActionInput::TouchMove(t2)) => t1 == t2,
+            (ActionInput::TouchEnd(t1), ActionInput::TouchEnd(t2)) => t1 == t2,
+            _ => false,
+        }
+    }
+}
 
 impl<C: ActionConfiguration> fmt::Debug for ActionInput<C> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
@@ -147,37 +702,342 @@ impl<C: ActionConfiguration> fmt::Debug for ActionInput<C> {
             ActionInput::FocusCoordinate(v) => write!(f, "FocusCoordinate({:?})", v),
             ActionInput::KeyDown(v) => write!(f, "KeyDown({:?})", v),
             ActionInput::KeyUp(v) => write!(f, "KeyUp({:?})", v),
+            ActionInput::KeyRepeat(v) => write!(f, "KeyRepeat({:?})", v),
+            ActionInput::Axis(a, v) => write!(f, "Axis({:?}, {:?})", a, v),
+            ActionInput::Stylus(t, s) => write!(f, "Stylus({:?}, {:?})", t, s),
+            ActionInput::Character(c) => write!(f, "Character({:?})", c),
+            ActionInput::TouchStart(t) => write!(f, "TouchStart({:?})", t),
+            ActionInput::TouchMove(t) => write!(f, "TouchMove({:?})", t),
+            ActionInput::TouchEnd(t) => write!(f, "TouchEnd({:?})", t),
         }
     }
 }
 
-pub enum ActionCondition<C: ActionConfiguration> {
+pub enum ActionCondition<'f, C: ActionConfiguration> {
     KeyPressed(C::KeyKind, bool),
+    /// Holds while the key is pressed or armed, see `ActionContextBuilder::with_sticky_key`.
+    ModifierActive(C::KeyKind),
+    /// Holds while the target last reported by a `FocusCoordinate` input passes the filter.
+    FocusIn(ActionTargetFilter<'f, C>),
+    /// Holds while the target last reported by a `CursorCoordinate` input passes the filter.
+    CursorOver(ActionTargetFilter<'f, C>),
+    /// Holds while the last value reported for the axis is at or past the threshold,
+    /// in the threshold's direction.
+    AxisBeyond(C::KeyKind, f32),
+    /// Holds while the predicate accepts the tracked environment; kept conditions are
+    /// re-evaluated after every input.
+    Custom(ActionEnvironmentPredicate<'f, C>),
+    Not(Box<ActionCondition<'f, C>>),
+    All(Vec<ActionCondition<'f, C>>),
+    Any(Vec<ActionCondition<'f, C>>),
+}
+
+impl<'f, C: ActionConfiguration> ActionCondition<'f, C> {
+    pub fn key_pressed(key: C::KeyKind) -> Self {
+        ActionCondition::KeyPressed(key, true)
+    }
+
+    pub fn modifier_active(key: C::KeyKind) -> Self {
+        ActionCondition::ModifierActive(key)
+    }
+
+    pub fn key_not_pressed(key: C::KeyKind) -> Self {
+        ActionCondition::KeyPressed(key, false)
+    }
+
+    pub fn focus_in<F>(filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        ActionCondition::FocusIn(ActionTargetFilter::new(filter))
+    }
+
+    pub fn cursor_over<F>(filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        ActionCondition::CursorOver(ActionTargetFilter::new(filter))
+    }
+
+    pub fn axis_beyond(axis: C::KeyKind, threshold: f32) -> Self {
+        ActionCondition::AxisBeyond(axis, threshold)
+    }
+
+    pub fn custom<F>(predicate: F) -> Self
+    where
+        F: Fn(&ActionEnvironmentView<C>) -> bool + MaybeSendSync + 'f,
+    {
+        ActionCondition::Custom(ActionEnvironmentPredicate::new(predicate))
+    }
+
+    pub fn and(self, other: Self) -> Self {
+        match self {
+            ActionCondition::All(mut conditions) => {
+                conditions.push(other);
+                ActionCondition::All(conditions)
+            }
+            condition => ActionCondition::All(vec![condition, other]),
+        }
+    }
+
+    pub fn or(self, other: Self) -> Self {
+        match self {
+            ActionCondition::Any(mut conditions) => {
+                conditions.push(other);
+                ActionCondition::Any(conditions)
+            }
+            condition => ActionCondition::Any(vec![condition, other]),
+        }
+    }
+
+    // kept conditions that can't be decided from single inputs are re-checked against
+    // the environment instead.
+    pub(crate) fn depends_on_environment(&self) -> bool {
+        matches!(
+            self,
+            ActionCondition::ModifierActive(_)
+                | ActionCondition::Custom(_)
+                | ActionCondition::Not(_)
+                | ActionCondition::All(_)
+                | ActionCondition::Any(_)
+        )
+    }
+}
+
+// a negative threshold is passed by values at or below it, any other one by values
+// at or above it.
+pub(crate) fn is_axis_beyond(value: f32, threshold: f32) -> bool {
+    if threshold < 0.0 {
+        value <= threshold
+    } else {
+        value >= threshold
+    }
+}
+
+impl<'f, C: ActionConfiguration> ops::Not for ActionCondition<'f, C> {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        ActionCondition::Not(Box::new(self))
+    }
 }
 
-impl<C: ActionConfiguration> Clone for ActionCondition<C> {
+impl<'f, C: ActionConfiguration> Clone for ActionCondition<'f, C> {
     fn clone(&self) -> Self {
         match self {
-            ActionCondition::KeyPressed(v, s) => ActionCondition::KeyPressed(v.clone(), s.clone()),
+            ActionCondition::KeyPressed(v, s) => ActionCondition::KeyPressed(v.clone(), *s),
+            ActionCondition::ModifierActive(v) => ActionCondition::ModifierActive(v.clone()),
+            ActionCondition::FocusIn(filter) => ActionCondition::FocusIn(filter.clone()),
+            ActionCondition::CursorOver(filter) => ActionCondition::CursorOver(filter.clone()),
+            ActionCondition::AxisBeyond(a, t) => ActionCondition::AxisBeyond(a.clone(), *t),
+            ActionCondition::Custom(predicate) => ActionCondition::Custom(predicate.clone()),
+            ActionCondition::Not(inner) => ActionCondition::Not(inner.clone()),
+            ActionCondition::All(conditions) => ActionCondition::All(conditions.clone()),
+            ActionCondition::Any(conditions) => ActionCondition::Any(conditions.clone()),
+        }
+    }
+}
+
+impl<'f, C: ActionConfiguration> fmt::Debug for ActionCondition<'f, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            ActionCondition::KeyPressed(v, s) => write!(f, "KeyPressed({:?}, {:?})", v, s),
+            ActionCondition::ModifierActive(v) => write!(f, "ModifierActive({:?})", v),
+            ActionCondition::FocusIn(_) => write!(f, "FocusIn(..)"),
+            ActionCondition::CursorOver(_) => write!(f, "CursorOver(..)"),
+            ActionCondition::AxisBeyond(a, t) => write!(f, "AxisBeyond({:?}, {:?})", a, t),
+            ActionCondition::Custom(_) => write!(f, "Custom(..)"),
+            ActionCondition::Not(inner) => write!(f, "Not({:?})", inner),
+            ActionCondition::All(conditions) => write!(f, "All({:?})", conditions),
+            ActionCondition::Any(conditions) => write!(f, "Any({:?})", conditions),
+        }
+    }
+}
+
+/// The modifier keys a chord added with `add_chord` requires to be held, and those
+/// it requires not to be.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ActionModifiers<K> {
+    held: Vec<K>,
+    excluded: Vec<K>,
+}
+
+impl<K> Default for ActionModifiers<K> {
+    fn default() -> Self {
+        ActionModifiers {
+            held: Vec::new(),
+            excluded: Vec::new(),
         }
     }
 }
 
-pub struct ActionRecipeBuilder<'a, C: ActionConfiguration> {
-    sequence_builder: ActionRecipeSequenceBuilder<'a, C>,
+impl<K> ActionModifiers<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, key: K) -> Self {
+        self.held.push(key);
+        self
+    }
+
+    pub fn without(mut self, key: K) -> Self {
+        self.excluded.push(key);
+        self
+    }
+
+    pub fn held(&self) -> &[K] {
+        &self.held
+    }
+
+    pub fn excluded(&self) -> &[K] {
+        &self.excluded
+    }
+}
+
+/// A cursor move between two consecutive matched cursor inputs, as checked by
+/// `add_cursor_motion_input`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ActionCursorMotion<P> {
+    pub from: P,
+    pub to: P,
+    pub elapsed: Duration,
+}
+
+impl<P> ActionCursorMotion<P> {
+    /// Distance per second under `metric`, infinite if no time elapsed.
+    pub fn speed<M>(&self, metric: M) -> f64
+    where
+        M: Fn(&P, &P) -> f64,
+    {
+        (metric)(&self.from, &self.to) / self.elapsed.as_secs_f64()
+    }
+}
+
+/// A predicate over targets, as used by `ActionCondition::FocusIn` and `CursorOver`.
+pub struct ActionTargetFilter<'f, C: ActionConfiguration>(Shared<dyn ActionTargetFilterFn<C> + 'f>);
+
+impl<'f, C: ActionConfiguration> ActionTargetFilter<'f, C> {
+    pub(crate) fn new<F>(filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        ActionTargetFilter(Shared::new(filter) as _)
+    }
+
+    pub(crate) fn matches(&self, target: &C::Target) -> bool {
+        (self.0)(target)
+    }
+}
+
+impl<'f, C: ActionConfiguration> Clone for ActionTargetFilter<'f, C> {
+    fn clone(&self) -> Self {
+        ActionTargetFilter(self.0.clone())
+    }
+}
+
+/// A predicate over the tracked environment, as used by `ActionCondition::Custom`.
+pub struct ActionEnvironmentPredicate<'f, C: ActionConfiguration>(
+    Shared<dyn ActionEnvironmentPredicateFn<C> + 'f>,
+);
+
+impl<'f, C: ActionConfiguration> ActionEnvironmentPredicate<'f, C> {
+    pub(crate) fn new<F>(predicate: F) -> Self
+    where
+        F: Fn(&ActionEnvironmentView<C>) -> bool + MaybeSendSync + 'f,
+    {
+        ActionEnvironmentPredicate(Shared::new(predicate) as _)
+    }
+
+    pub(crate) fn matches(&self, env: &ActionEnvironmentView<C>) -> bool {
+        (self.0)(env)
+    }
+}
+
+impl<'f, C: ActionConfiguration> Clone for ActionEnvironmentPredicate<'f, C> {
+    fn clone(&self) -> Self {
+        ActionEnvironmentPredicate(self.0.clone())
+    }
+}
+
+pub struct ActionRecipeBuilder<'a, 'f: 'a, C: ActionConfiguration> {
+    sequence_builder: ActionRecipeSequenceBuilder<'a, 'f, C>,
     nest_recipes: Vec<ActionRecipe<C>>,
+    timeout: Option<Duration>,
+    name: Option<String>,
+    description: Option<String>,
+    category: Option<String>,
+    strictness: ActionRecipeStrictness,
+    consumes_inputs: bool,
+    is_repeatable: bool,
+    input_buffer: Option<Duration>,
+    prefix: Option<String>,
+    on_abort: Vec<ActionRecipeItemIdx>,
+    target_guard: Option<ActionTargetFilter<'f, C>>,
+    auto_eliminate: bool,
+    item_names: Vec<(String, ActionRecipeItemIdx)>,
+    error: Option<ConcertoError>,
 }
 
-impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
-    pub(crate) fn new(context_builder: &'a mut ActionContextBuilder<C>) -> Self {
+impl<'a, 'f, C: ActionConfiguration> ActionRecipeBuilder<'a, 'f, C> {
+    pub(crate) fn new(context_builder: &'a mut ActionContextBuilder<'f, C>) -> Self {
         ActionRecipeBuilder {
             sequence_builder: ActionRecipeSequenceBuilder::new(context_builder),
             nest_recipes: Vec::new(),
+            timeout: None,
+            name: None,
+            description: None,
+            category: None,
+            strictness: ActionRecipeStrictness::default(),
+            consumes_inputs: false,
+            is_repeatable: false,
+            input_buffer: None,
+            prefix: None,
+            on_abort: Vec::new(),
+            target_guard: None,
+            auto_eliminate: false,
+            item_names: Vec::new(),
+            error: None,
         }
     }
-    pub fn build(self) -> ActionRecipe<C> {
-        let (context_builder, sequence) = self.sequence_builder.build();
+    pub fn build(self) -> Result<ActionRecipe<C>, ConcertoError> {
+        let name = self.name;
+        let with_name = |error: ConcertoError| match &name {
+            Some(name) => error.in_recipe(name),
+            None => error,
+        };
+        if let Some(error) = self.error {
+            return Err(with_name(error));
+        }
+        let mut sequence_builder = self.sequence_builder;
+        if self.auto_eliminate {
+            sequence_builder.eliminate_kept_items();
+        }
+        let (context_builder, sequence) = sequence_builder.build();
+        let prefix = match &self.prefix {
+            Some(prefix) => Some(
+                context_builder
+                    .prefix_index(prefix)
+                    .ok_or_else(|| with_name(ConcertoError::UnknownPrefix(prefix.clone())))?,
+            ),
+            None => None,
+        };
         let item_idx = context_builder.recipe_items.register_item(sequence);
+        validate_recipe(
+            &context_builder.recipe_items,
+            item_idx,
+            self.nest_recipes.len(),
+        )
+        .map_err(with_name)?;
+        let dwell_durations =
+            collect_dwell_durations(&context_builder.recipe_items, item_idx).map_err(with_name)?;
+        let starts_with_cursor =
+            may_start_with_cursor_input(&context_builder.recipe_items, item_idx);
+        let target_guard = self.target_guard.map(|guard| {
+            context_builder
+                .recipe_items
+                .register_item(ActionRecipeItem::TargetGuard(guard))
+        });
 
         let mut nest_recipes = Vec::new();
 
@@ -186,35 +1046,227 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
             nest_recipes.push(idx);
         }
 
-        ActionRecipe {
+        Ok(ActionRecipe {
             root_item: item_idx,
             phantom: PhantomData,
             is_enabled: true,
+            is_broken: false,
             is_nested: false,
             nest_recipes,
+            timeout: self.timeout,
+            name,
+            description: self.description,
+            category: self.category,
+            metrics: ActionRecipeMetrics::default(),
+            compiled: None,
+            inline_contract_limit: DEFAULT_INLINE_CONTRACT_LIMIT,
+            strictness: self.strictness,
+            consumes_inputs: self.consumes_inputs,
+            is_repeatable: self.is_repeatable,
+            input_buffer: self.input_buffer,
+            prefix,
+            arms_prefix: None,
+            on_abort: self.on_abort,
+            target_guard,
+            dwell_durations,
+            starts_with_cursor,
+        })
+    }
+}
+
+fn collect_dwell_durations<C: ActionConfiguration>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    root_item: ActionRecipeItemIdx,
+) -> Result<Vec<Duration>, ConcertoError> {
+    let mut durations = Vec::new();
+    let mut pending = vec![root_item];
+    while let Some(item_idx) = pending.pop() {
+        let item = recipe_items.get(item_idx)?;
+        if let ActionRecipeItem::StartDwell(duration) | ActionRecipeItem::StartIdle(duration) = item
+        {
+            durations.push(*duration);
+        } else if item.is_compound() {
+            pending.extend(item.compound_sequence(item_idx)?.iter().cloned());
         }
     }
+    Ok(durations)
 }
 
-impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
-    pub fn keep_cursor_coordinate_input(mut self, target: C::Target) -> Self {
-        self.sequence_builder
+impl<'a, 'f, C: ActionConfiguration> ActionRecipeBuilder<'a, 'f, C> {
+    /// Names the recipe in logs, diagnostics and command provenance.
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// A user-facing description of what the recipe does, e.g. for a shortcut list.
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// The user-facing category the recipe is listed under.
+    pub fn with_category<S: Into<String>>(mut self, category: S) -> Self {
+        self.category = Some(category.into());
+        self
+    }
+
+    /// Aborts an execution of this recipe that hasn't completed within `timeout`
+    /// of its first input, as measured by `ActionContext::advance_time`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how executions of this recipe treat inputs they don't expect, `Lenient`
+    /// by default.
+    pub fn with_strictness(mut self, strictness: ActionRecipeStrictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    /// Marks the inputs matched by executions of this recipe as consumed, as reported
+    /// by `ActionContext::process_input_with_outcome`.
+    pub fn with_input_consumption(mut self, consumes_inputs: bool) -> Self {
+        self.consumes_inputs = consumes_inputs;
+        self
+    }
+
+    /// Lets this recipe complete without resetting the executions of the other
+    /// recipes, for bindings that fire many times in a row.
+    pub fn repeatable(mut self) -> Self {
+        self.is_repeatable = true;
+        self
+    }
+
+    /// Keeps the inputs an execution ignored for `window`, so one that arrives a
+    /// little before the step expecting it still counts once that step is reached.
+    pub fn with_input_buffer(mut self, window: Duration) -> Self {
+        self.input_buffer = Some(window);
+        self
+    }
+
+    /// Only lets this recipe start while the prefix declared with
+    /// `ActionContextBuilder::add_prefix` is pending.
+    pub fn under_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Only lets the recipe see the cursor, focus and stylus inputs reporting `target`;
+    /// the others are ignored by its executions, whatever their strictness.
+    pub fn with_target(self, target: C::Target) -> Self
+    where
+        C::Target: MaybeSendSync + 'f,
+    {
+        self.with_target_filter(move |reported: &C::Target| C::target_matches(&target, reported))
+    }
+
+    /// Like `with_target`, for the targets `filter` accepts.
+    pub fn with_target_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        self.target_guard = Some(ActionTargetFilter::new(filter));
+        self
+    }
+
+    /// Names the step added last, e.g. a `keep_*` input or condition, so a later
+    /// `eliminate` can refer to it, also from outside the sub-sequence it is in.
+    pub fn named<S: Into<String>>(mut self, name: S) -> Self {
+        let recipe_items = &self.sequence_builder.context_builder.recipe_items;
+        let item_idx = self
+            .sequence_builder
+            .item_idxes
+            .iter()
+            .rev()
+            .find(|&&item_idx| {
+                !matches!(
+                    recipe_items.get(item_idx),
+                    Ok(ActionRecipeItem::EliminateItem(_))
+                )
+            })
+            .cloned();
+        let name = name.into();
+        match item_idx {
+            Some(item_idx) => self.item_names.push((name, item_idx)),
+            None => {
+                self.error
+                    .get_or_insert(ConcertoError::UnknownItemName(name));
+            }
+        }
+        self
+    }
+
+    /// Eliminates the input or condition kept by the step most recently given `name`.
+    pub fn eliminate(mut self, name: &str) -> Self {
+        let item_idx = self
+            .item_names
+            .iter()
+            .rev()
+            .find(|(item_name, _)| item_name == name)
+            .map(|(_, item_idx)| *item_idx);
+        match item_idx {
+            Some(item_idx) => {
+                self.sequence_builder.add_primitive_eliminate_item(item_idx);
+            }
+            None => {
+                self.error
+                    .get_or_insert(ConcertoError::UnknownItemName(name.to_owned()));
+            }
+        }
+        self
+    }
+
+    /// When enabled, the inputs and conditions still kept at the end of the recipe, or
+    /// of a sub-sequence built after this call, are eliminated there, latest kept first.
+    pub fn auto_eliminate(mut self, enabled: bool) -> Self {
+        self.auto_eliminate = enabled;
+        self
+    }
+
+    pub fn keep_cursor_coordinate_input(mut self, target: C::Target) -> Self {
+        self.sequence_builder
             .add_primitive_start_cursor_coordinate_input(target);
         self
     }
 
     pub fn keep_cursor_coordinate_filtered_input<F>(mut self, filter: F) -> Self
     where
-        F: Fn(&C::Target) -> bool + 'static,
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
     {
         self.sequence_builder
             .add_primitive_start_cursor_coordinate_filtered_input(filter);
         self
     }
 
+    /// Matches an input of any kind that `filter` decides is a match; an input it
+    /// rejects aborts the execution.
+    pub fn add_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ActionInput<C>) -> ActionMatchDecision + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_filtered_input(filter);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Like `add_filtered_input`, keeping the input until it is eliminated.
+    pub fn keep_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ActionInput<C>) -> ActionMatchDecision + MaybeSendSync + 'f,
+    {
+        self.sequence_builder
+            .add_primitive_start_filtered_input(filter);
+        self
+    }
+
     pub fn add_cursor_coordinate_filtered_input<F>(mut self, filter: F) -> Self
     where
-        F: Fn(&C::Target) -> bool + 'static,
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
     {
         let input_idx = self
             .sequence_builder
@@ -224,6 +1276,397 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
         self
     }
 
+    /// Captures `Character` inputs until `terminator` is pressed, see
+    /// `ActionRecipeExecutionInfo::captured_text`. Pressing `cancel` aborts the execution.
+    pub fn add_text_capture(self, terminator: C::KeyKind, cancel: C::KeyKind) -> Self {
+        self.add_text_capture_item(None, terminator, cancel)
+    }
+
+    /// Like `add_text_capture`, but captures the pressed keys `translate` turns into
+    /// characters. It should map neither `terminator` nor `cancel`.
+    pub fn add_text_capture_with<F>(
+        self,
+        translate: F,
+        terminator: C::KeyKind,
+        cancel: C::KeyKind,
+    ) -> Self
+    where
+        F: Fn(&C::KeyKind) -> Option<char> + MaybeSendSync + 'f,
+    {
+        self.add_text_capture_item(Some(Shared::new(translate) as _), terminator, cancel)
+    }
+
+    fn add_text_capture_item(
+        mut self,
+        translate: Option<ActionKeyCharacterMap<'f, C>>,
+        terminator: C::KeyKind,
+        cancel: C::KeyKind,
+    ) -> Self {
+        let cancel_idx = self
+            .sequence_builder
+            .add_primitive_start_key_condition(cancel, false);
+        self = self.optionally_repeatedly(|mut b| {
+            let input_idx = b
+                .sequence_builder
+                .add_primitive_start_character_input(translate);
+            b.sequence_builder.add_primitive_eliminate_item(input_idx);
+            b
+        });
+        let terminator_idx = self
+            .sequence_builder
+            .add_primitive_start_key_down_input(terminator);
+        self.sequence_builder
+            .add_primitive_eliminate_item(terminator_idx);
+        self.sequence_builder
+            .add_primitive_eliminate_item(cancel_idx);
+        self
+    }
+
+    /// Matches the first stylus sample the filter accepts, ignoring the others.
+    pub fn add_stylus_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target, &ActionStylus) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_stylus_filtered_input(filter);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches the first stylus sample pressed harder than `threshold`.
+    pub fn add_stylus_pressure_input(self, threshold: f32) -> Self {
+        self.add_stylus_filtered_input(move |_, stylus| stylus.pressure > threshold)
+    }
+
+    /// Waits for a cursor input more than `threshold` away from the first cursor
+    /// target the execution matched, e.g. the one captured at the press. Closer
+    /// cursor inputs are ignored.
+    pub fn add_cursor_moved_beyond<P, M>(mut self, threshold: f64, position: P, metric: M) -> Self
+    where
+        P: Fn(&C::Target) -> Option<C::CursorPos> + MaybeSendSync + 'f,
+        M: Fn(&C::CursorPos, &C::CursorPos) -> f64 + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_cursor_distance_filtered_input(threshold, position, metric);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches a cursor input if `predicate` accepts its motion from the previously
+    /// matched cursor target, e.g. a minimum speed or a direction; aborts otherwise.
+    pub fn add_cursor_motion_input<P, F>(mut self, position: P, predicate: F) -> Self
+    where
+        P: Fn(&C::Target) -> Option<C::CursorPos> + MaybeSendSync + 'f,
+        F: Fn(&ActionCursorMotion<C::CursorPos>) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_cursor_motion_filtered_input(position, predicate);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches the cursor input that moves onto targets accepted by `filter`.
+    pub fn add_target_entered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_cursor_transition(filter, true);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches the cursor input that moves off targets accepted by `filter`.
+    pub fn add_target_left_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_cursor_transition(filter, false);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches once the cursor stayed over targets accepted by `filter` for `duration`,
+    /// counted from the cursor input that moved onto them. Leaving them aborts.
+    pub fn add_dwell_input<F>(mut self, filter: F, duration: Duration) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let filter = Shared::new(filter);
+        let enter_filter = filter.clone();
+        let enter_idx = self
+            .sequence_builder
+            .add_primitive_start_cursor_coordinate_filtered_input(move |target| {
+                (enter_filter)(target)
+            });
+        self.sequence_builder
+            .add_primitive_eliminate_item(enter_idx);
+        let condition_idx = self
+            .sequence_builder
+            .add_primitive_start_cursor_condition(move |target| (filter)(target));
+        let dwell_idx = self.sequence_builder.add_primitive_start_dwell(duration);
+        self.sequence_builder
+            .add_primitive_eliminate_item(dwell_idx);
+        self.sequence_builder
+            .add_primitive_eliminate_item(condition_idx);
+        self
+    }
+
+    /// Matches the first input to arrive once `duration` passed without the execution
+    /// matching anything, or the tracked cursor target as time advances. As the last
+    /// branch of a `choice`, it is taken when the other branches sat idle too long.
+    pub fn add_idle_input(mut self, duration: Duration) -> Self {
+        let input_idx = self.sequence_builder.add_primitive_start_idle(duration);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches any key press. As the last branch of a `choice`, it takes the keys the
+    /// other branches don't, e.g. for a menu that any other key cancels.
+    pub fn add_else_input(self) -> Self {
+        self.add_filtered_input(|input| match input {
+            ActionInput::KeyDown(_) => ActionMatchDecision::Match,
+            _ => ActionMatchDecision::Ignore,
+        })
+    }
+
+    /// Matches the first finger touching down that the filter accepts, ignoring the
+    /// other inputs.
+    pub fn add_touch_start_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ActionTouch) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_touch_filtered_input(filter, ActionTouchPhase::Start);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Like `add_touch_start_filtered_input`, for a touching finger moving.
+    pub fn add_touch_move_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ActionTouch) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_touch_filtered_input(filter, ActionTouchPhase::Move);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Like `add_touch_start_filtered_input`, for a finger lifting.
+    pub fn add_touch_end_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ActionTouch) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_touch_filtered_input(filter, ActionTouchPhase::End);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches the next move of a finger whose touching down the execution matched,
+    /// ignoring the other fingers.
+    pub fn add_matched_touch_move_input(mut self) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_matched_touch_input(false);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Like `add_matched_touch_move_input`, for one of those fingers lifting.
+    pub fn add_matched_touch_end_input(mut self) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_matched_touch_input(true);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    pub fn keep_focus_coordinate_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        self.sequence_builder
+            .add_primitive_start_focus_coordinate_filtered_input(filter);
+        self
+    }
+
+    pub fn keep_focus_in<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        self.sequence_builder
+            .add_primitive_start_focus_condition(filter);
+        self
+    }
+
+    pub fn check_focus_in<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_focus_condition(filter);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    pub fn keep_condition(mut self, condition: ActionCondition<'f, C>) -> Self {
+        self.sequence_builder
+            .add_primitive_start_condition(condition);
+        self
+    }
+
+    pub fn check_condition(mut self, condition: ActionCondition<'f, C>) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_condition(condition);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    pub fn keep_cursor_over<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        self.sequence_builder
+            .add_primitive_start_cursor_condition(filter);
+        self
+    }
+
+    pub fn check_cursor_over<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_cursor_condition(filter);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    pub fn keep_axis_beyond(mut self, axis: C::KeyKind, threshold: f32) -> Self {
+        self.sequence_builder
+            .add_primitive_start_condition(ActionCondition::AxisBeyond(axis, threshold));
+        self
+    }
+
+    pub fn check_axis_beyond(mut self, axis: C::KeyKind, threshold: f32) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_condition(ActionCondition::AxisBeyond(axis, threshold));
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches the first value of `axis` at or past `threshold`, in the threshold's direction.
+    pub fn add_axis_beyond_input(mut self, axis: C::KeyKind, threshold: f32) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_axis_input(axis, threshold, true);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches the first value of `axis` that falls back short of `threshold`.
+    pub fn add_axis_within_input(mut self, axis: C::KeyKind, threshold: f32) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_axis_input(axis, threshold, false);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches `axis` passing `press`, then keeps the execution only while it stays past
+    /// `release`. A `release` closer to zero keeps a value resting near `press` from
+    /// aborting it.
+    pub fn add_axis_press_input(self, axis: C::KeyKind, press: f32, release: f32) -> Self {
+        self.add_axis_beyond_input(axis.clone(), press)
+            .keep_axis_beyond(axis, release)
+    }
+
+    pub fn keep_condition_with<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&ActionEnvironmentView<C>) -> bool + MaybeSendSync + 'f,
+    {
+        self.sequence_builder
+            .add_primitive_start_custom_condition(predicate);
+        self
+    }
+
+    pub fn check_condition_with<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&ActionEnvironmentView<C>) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_custom_condition(predicate);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Requires `key` to be held, or armed if it is a sticky key, until the end.
+    pub fn keep_modifier(mut self, key: C::KeyKind) -> Self {
+        self.sequence_builder
+            .add_primitive_start_condition(ActionCondition::modifier_active(key));
+        self
+    }
+
+    pub fn check_modifier(mut self, key: C::KeyKind) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_condition(ActionCondition::modifier_active(key));
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Adds the target the cursor is over to the matched inputs, as if it had been
+    /// reported right now. Aborts the execution if no cursor input was seen yet.
+    pub fn capture_cursor_target(mut self) -> Self {
+        self.sequence_builder.add_primitive_capture_cursor_target();
+        self
+    }
+
+    /// Aborts the execution at this point unless `predicate` accepts what it has
+    /// matched so far.
+    pub fn check_execution<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(ActionRecipeExecutionInfo<C>) -> bool + MaybeSendSync + 'f,
+    {
+        self.sequence_builder
+            .add_primitive_check_execution(predicate);
+        self
+    }
+
     pub fn keep_key_not_pressed(mut self, key: C::KeyKind) -> Self {
         self.sequence_builder
             .add_primitive_start_key_condition(key, false);
@@ -238,6 +1681,30 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
             .add_primitive_eliminate_item(input_idx);
         self
     }
+    pub fn add_key_down_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::KeyKind) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_key_filtered_input(filter, true);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    pub fn add_key_up_filtered_input<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&C::KeyKind) -> bool + MaybeSendSync + 'f,
+    {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_key_filtered_input(filter, false);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
     pub fn add_key_down_input(mut self, key: C::KeyKind) -> Self {
         let input_idx = self
             .sequence_builder
@@ -253,19 +1720,221 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
         self
     }
 
-    pub fn enable_starting_nest_recipe<F>(mut self, f: F) -> Self
+    /// Matches a `KeyUp` of `key` after it was held for at least `min_hold`, however
+    /// the press began. A release after a shorter hold aborts with `Reject`, or is left
+    /// for a later step with `Ignore`.
+    pub fn add_held_key_up_input(
+        mut self,
+        key: C::KeyKind,
+        min_hold: Duration,
+        on_early_release: ActionMatchDecision,
+    ) -> Self {
+        let input_idx =
+            self.sequence_builder
+                .add_primitive_start_held_key_up(key, min_hold, on_early_release);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    /// Matches a full press of `key`: its `KeyDown`, then eventually its `KeyUp`.
+    pub fn add_key_click_input(self, key: C::KeyKind) -> Self {
+        self.add_key_down_input(key.clone()).add_key_up_input(key)
+    }
+
+    /// Matches an auto-repeat of `key`, as reported under `ActionKeyRepeat::Distinct`.
+    pub fn add_key_repeat_input(mut self, key: C::KeyKind) -> Self {
+        let input_idx = self
+            .sequence_builder
+            .add_primitive_start_key_repeat_input(key);
+        self.sequence_builder
+            .add_primitive_eliminate_item(input_idx);
+        self
+    }
+
+    pub fn enable_starting_nest_recipe<F>(mut self, f: F) -> Self
+    where
+        F: for<'r> FnOnce(
+            usize,
+            ActionRecipeBuilder<'r, 'f, C>,
+        ) -> Result<ActionRecipe<C>, ConcertoError>,
+    {
+        let nest_recipe_idx = self.nest_recipes.len();
+        let nest_recipe = {
+            let nest_recipe_builder =
+                ActionRecipeBuilder::new(self.sequence_builder.context_builder);
+            (f)(nest_recipe_idx, nest_recipe_builder)
+        };
+        match nest_recipe {
+            Ok(nest_recipe) => {
+                self.nest_recipes.push(nest_recipe);
+                self.sequence_builder
+                    .add_primitive_start_nest_recipe(nest_recipe_idx);
+            }
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+        self
+    }
+
+    /// Adds the steps built by `f` as a sub-sequence that is skipped when the input
+    /// right after it arrives before the sub-sequence's first input does. The name,
+    /// timeout, strictness and input consumption of the inner builder are ignored.
+    pub fn optionally<F>(self, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        self.add_sub_sequence(ActionRecipeSequenceKind::Optional, f)
+    }
+
+    /// Adds the steps built by `f` as a sub-sequence, e.g. to keep contracts for a part
+    /// of the recipe with `auto_eliminate`.
+    pub fn sequential<F>(self, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        self.add_sub_sequence(ActionRecipeSequenceKind::Sequential, f)
+    }
+
+    /// Adds the inputs built by `f` as a block matching all of them in any order. Each
+    /// step has to be a single input; those added with `add_*` are eliminated once
+    /// the whole block matched.
+    pub fn unordered<F>(self, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        self.add_sub_sequence(ActionRecipeSequenceKind::Unordered(None), f)
+    }
+
+    /// Like `unordered`, but aborts unless the inputs arrived within `window` of each
+    /// other, e.g. so the keys of a chord have to be pressed together.
+    pub fn unordered_within<F>(self, window: Duration, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        let mut builder = self.unordered(f);
+        builder
+            .sequence_builder
+            .add_primitive_check_unordered_window(window);
+        builder
+    }
+
+    /// Like `unordered`, but the block is done once `count` of its inputs matched, in
+    /// any order; the others are no longer waited for.
+    pub fn unordered_at_least<F>(self, count: usize, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        self.add_sub_sequence(ActionRecipeSequenceKind::Unordered(Some(count)), f)
+    }
+
+    /// Adds the inputs built by `f` as a block matching the first of them to arrive,
+    /// with the same restrictions as `unordered`.
+    pub fn choice<F>(self, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        self.add_sub_sequence(ActionRecipeSequenceKind::Choice, f)
+    }
+
+    /// Adds the steps built by `f` as a sub-sequence that matches one or more times,
+    /// until the input right after it arrives between two iterations. Contracts kept
+    /// inside the sub-sequence end with each iteration.
+    pub fn repeatedly<F>(self, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        self.add_sub_sequence(
+            ActionRecipeSequenceKind::Repeated(ActionRecipeRepetition::OneOrMore),
+            f,
+        )
+    }
+
+    /// Like `repeatedly`, but the sub-sequence may also be skipped entirely.
+    pub fn optionally_repeatedly<F>(self, f: F) -> Self
+    where
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
+    {
+        self.add_sub_sequence(
+            ActionRecipeSequenceKind::Repeated(ActionRecipeRepetition::ZeroOrMore),
+            f,
+        )
+    }
+
+    fn add_sub_sequence<F>(mut self, kind: ActionRecipeSequenceKind, f: F) -> Self
     where
-        F: for<'r> FnOnce(usize, ActionRecipeBuilder<'r, C>) -> ActionRecipe<C>,
+        F: for<'r> FnOnce(ActionRecipeBuilder<'r, 'f, C>) -> ActionRecipeBuilder<'r, 'f, C>,
     {
-        let nest_recipe_idx = self.nest_recipes.len();
-        let nest_recipe = {
-            let nest_recipe_builder =
-                ActionRecipeBuilder::new(self.sequence_builder.context_builder);
-            (f)(nest_recipe_idx, nest_recipe_builder)
+        let nest_recipes = mem::take(&mut self.nest_recipes);
+        let item_names = mem::take(&mut self.item_names);
+        let mut eliminate_items = SmallVec::<[ActionRecipeItemIdx; 3]>::new();
+        let (sequence, nest_recipes, item_names, error) = {
+            let inner_builder = ActionRecipeBuilder {
+                sequence_builder: ActionRecipeSequenceBuilder::new_inner(
+                    &mut self.sequence_builder,
+                    kind,
+                ),
+                nest_recipes,
+                timeout: None,
+                name: None,
+                description: None,
+                category: None,
+                strictness: ActionRecipeStrictness::default(),
+                consumes_inputs: false,
+                is_repeatable: false,
+                input_buffer: None,
+                prefix: None,
+                on_abort: Vec::new(),
+                target_guard: None,
+                auto_eliminate: self.auto_eliminate,
+                item_names,
+                error: None,
+            };
+            let mut inner_builder = (f)(inner_builder);
+            if inner_builder.auto_eliminate {
+                inner_builder.sequence_builder.eliminate_kept_items();
+            }
+            if let ActionRecipeSequenceKind::Unordered(_) | ActionRecipeSequenceKind::Choice =
+                inner_builder.sequence_builder.kind
+            {
+                // branches have to be plain inputs, so their eliminations follow the block.
+                let recipe_items = &inner_builder.sequence_builder.context_builder.recipe_items;
+                let (eliminations, branches) = inner_builder
+                    .sequence_builder
+                    .item_idxes
+                    .iter()
+                    .cloned()
+                    .partition::<SmallVec<[_; 3]>, _>(|&item_idx| {
+                        matches!(
+                            recipe_items.get(item_idx),
+                            Ok(ActionRecipeItem::EliminateItem(_))
+                        )
+                    });
+                eliminate_items = eliminations;
+                inner_builder.sequence_builder.item_idxes = branches;
+            }
+            let (_, sequence) = inner_builder.sequence_builder.build();
+            self.on_abort.extend(inner_builder.on_abort);
+            (
+                sequence,
+                inner_builder.nest_recipes,
+                inner_builder.item_names,
+                inner_builder.error,
+            )
         };
-        self.nest_recipes.push(nest_recipe);
-        self.sequence_builder
-            .add_primitive_start_nest_recipe(nest_recipe_idx);
+        self.nest_recipes = nest_recipes;
+        self.item_names = item_names;
+        if let Some(error) = error {
+            self.error.get_or_insert(error);
+        }
+        let item_idx = self
+            .sequence_builder
+            .context_builder
+            .recipe_items
+            .register_item(sequence);
+        self.sequence_builder.add_recipe_item(item_idx);
+        self.sequence_builder.item_idxes.extend(eliminate_items);
         self
     }
 
@@ -275,6 +1944,13 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
         self
     }
 
+    /// Ends every effect started so far with its normal end command, so a later abort
+    /// no longer rolls them back.
+    pub fn checkpoint(mut self) -> Self {
+        self.sequence_builder.add_primitive_checkpoint();
+        self
+    }
+
     pub fn issue_command(mut self, command: C::Command) -> Self {
         self.sequence_builder.add_primitive_issue_command(command);
         self
@@ -282,28 +1958,155 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
 
     pub fn issue_command_with<F>(mut self, command_generator: F) -> Self
     where
-        F: Fn(ActionRecipeExecutionInfo<C>) -> C::Command + 'static,
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend + 'f,
     {
         self.sequence_builder
             .add_primitive_issue_command_with(command_generator);
         self
     }
 
+    /// Issues `command` paired with `inverse`, the command undoing it, so the host can
+    /// push the latter onto its undo stack.
+    pub fn issue_undoable_command(self, command: C::Command, inverse: C::Command) -> Self
+    where
+        C::Command: ActionUndoableCommand,
+    {
+        self.issue_command(ActionUndoableCommand::with_inverse(command, inverse))
+    }
+
+    /// Like `issue_undoable_command`, generating the command and its inverse from what
+    /// the execution matched.
+    pub fn issue_undoable_command_with<F>(self, mut command_generator: F) -> Self
+    where
+        C::Command: ActionUndoableCommand,
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command) + MaybeSend + 'f,
+    {
+        self.issue_command_with(move |exec_info| {
+            let (command, inverse) = command_generator(exec_info);
+            ActionUndoableCommand::with_inverse(command, inverse)
+        })
+    }
+
+    /// Issues `command` whenever an execution of this recipe is aborted, before the
+    /// ends of its effects.
+    pub fn on_abort_issue(mut self, command: C::Command) -> Self {
+        let item = ActionRecipeItem::DoCommand(ActionRecipeCommand(Shared::new(command)));
+        let item_idx = self
+            .sequence_builder
+            .context_builder
+            .recipe_items
+            .register_item(item);
+        self.on_abort.push(item_idx);
+        self
+    }
+
+    /// Like `on_abort_issue`, generating the command from what the execution matched.
+    pub fn on_abort_issue_with<F>(mut self, command_generator: F) -> Self
+    where
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend + 'f,
+    {
+        let item =
+            ActionRecipeItem::DoCommandOf(GeneratorCell::new(Box::new(command_generator) as _));
+        let item_idx = self
+            .sequence_builder
+            .context_builder
+            .recipe_items
+            .register_item(item);
+        self.on_abort.push(item_idx);
+        self
+    }
+
+    /// Issues `effect_start` now and `effect_end` once the execution ends. Effects
+    /// that are still in effect then end in the reverse order they started.
     pub fn issue_effect(mut self, effect_start: C::Command, effect_end: C::Command) -> Self {
         self.sequence_builder
             .add_primitive_issue_effect(effect_start, effect_end);
         self
     }
 
+    /// Like `issue_effect`, but issues `effect_end_on_abort` instead of the end command
+    /// when the execution is aborted before the effect ends.
+    pub fn issue_effect_with_abort(
+        mut self,
+        effect_start: C::Command,
+        effect_end_on_complete: C::Command,
+        effect_end_on_abort: C::Command,
+    ) -> Self {
+        self.sequence_builder.add_primitive_issue_effect_with_abort(
+            effect_start,
+            effect_end_on_complete,
+            effect_end_on_abort,
+        );
+        self
+    }
+
     pub fn issue_effect_with<F>(mut self, effect_generator: F) -> Self
     where
-        F: Fn(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command) + 'static,
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command) + MaybeSend + 'f,
     {
         self.sequence_builder
             .add_primitive_issue_effect_with(effect_generator);
         self
     }
 
+    /// From here on, issues the command `update_generator` makes for every further
+    /// input the execution matches, such as each cursor move of a drag, until the
+    /// execution ends.
+    pub fn issue_updates_with<F>(self, update_generator: F) -> Self
+    where
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend + 'f,
+    {
+        self.issue_updates_with_rate(ActionUpdateRate::EveryInput, update_generator)
+    }
+
+    /// Like `issue_updates_with`, issuing commands no more often than `rate` allows.
+    pub fn issue_updates_with_rate<F>(mut self, rate: ActionUpdateRate, update_generator: F) -> Self
+    where
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend + 'f,
+    {
+        self.sequence_builder
+            .add_primitive_issue_updates_with(rate, update_generator);
+        self
+    }
+
+    /// Presses `key` while the held modifiers are down, in any order, and none of the
+    /// excluded ones are, then waits for all of them to be released. The excluded
+    /// modifiers stay forbidden until the recipe ends.
+    pub fn add_chord(mut self, modifiers: ActionModifiers<C::KeyKind>, key: C::KeyKind) -> Self {
+        let ActionModifiers { held, excluded } = modifiers;
+        for excluded in excluded {
+            self.sequence_builder
+                .add_primitive_start_key_condition(excluded, false);
+        }
+        let mut items = Vec::new();
+        if !held.is_empty() {
+            self.sequence_builder.add_compound_sequence(
+                ActionRecipeSequenceKind::Unordered(None),
+                |builder| {
+                    items.extend(
+                        held.iter()
+                            .map(|key| builder.add_primitive_start_key_down_input(key.clone())),
+                    );
+                },
+            );
+        }
+        items.push(
+            self.sequence_builder
+                .add_primitive_start_key_down_input(key.clone()),
+        );
+        self.sequence_builder.add_compound_sequence(
+            ActionRecipeSequenceKind::Sequential,
+            |builder| {
+                items.into_iter().for_each(|item| {
+                    builder.add_primitive_eliminate_item(item);
+                });
+            },
+        );
+        let mut keys = held;
+        keys.push(key);
+        self.add_unordered_multiple_key_up_input(&keys)
+    }
+
     pub fn add_sequential_multiple_key_down_input(mut self, keys: &[C::KeyKind]) -> Self {
         let mut items = None;
         self.sequence_builder.add_compound_sequence(
@@ -332,7 +2135,7 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
     pub fn add_unordered_multiple_key_down_input(mut self, keys: &[C::KeyKind]) -> Self {
         let mut items = None;
         self.sequence_builder.add_compound_sequence(
-            ActionRecipeSequenceKind::Unordered,
+            ActionRecipeSequenceKind::Unordered(None),
             |builder| {
                 items = Some(
                     keys.iter()
@@ -354,10 +2157,24 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
         }
         self
     }
+    /// Like `add_unordered_multiple_key_down_input`, but aborts unless the keys went
+    /// down within `window` of each other.
+    pub fn add_unordered_multiple_key_down_input_within(
+        self,
+        keys: &[C::KeyKind],
+        window: Duration,
+    ) -> Self {
+        let mut builder = self.add_unordered_multiple_key_down_input(keys);
+        builder
+            .sequence_builder
+            .add_primitive_check_unordered_window(window);
+        builder
+    }
+
     pub fn add_unordered_multiple_key_up_input(mut self, keys: &[C::KeyKind]) -> Self {
         let mut items = None;
         self.sequence_builder.add_compound_sequence(
-            ActionRecipeSequenceKind::Unordered,
+            ActionRecipeSequenceKind::Unordered(None),
             |builder| {
                 items = Some(
                     keys.iter()
@@ -407,18 +2224,21 @@ impl<'a, C: ActionConfiguration> ActionRecipeBuilder<'a, C> {
 
 enum ActionRecipeSequenceKind {
     Sequential,
-    Unordered,
+    // the number of children that have to match, all of them by default.
+    Unordered(Option<usize>),
     Choice,
+    Optional,
+    Repeated(ActionRecipeRepetition),
 }
 
-struct ActionRecipeSequenceBuilder<'a, C: ActionConfiguration> {
+struct ActionRecipeSequenceBuilder<'a, 'f: 'a, C: ActionConfiguration> {
     kind: ActionRecipeSequenceKind,
-    context_builder: &'a mut ActionContextBuilder<C>,
+    context_builder: &'a mut ActionContextBuilder<'f, C>,
     item_idxes: SmallVec<[ActionRecipeItemIdx; 3]>,
 }
 
-impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
-    fn new(context_builder: &'a mut ActionContextBuilder<C>) -> Self {
+impl<'a, 'f, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, 'f, C> {
+    fn new(context_builder: &'a mut ActionContextBuilder<'f, C>) -> Self {
         ActionRecipeSequenceBuilder {
             kind: ActionRecipeSequenceKind::Sequential,
             context_builder,
@@ -427,7 +2247,7 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
     }
 
     fn new_inner<'b>(
-        parent_builder: &'a mut ActionRecipeSequenceBuilder<'b, C>,
+        parent_builder: &'a mut ActionRecipeSequenceBuilder<'b, 'f, C>,
         kind: ActionRecipeSequenceKind,
     ) -> Self {
         ActionRecipeSequenceBuilder {
@@ -441,11 +2261,41 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         self.item_idxes.push(item_idx);
     }
 
+    // the branches of unordered and choice items are eliminated by the sequence holding them.
+    fn eliminate_kept_items(&mut self) {
+        match self.kind {
+            ActionRecipeSequenceKind::Unordered(_) | ActionRecipeSequenceKind::Choice => return,
+            _ => {}
+        }
+        let mut kept_items = SmallVec::<[ActionRecipeItemIdx; 4]>::new();
+        for &item_idx in self.item_idxes.iter() {
+            let item = match self.context_builder.recipe_items.get(item_idx) {
+                Ok(item) => item,
+                Err(_) => continue,
+            };
+            match item {
+                ActionRecipeItem::EliminateItem(eliminated_idx) => {
+                    kept_items.retain(|kept_idx| kept_idx != eliminated_idx);
+                }
+                ActionRecipeItem::StartCondition(_) => kept_items.push(item_idx),
+                ActionRecipeItem::Unordered(seq, _) | ActionRecipeItem::Choice(seq) => {
+                    kept_items.extend(seq.iter().cloned());
+                }
+                _ if item.is_interactive() => kept_items.push(item_idx),
+                _ => {}
+            }
+        }
+        for item_idx in kept_items.into_iter().rev() {
+            self.add_primitive_eliminate_item(item_idx);
+        }
+    }
+
     fn add_primitive_start_cursor_coordinate_input(
         &mut self,
         target: C::Target,
     ) -> ActionRecipeItemIdx {
-        let input = ActionRecipeItem::StartInput(ActionInput::CursorCoordinate(target));
+        let input =
+            ActionRecipeItem::StartInput(Shared::new(ActionInput::CursorCoordinate(target)));
         let item_idx = self.context_builder.recipe_items.register_item(input);
         self.add_recipe_item(item_idx);
         item_idx
@@ -456,9 +2306,9 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         filter: F,
     ) -> ActionRecipeItemIdx
     where
-        F: Fn(&C::Target) -> bool + 'static,
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
     {
-        let input = ActionRecipeItem::StartFilteredInput(Rc::new(
+        let input = ActionRecipeItem::StartFilteredInput(Shared::new(
             ActionExecutionCtx::make_input_filter_with_cursor_coordinate_filter(filter),
         ) as _);
         let item_idx = self.context_builder.recipe_items.register_item(input);
@@ -466,15 +2316,162 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         item_idx
     }
 
+    fn add_primitive_start_filtered_input<F>(&mut self, filter: F) -> ActionRecipeItemIdx
+    where
+        F: Fn(&ActionInput<C>) -> ActionMatchDecision + MaybeSendSync + 'f,
+    {
+        let input =
+            ActionRecipeItem::StartFilteredInput(Shared::new(move |input: &ActionInput<C>| {
+                ExecutionContextResult::from((filter)(input))
+            }) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_character_input(
+        &mut self,
+        translate: Option<ActionKeyCharacterMap<'f, C>>,
+    ) -> ActionRecipeItemIdx {
+        let input = ActionRecipeItem::StartCharacterInput(translate);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_stylus_filtered_input<F>(&mut self, filter: F) -> ActionRecipeItemIdx
+    where
+        F: Fn(&C::Target, &ActionStylus) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartFilteredInput(Shared::new(
+            ActionExecutionCtx::make_input_filter_with_stylus_filter(filter),
+        ) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_matched_touch_input(&mut self, ending: bool) -> ActionRecipeItemIdx {
+        let input = ActionRecipeItem::StartExecutionFilteredInput(Shared::new(
+            ActionExecutionCtx::make_input_filter_with_matched_touch(ending),
+        ) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_cursor_distance_filtered_input<P, M>(
+        &mut self,
+        threshold: f64,
+        position: P,
+        metric: M,
+    ) -> ActionRecipeItemIdx
+    where
+        P: Fn(&C::Target) -> Option<C::CursorPos> + MaybeSendSync + 'f,
+        M: Fn(&C::CursorPos, &C::CursorPos) -> f64 + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartExecutionFilteredInput(Shared::new(
+            ActionExecutionCtx::make_input_filter_with_cursor_distance_filter(
+                threshold, position, metric,
+            ),
+        ) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_cursor_motion_filtered_input<P, F>(
+        &mut self,
+        position: P,
+        predicate: F,
+    ) -> ActionRecipeItemIdx
+    where
+        P: Fn(&C::Target) -> Option<C::CursorPos> + MaybeSendSync + 'f,
+        F: Fn(&ActionCursorMotion<C::CursorPos>) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartExecutionFilteredInput(Shared::new(
+            ActionExecutionCtx::make_input_filter_with_cursor_motion_filter(position, predicate),
+        ) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_focus_coordinate_filtered_input<F>(
+        &mut self,
+        filter: F,
+    ) -> ActionRecipeItemIdx
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartFilteredInput(Shared::new(
+            ActionExecutionCtx::make_input_filter_with_focus_coordinate_filter(filter),
+        ) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_touch_filtered_input<F>(
+        &mut self,
+        filter: F,
+        phase: ActionTouchPhase,
+    ) -> ActionRecipeItemIdx
+    where
+        F: Fn(&ActionTouch) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartFilteredInput(Shared::new(
+            ActionExecutionCtx::make_input_filter_with_touch_filter(filter, phase),
+        ) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
     fn add_primitive_start_key_down_input(&mut self, key: C::KeyKind) -> ActionRecipeItemIdx {
-        let input = ActionRecipeItem::StartInput(ActionInput::KeyDown(key));
+        let input = ActionRecipeItem::StartInput(Shared::new(ActionInput::KeyDown(key)));
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_key_filtered_input<F>(
+        &mut self,
+        filter: F,
+        key_down: bool,
+    ) -> ActionRecipeItemIdx
+    where
+        F: Fn(&C::KeyKind) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartFilteredInput(Shared::new(
+            ActionExecutionCtx::make_input_filter_with_key_filter(filter, key_down),
+        ) as _);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_axis_input(
+        &mut self,
+        axis: C::KeyKind,
+        threshold: f32,
+        beyond: bool,
+    ) -> ActionRecipeItemIdx {
+        let input = ActionRecipeItem::StartAxisInput(axis, threshold, beyond);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_key_repeat_input(&mut self, key: C::KeyKind) -> ActionRecipeItemIdx {
+        let input = ActionRecipeItem::StartInput(Shared::new(ActionInput::KeyRepeat(key)));
         let item_idx = self.context_builder.recipe_items.register_item(input);
         self.add_recipe_item(item_idx);
         item_idx
     }
 
     fn add_primitive_start_key_up_input(&mut self, key: C::KeyKind) -> ActionRecipeItemIdx {
-        let input = ActionRecipeItem::StartInput(ActionInput::KeyUp(key));
+        let input = ActionRecipeItem::StartInput(Shared::new(ActionInput::KeyUp(key)));
         let item_idx = self.context_builder.recipe_items.register_item(input);
         self.add_recipe_item(item_idx);
         item_idx
@@ -491,6 +2488,141 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         item_idx
     }
 
+    fn add_primitive_start_focus_condition<F>(&mut self, filter: F) -> ActionRecipeItemIdx
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartCondition(ActionCondition::FocusIn(
+            ActionTargetFilter::new(filter),
+        ));
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_condition(
+        &mut self,
+        condition: ActionCondition<'f, C>,
+    ) -> ActionRecipeItemIdx {
+        let input = ActionRecipeItem::StartCondition(condition);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_cursor_condition<F>(&mut self, filter: F) -> ActionRecipeItemIdx
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartCondition(ActionCondition::CursorOver(
+            ActionTargetFilter::new(filter),
+        ));
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_cursor_transition<F>(
+        &mut self,
+        filter: F,
+        entered: bool,
+    ) -> ActionRecipeItemIdx
+    where
+        F: Fn(&C::Target) -> bool + MaybeSendSync + 'f,
+    {
+        let input =
+            ActionRecipeItem::StartCursorTransition(ActionTargetFilter::new(filter), entered);
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_dwell(&mut self, duration: Duration) -> ActionRecipeItemIdx {
+        let item_idx = self
+            .context_builder
+            .recipe_items
+            .register_item(ActionRecipeItem::StartDwell(duration));
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_held_key_up(
+        &mut self,
+        key: C::KeyKind,
+        min_hold: Duration,
+        on_early_release: ActionMatchDecision,
+    ) -> ActionRecipeItemIdx {
+        let item_idx =
+            self.context_builder
+                .recipe_items
+                .register_item(ActionRecipeItem::StartHeldKeyUp(
+                    key,
+                    min_hold,
+                    on_early_release,
+                ));
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_start_idle(&mut self, duration: Duration) -> ActionRecipeItemIdx {
+        let item_idx = self
+            .context_builder
+            .recipe_items
+            .register_item(ActionRecipeItem::StartIdle(duration));
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_capture_cursor_target(&mut self) -> ActionRecipeItemIdx {
+        let item_idx = self
+            .context_builder
+            .recipe_items
+            .register_item(ActionRecipeItem::CaptureCursorTarget);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    fn add_primitive_check_execution<F>(&mut self, predicate: F) -> ActionRecipeItemIdx
+    where
+        F: Fn(ActionRecipeExecutionInfo<C>) -> bool + MaybeSendSync + 'f,
+    {
+        let item = ActionRecipeItem::CheckExecution(Shared::new(predicate));
+        let item_idx = self.context_builder.recipe_items.register_item(item);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    // checks the inputs of the last unordered item added, if any.
+    fn add_primitive_check_unordered_window(
+        &mut self,
+        window: Duration,
+    ) -> Option<ActionRecipeItemIdx> {
+        let recipe_items = &self.context_builder.recipe_items;
+        let branches = self.item_idxes.iter().rev().find_map(|&item_idx| {
+            match recipe_items.get(item_idx) {
+                Ok(ActionRecipeItem::Unordered(seq, _)) => Some(seq.clone()),
+                _ => None,
+            }
+        })?;
+        Some(
+            self.add_primitive_check_execution(move |info| {
+                info.arrival_spread(&branches) <= window
+            }),
+        )
+    }
+
+    fn add_primitive_start_custom_condition<F>(&mut self, predicate: F) -> ActionRecipeItemIdx
+    where
+        F: Fn(&ActionEnvironmentView<C>) -> bool + MaybeSendSync + 'f,
+    {
+        let input = ActionRecipeItem::StartCondition(ActionCondition::Custom(
+            ActionEnvironmentPredicate::new(predicate),
+        ));
+        let item_idx = self.context_builder.recipe_items.register_item(input);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
     fn add_primitive_start_nest_recipe(&mut self, nest_recipe: usize) -> ActionRecipeItemIdx {
         let input = ActionRecipeItem::StartNestRecipe(nest_recipe);
         let item_idx = self.context_builder.recipe_items.register_item(input);
@@ -505,6 +2637,15 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         item_idx
     }
 
+    fn add_primitive_checkpoint(&mut self) -> ActionRecipeItemIdx {
+        let item_idx = self
+            .context_builder
+            .recipe_items
+            .register_item(ActionRecipeItem::Checkpoint);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
     fn add_primitive_eliminate_item(&mut self, item: ActionRecipeItemIdx) -> ActionRecipeItemIdx {
         let input = ActionRecipeItem::EliminateItem(item);
         let item_idx = self.context_builder.recipe_items.register_item(input);
@@ -513,7 +2654,7 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
     }
 
     pub fn add_primitive_issue_command(&mut self, command: C::Command) -> ActionRecipeItemIdx {
-        let command = ActionRecipeItem::DoCommand(ActionRecipeCommand(command));
+        let command = ActionRecipeItem::DoCommand(ActionRecipeCommand(Shared::new(command)));
         let item_idx = self.context_builder.recipe_items.register_item(command);
         self.add_recipe_item(item_idx);
         item_idx
@@ -524,9 +2665,10 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         command_generator: F,
     ) -> ActionRecipeItemIdx
     where
-        F: Fn(ActionRecipeExecutionInfo<C>) -> C::Command + 'static,
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend + 'f,
     {
-        let command_of = ActionRecipeItem::DoCommandOf(Box::new(command_generator) as _);
+        let command_of =
+            ActionRecipeItem::DoCommandOf(GeneratorCell::new(Box::new(command_generator) as _));
         let item_idx = self.context_builder.recipe_items.register_item(command_of);
         self.add_recipe_item(item_idx);
         item_idx
@@ -537,7 +2679,28 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         effect_start: C::Command,
         effect_end: C::Command,
     ) -> ActionRecipeItemIdx {
-        let command = ActionRecipeItem::StartEffect(ActionRecipeEffect(effect_start, effect_end));
+        let effect_end = Shared::new(effect_end);
+        let command = ActionRecipeItem::StartEffect(ActionRecipeEffect(
+            Shared::new(effect_start),
+            effect_end.clone(),
+            effect_end,
+        ));
+        let item_idx = self.context_builder.recipe_items.register_item(command);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
+    pub fn add_primitive_issue_effect_with_abort(
+        &mut self,
+        effect_start: C::Command,
+        effect_end_on_complete: C::Command,
+        effect_end_on_abort: C::Command,
+    ) -> ActionRecipeItemIdx {
+        let command = ActionRecipeItem::StartEffect(ActionRecipeEffect(
+            Shared::new(effect_start),
+            Shared::new(effect_end_on_complete),
+            Shared::new(effect_end_on_abort),
+        ));
         let item_idx = self.context_builder.recipe_items.register_item(command);
         self.add_recipe_item(item_idx);
         item_idx
@@ -545,21 +2708,39 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
 
     pub fn add_primitive_issue_effect_with<F>(&mut self, effect_generator: F) -> ActionRecipeItemIdx
     where
-        F: Fn(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command) + 'static,
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> (C::Command, C::Command) + MaybeSend + 'f,
     {
-        let effect_of = ActionRecipeItem::StartEffectOf(Box::new(effect_generator) as _);
+        let effect_of =
+            ActionRecipeItem::StartEffectOf(GeneratorCell::new(Box::new(effect_generator) as _));
         let item_idx = self.context_builder.recipe_items.register_item(effect_of);
         self.add_recipe_item(item_idx);
         item_idx
     }
 
+    pub fn add_primitive_issue_updates_with<F>(
+        &mut self,
+        rate: ActionUpdateRate,
+        update_generator: F,
+    ) -> ActionRecipeItemIdx
+    where
+        F: FnMut(ActionRecipeExecutionInfo<C>) -> C::Command + MaybeSend + 'f,
+    {
+        let updates_of = ActionRecipeItem::StartUpdatesOf(
+            GeneratorCell::new(Box::new(update_generator) as _),
+            rate,
+        );
+        let item_idx = self.context_builder.recipe_items.register_item(updates_of);
+        self.add_recipe_item(item_idx);
+        item_idx
+    }
+
     pub fn add_compound_sequence<F>(
         &mut self,
         kind: ActionRecipeSequenceKind,
         sequence_generator: F,
     ) -> ActionRecipeItemIdx
     where
-        F: for<'r> FnOnce(&mut ActionRecipeSequenceBuilder<'r, C>),
+        F: for<'r> FnOnce(&mut ActionRecipeSequenceBuilder<'r, 'f, C>),
     {
         let sequence = {
             let mut builder = ActionRecipeSequenceBuilder::new_inner(self, kind);
@@ -571,15 +2752,22 @@ impl<'a, C: ActionConfiguration> ActionRecipeSequenceBuilder<'a, C> {
         item_idx
     }
 
-    fn build(self) -> (&'a mut ActionContextBuilder<C>, ActionRecipeItem<C>) {
+    fn build(self) -> (&'a mut ActionContextBuilder<'f, C>, ActionRecipeItem<'f, C>) {
         (
             self.context_builder,
             match self.kind {
                 ActionRecipeSequenceKind::Sequential => {
                     ActionRecipeItem::Sequential(self.item_idxes)
                 }
-                ActionRecipeSequenceKind::Unordered => ActionRecipeItem::Unordered(self.item_idxes),
+                ActionRecipeSequenceKind::Unordered(required) => {
+                    let required = required.unwrap_or(self.item_idxes.len());
+                    ActionRecipeItem::Unordered(self.item_idxes, required)
+                }
                 ActionRecipeSequenceKind::Choice => ActionRecipeItem::Choice(self.item_idxes),
+                ActionRecipeSequenceKind::Optional => ActionRecipeItem::Optional(self.item_idxes),
+                ActionRecipeSequenceKind::Repeated(repetition) => {
+                    ActionRecipeItem::Repeated(self.item_idxes, repetition)
+                }
             },
         )
     }
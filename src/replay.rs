@@ -0,0 +1,184 @@
+use std::fmt::Debug;
+use std::time::Duration;
+use ActionConfiguration;
+use ActionContext;
+use ActionInput;
+
+/// Inputs fed into a context, stamped with the context time relative to the start of
+/// the recording, along with every command collected from it.
+pub struct ActionRecording<C: ActionConfiguration> {
+    inputs: Vec<(Duration, ActionInput<C>)>,
+    commands: Vec<C::Command>,
+    duration: Duration,
+}
+
+impl<C: ActionConfiguration> ActionRecording<C> {
+    pub fn inputs(&self) -> &[(Duration, ActionInput<C>)] {
+        &self.inputs
+    }
+
+    pub fn commands(&self) -> &[C::Command] {
+        &self.commands
+    }
+
+    /// Context time covered by the recording, including the time after the last input.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Feeds the recorded inputs into `context` at the same relative times and returns
+    /// the commands it issues.
+    pub fn replay(&self, context: &mut ActionContext<C>) -> Vec<C::Command>
+    where
+        C::Command: Clone,
+    {
+        let start_time = context.current_time();
+        let mut commands = Vec::new();
+        let mut collect = |context: &mut ActionContext<C>| {
+            if let Some(new_commands) = context.collect_commands() {
+                commands.extend(new_commands);
+            }
+        };
+        for (time, input) in &self.inputs {
+            let elapsed = (start_time + *time).saturating_sub(context.current_time());
+            if elapsed > Duration::default() {
+                context.update(elapsed);
+                collect(context);
+            }
+            context.process_input(input);
+            collect(context);
+        }
+        let elapsed = (start_time + self.duration).saturating_sub(context.current_time());
+        if elapsed > Duration::default() {
+            context.update(elapsed);
+            collect(context);
+        }
+        commands
+    }
+
+    /// Replays into `context` and panics unless it issues exactly the recorded commands.
+    pub fn assert_replay(&self, context: &mut ActionContext<C>)
+    where
+        C::Command: Clone + PartialEq + Debug,
+    {
+        let commands = self.replay(context);
+        assert_eq!(
+            commands, self.commands,
+            "replayed commands differ from the recorded ones"
+        );
+    }
+}
+
+/// Wraps a context and records what passes through it.
+pub struct ActionRecorder<'f, C: ActionConfiguration> {
+    context: ActionContext<'f, C>,
+    start_time: Duration,
+    recording: ActionRecording<C>,
+}
+
+impl<'f, C: ActionConfiguration> ActionRecorder<'f, C>
+where
+    C::Command: Clone,
+{
+    pub fn new(context: ActionContext<'f, C>) -> Self {
+        ActionRecorder {
+            start_time: context.current_time(),
+            context,
+            recording: ActionRecording {
+                inputs: Vec::new(),
+                commands: Vec::new(),
+                duration: Duration::default(),
+            },
+        }
+    }
+
+    pub fn context(&self) -> &ActionContext<'f, C> {
+        &self.context
+    }
+
+    pub fn process_input(&mut self, input: &ActionInput<C>) -> bool {
+        // the input is stamped with the time it is processed at.
+        self.context.sync_time();
+        let time = self.context.current_time() - self.start_time;
+        self.recording.inputs.push((time, input.clone()));
+        self.context.process_input(input)
+    }
+
+    pub fn process_inputs(&mut self, inputs: &[ActionInput<C>]) -> bool {
+        let mut result = false;
+        for input in inputs {
+            if self.process_input(input) {
+                result = true;
+            }
+        }
+        result
+    }
+
+    pub fn update(&mut self, dt: Duration) -> bool {
+        self.context.update(dt)
+    }
+
+    pub fn collect_commands(&mut self) -> Vec<C::Command> {
+        let commands = match self.context.collect_commands() {
+            Some(commands) => commands.collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        self.recording.commands.extend(commands.iter().cloned());
+        commands
+    }
+
+    /// Stops recording. Commands that haven't been collected yet are recorded too.
+    pub fn finish(mut self) -> (ActionContext<'f, C>, ActionRecording<C>) {
+        self.collect_commands();
+        self.recording.duration = self.context.current_time() - self.start_time;
+        (self.context, self.recording)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ActionRecorder;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use ActionConfiguration;
+    use ActionContextBuilder;
+    use ActionInput::{KeyDown, KeyUp};
+
+    struct Config;
+
+    impl ActionConfiguration for Config {
+        type Target = ();
+        type KeyKind = char;
+        type CursorPos = ();
+        type Command = &'static str;
+        type EnvExt = ();
+    }
+
+    #[test]
+    fn inputs_are_stamped_with_the_synced_time() {
+        let clock = Arc::new(AtomicU64::new(0));
+        let source = clock.clone();
+        let context = ActionContextBuilder::<Config>::new()
+            .with_time_source(move || Duration::from_millis(source.load(Ordering::SeqCst)))
+            .add_recipe(|b| b.add_key_click_input('a').issue_command("a").build())
+            .build()
+            .unwrap();
+        let mut recorder = ActionRecorder::new(context);
+        clock.store(100, Ordering::SeqCst);
+        recorder.process_input(&KeyDown('a'));
+        clock.store(250, Ordering::SeqCst);
+        recorder.process_input(&KeyUp('a'));
+        let (_, recording) = recorder.finish();
+        let stamps = recording
+            .inputs()
+            .iter()
+            .map(|(time, _)| *time)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stamps,
+            vec![Duration::from_millis(100), Duration::from_millis(250)]
+        );
+        assert_eq!(recording.commands(), &["a"]);
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::iter::FromIterator;
+
+/// Reference-counted pointer holding commands: `Rc`, or `Arc` with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub type Shared<T> = ::std::rc::Rc<T>;
+#[cfg(feature = "sync")]
+pub type Shared<T> = ::std::sync::Arc<T>;
+
+/// Extra bound on closures stored inside recipes: `Send + Sync` with the `sync`
+/// feature enabled, so that contexts can be moved across threads, and implemented
+/// by every type otherwise.
+#[cfg(feature = "sync")]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + ?Sized> MaybeSendSync for T {}
+
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSendSync {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSendSync for T {}
+
+/// Extra bound on stateful (`FnMut`) closures stored inside recipes: `Send` with the
+/// `sync` feature enabled, and implemented by every type otherwise.
+#[cfg(feature = "sync")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "sync")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// Interior mutability for stateful closures: a `RefCell`, or a `Mutex` with the
+/// `sync` feature enabled.
+pub(crate) struct GeneratorCell<T> {
+    #[cfg(not(feature = "sync"))]
+    inner: ::std::cell::RefCell<T>,
+    #[cfg(feature = "sync")]
+    inner: ::std::sync::Mutex<T>,
+}
+
+impl<T> GeneratorCell<T> {
+    pub(crate) fn new(value: T) -> Self {
+        GeneratorCell {
+            inner: From::from(value),
+        }
+    }
+
+    #[cfg(not(feature = "sync"))]
+    pub(crate) fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.inner.borrow_mut())
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        let mut guard = self.inner.lock().unwrap_or_else(|x| x.into_inner());
+        f(&mut guard)
+    }
+}
+
+/// Bound on `ActionConfiguration::KeyKind`, choosing the collections keys are kept
+/// in. Every `Ord` key type uses ordered ones; a key type without a sensible ordering
+/// implements this trait itself with `type Storage = ActionHashedKeys`.
+pub trait ActionKey: Clone + PartialEq + Debug {
+    type Storage: ActionKeyStorage<Self>;
+}
+
+impl<T: Clone + Ord + Debug> ActionKey for T {
+    type Storage = ActionOrderedKeys;
+}
+
+/// Keeps keys in a `BTreeSet` and a `BTreeMap`.
+#[derive(Clone, Copy, Debug)]
+pub enum ActionOrderedKeys {}
+
+/// Keeps keys in a `HashSet` and a `HashMap`.
+#[derive(Clone, Copy, Debug)]
+pub enum ActionHashedKeys {}
+
+/// The set and map of keys of type `K` a storage kind provides, implemented by
+/// `ActionOrderedKeys` and `ActionHashedKeys`.
+pub trait ActionKeyStorage<K> {
+    #[doc(hidden)]
+    type Set: Default
+        + Clone
+        + PartialEq
+        + Debug
+        + Extend<K>
+        + FromIterator<K>
+        + IntoIterator<Item = K>;
+    #[doc(hidden)]
+    type Map<V>;
+
+    #[doc(hidden)]
+    fn set_insert(set: &mut Self::Set, key: K) -> bool;
+    #[doc(hidden)]
+    fn set_remove(set: &mut Self::Set, key: &K) -> bool;
+    #[doc(hidden)]
+    fn set_contains(set: &Self::Set, key: &K) -> bool;
+    #[doc(hidden)]
+    fn set_iter<'a>(set: &'a Self::Set) -> Box<dyn Iterator<Item = &'a K> + 'a>;
+    #[doc(hidden)]
+    fn set_clear(set: &mut Self::Set);
+    #[doc(hidden)]
+    fn set_len(set: &Self::Set) -> usize;
+
+    #[doc(hidden)]
+    fn map_new<V>() -> Self::Map<V>;
+    #[doc(hidden)]
+    fn map_clone<V: Clone>(map: &Self::Map<V>) -> Self::Map<V>;
+    #[doc(hidden)]
+    fn map_fmt<V: Debug>(map: &Self::Map<V>, f: &mut fmt::Formatter) -> fmt::Result;
+    #[doc(hidden)]
+    fn map_get<'a, V>(map: &'a Self::Map<V>, key: &K) -> Option<&'a V>;
+    #[doc(hidden)]
+    fn map_get_mut<'a, V>(map: &'a mut Self::Map<V>, key: &K) -> Option<&'a mut V>;
+    #[doc(hidden)]
+    fn map_get_or_default<V: Default>(map: &mut Self::Map<V>, key: K) -> &mut V;
+    #[doc(hidden)]
+    fn map_insert<V>(map: &mut Self::Map<V>, key: K, value: V) -> Option<V>;
+    #[doc(hidden)]
+    fn map_remove<V>(map: &mut Self::Map<V>, key: &K) -> Option<V>;
+    #[doc(hidden)]
+    fn map_retain<V, F: FnMut(&K, &mut V) -> bool>(map: &mut Self::Map<V>, f: F);
+    #[doc(hidden)]
+    fn map_iter<'a, V>(map: &'a Self::Map<V>) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a>;
+    #[doc(hidden)]
+    fn map_clear<V>(map: &mut Self::Map<V>);
+}
+
+macro_rules! impl_key_storage {
+    ($storage:ty, $set:ident, $map:ident, $($bound:tt)+) => {
+        impl<K: Clone + Debug + $($bound)+> ActionKeyStorage<K> for $storage {
+            type Set = $set<K>;
+            type Map<V> = $map<K, V>;
+
+            fn set_insert(set: &mut Self::Set, key: K) -> bool {
+                set.insert(key)
+            }
+            fn set_remove(set: &mut Self::Set, key: &K) -> bool {
+                set.remove(key)
+            }
+            fn set_contains(set: &Self::Set, key: &K) -> bool {
+                set.contains(key)
+            }
+            fn set_iter<'a>(set: &'a Self::Set) -> Box<dyn Iterator<Item = &'a K> + 'a> {
+                Box::new(set.iter())
+            }
+            fn set_clear(set: &mut Self::Set) {
+                set.clear()
+            }
+            fn set_len(set: &Self::Set) -> usize {
+                set.len()
+            }
+
+            fn map_new<V>() -> Self::Map<V> {
+                $map::new()
+            }
+            fn map_clone<V: Clone>(map: &Self::Map<V>) -> Self::Map<V> {
+                map.clone()
+            }
+            fn map_fmt<V: Debug>(map: &Self::Map<V>, f: &mut fmt::Formatter) -> fmt::Result {
+                map.fmt(f)
+            }
+            fn map_get<'a, V>(map: &'a Self::Map<V>, key: &K) -> Option<&'a V> {
+                map.get(key)
+            }
+            fn map_get_mut<'a, V>(map: &'a mut Self::Map<V>, key: &K) -> Option<&'a mut V> {
+                map.get_mut(key)
+            }
+            fn map_get_or_default<V: Default>(map: &mut Self::Map<V>, key: K) -> &mut V {
+                map.entry(key).or_default()
+            }
+            fn map_insert<V>(map: &mut Self::Map<V>, key: K, value: V) -> Option<V> {
+                map.insert(key, value)
+            }
+            fn map_remove<V>(map: &mut Self::Map<V>, key: &K) -> Option<V> {
+                map.remove(key)
+            }
+            fn map_retain<V, F: FnMut(&K, &mut V) -> bool>(map: &mut Self::Map<V>, f: F) {
+                map.retain(f)
+            }
+            fn map_iter<'a, V>(
+                map: &'a Self::Map<V>,
+            ) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+                Box::new(map.iter())
+            }
+            fn map_clear<V>(map: &mut Self::Map<V>) {
+                map.clear()
+            }
+        }
+    };
+}
+
+impl_key_storage!(ActionOrderedKeys, BTreeSet, BTreeMap, Ord);
+impl_key_storage!(ActionHashedKeys, HashSet, HashMap, Eq + Hash);
+
+type StorageOf<K> = <K as ActionKey>::Storage;
+
+/// Set of keys in the storage their type chose.
+pub(crate) struct KeySet<K: ActionKey>(<StorageOf<K> as ActionKeyStorage<K>>::Set);
+
+impl<K: ActionKey> KeySet<K> {
+    pub(crate) fn new() -> Self {
+        KeySet(Default::default())
+    }
+
+    pub(crate) fn insert(&mut self, key: K) -> bool {
+        StorageOf::<K>::set_insert(&mut self.0, key)
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) -> bool {
+        StorageOf::<K>::set_remove(&mut self.0, key)
+    }
+
+    pub(crate) fn contains(&self, key: &K) -> bool {
+        StorageOf::<K>::set_contains(&self.0, key)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &K> + '_ {
+        StorageOf::<K>::set_iter(&self.0)
+    }
+
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    pub(crate) fn difference<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a K> + 'a {
+        self.iter().filter(move |key| !other.contains(key))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        StorageOf::<K>::set_clear(&mut self.0)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        StorageOf::<K>::set_len(&self.0)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: ActionKey> Default for KeySet<K> {
+    fn default() -> Self {
+        KeySet::new()
+    }
+}
+
+impl<K: ActionKey> Clone for KeySet<K> {
+    fn clone(&self) -> Self {
+        KeySet(self.0.clone())
+    }
+}
+
+impl<K: ActionKey> PartialEq for KeySet<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<K: ActionKey> Debug for KeySet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<K: ActionKey> Extend<K> for KeySet<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl<K: ActionKey> FromIterator<K> for KeySet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        KeySet(iter.into_iter().collect())
+    }
+}
+
+impl<K: ActionKey> IntoIterator for KeySet<K> {
+    type Item = K;
+    type IntoIter = <<StorageOf<K> as ActionKeyStorage<K>>::Set as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Map from keys in the storage their type chose.
+pub(crate) struct KeyMap<K: ActionKey, V>(<StorageOf<K> as ActionKeyStorage<K>>::Map<V>);
+
+impl<K: ActionKey, V> KeyMap<K, V> {
+    pub(crate) fn new() -> Self {
+        KeyMap(StorageOf::<K>::map_new())
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<&V> {
+        StorageOf::<K>::map_get(&self.0, key)
+    }
+
+    #[cfg_attr(not(feature = "gilrs"), allow(dead_code))]
+    pub(crate) fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        StorageOf::<K>::map_get_mut(&mut self.0, key)
+    }
+
+    pub(crate) fn get_or_default(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        StorageOf::<K>::map_get_or_default(&mut self.0, key)
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        StorageOf::<K>::map_insert(&mut self.0, key, value)
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) -> Option<V> {
+        StorageOf::<K>::map_remove(&mut self.0, key)
+    }
+
+    pub(crate) fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+        StorageOf::<K>::map_retain(&mut self.0, f)
+    }
+
+    #[cfg_attr(not(feature = "gilrs"), allow(dead_code))]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        StorageOf::<K>::map_iter(&self.0)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        StorageOf::<K>::map_clear(&mut self.0)
+    }
+}
+
+impl<K: ActionKey, V> Default for KeyMap<K, V> {
+    fn default() -> Self {
+        KeyMap::new()
+    }
+}
+
+impl<K: ActionKey, V: Clone> Clone for KeyMap<K, V> {
+    fn clone(&self) -> Self {
+        KeyMap(StorageOf::<K>::map_clone(&self.0))
+    }
+}
+
+impl<K: ActionKey, V: Debug> Debug for KeyMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        StorageOf::<K>::map_fmt(&self.0, f)
+    }
+}
@@ -0,0 +1,207 @@
+use futures::stream::Stream;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use ActionConfiguration;
+use ActionContext;
+use ActionInput;
+
+/// Time source and sleep primitive used by `ActionCommandStream` to drive recipe timeouts.
+pub trait ActionTimer {
+    type Sleep: Future<Output = ()>;
+
+    /// Monotonic time elapsed since an arbitrary fixed starting point.
+    fn now(&self) -> Duration;
+
+    fn sleep(&mut self, duration: Duration) -> Self::Sleep;
+}
+
+pub struct ActionCommandStream<'f, C: ActionConfiguration, S, T: ActionTimer> {
+    context: ActionContext<'f, C>,
+    inputs: S,
+    inputs_finished: bool,
+    timer: T,
+    last_time: Duration,
+    sleep: Option<Pin<Box<T::Sleep>>>,
+    pending_commands: VecDeque<C::Command>,
+}
+
+impl<'f, C: ActionConfiguration, S, T: ActionTimer> ActionCommandStream<'f, C, S, T>
+where
+    C::Command: Clone,
+{
+    pub fn new(context: ActionContext<'f, C>, inputs: S, timer: T) -> Self {
+        let last_time = timer.now();
+        ActionCommandStream {
+            context,
+            inputs,
+            inputs_finished: false,
+            timer,
+            last_time,
+            sleep: None,
+            pending_commands: VecDeque::new(),
+        }
+    }
+
+    pub fn context(&self) -> &ActionContext<'f, C> {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut ActionContext<'f, C> {
+        &mut self.context
+    }
+
+    pub fn into_inner(self) -> (ActionContext<'f, C>, S, T) {
+        (self.context, self.inputs, self.timer)
+    }
+
+    fn sync_time(&mut self) {
+        let now = self.timer.now();
+        let elapsed = now.saturating_sub(self.last_time);
+        self.last_time = now;
+        self.context.update(elapsed);
+    }
+
+    fn collect_commands(&mut self) {
+        if let Some(commands) = self.context.collect_commands() {
+            self.pending_commands.extend(commands);
+        }
+    }
+}
+
+// No field is ever structurally pinned: the sleep future is boxed and the
+// input stream is required to be `Unpin` itself.
+impl<'f, C: ActionConfiguration, S: Unpin, T: ActionTimer + Unpin> Unpin
+    for ActionCommandStream<'f, C, S, T>
+{
+}
+
+impl<'f, C, S, T> Stream for ActionCommandStream<'f, C, S, T>
+where
+    C: ActionConfiguration,
+    C::Command: Clone,
+    S: Stream<Item = ActionInput<C>> + Unpin,
+    T: ActionTimer + Unpin,
+{
+    type Item = C::Command;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<C::Command>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(command) = this.pending_commands.pop_front() {
+                return Poll::Ready(Some(command));
+            }
+            if this.inputs_finished {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut this.inputs).poll_next(cx) {
+                Poll::Ready(Some(input)) => {
+                    this.sync_time();
+                    this.context.process_input(&input);
+                    // executions may have started or ended, so the pending deadline is stale.
+                    this.sleep = None;
+                    this.collect_commands();
+                    continue;
+                }
+                Poll::Ready(None) => {
+                    this.inputs_finished = true;
+                    continue;
+                }
+                Poll::Pending => {}
+            }
+
+            if this.sleep.is_none() {
+                this.sync_time();
+                this.collect_commands();
+                if !this.pending_commands.is_empty() {
+                    continue;
+                }
+                match this.context.next_timeout() {
+                    Some(timeout) => this.sleep = Some(Box::pin(this.timer.sleep(timeout))),
+                    None => return Poll::Pending,
+                }
+            }
+
+            match this.sleep.as_mut().map(|sleep| sleep.as_mut().poll(cx)) {
+                Some(Poll::Ready(())) => {
+                    this.sleep = None;
+                    this.sync_time();
+                    this.collect_commands();
+                }
+                _ => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActionCommandStream, ActionTimer};
+    use futures::future::{ready, Ready};
+    use futures::stream::{self, Stream, StreamExt};
+    use futures::task::noop_waker_ref;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+    use ActionConfiguration;
+    use ActionContextBuilder;
+    use ActionInput::KeyDown;
+
+    struct Config;
+
+    impl ActionConfiguration for Config {
+        type Target = ();
+        type KeyKind = char;
+        type CursorPos = ();
+        type Command = &'static str;
+        type EnvExt = ();
+    }
+
+    // Sleeping moves the clock on by the time slept, right away.
+    struct FakeTimer {
+        now: Duration,
+    }
+
+    impl ActionTimer for FakeTimer {
+        type Sleep = Ready<()>;
+
+        fn now(&self) -> Duration {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) -> Ready<()> {
+            self.now += duration;
+            ready(())
+        }
+    }
+
+    fn poll<S: Stream + Unpin>(stream: &mut S) -> Poll<Option<S::Item>> {
+        Pin::new(stream).poll_next(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    #[test]
+    fn timed_out_execution_aborts_while_waiting_for_input() {
+        let context = ActionContextBuilder::<Config>::new()
+            .add_recipe(|b| {
+                b.with_timeout(Duration::from_millis(100))
+                    .add_key_down_input('a')
+                    .add_key_down_input('b')
+                    .issue_command("ab")
+                    .on_abort_issue("timed out")
+                    .build()
+            })
+            .build()
+            .unwrap();
+        let inputs = stream::iter(vec![KeyDown('a')]).chain(stream::pending());
+        let timer = FakeTimer {
+            now: Duration::default(),
+        };
+        let mut commands = ActionCommandStream::new(context, inputs, timer);
+        assert_eq!(poll(&mut commands), Poll::Ready(Some("timed out")));
+        assert_eq!(poll(&mut commands), Poll::Pending);
+        assert!(!commands.context().has_active_executions());
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt::Debug;
+use std::time::Duration;
+use ActionConfiguration;
+use ActionContext;
+use ActionContextBuilder;
+use ActionInput;
+
+/// Drives a context through inputs and time, asserting on the commands it issues.
+pub struct Harness<'f, C: ActionConfiguration> {
+    context: ActionContext<'f, C>,
+}
+
+impl<'f, C: ActionConfiguration> Harness<'f, C>
+where
+    C::Command: Clone + PartialEq + Debug,
+{
+    #[track_caller]
+    pub fn new(builder: ActionContextBuilder<'f, C>) -> Self {
+        match builder.build() {
+            Ok(context) => Harness { context },
+            Err(error) => panic!("failed to build the context: {}", error),
+        }
+    }
+
+    pub fn from_context(context: ActionContext<'f, C>) -> Self {
+        Harness { context }
+    }
+
+    pub fn context(&self) -> &ActionContext<'f, C> {
+        &self.context
+    }
+
+    pub fn into_context(self) -> ActionContext<'f, C> {
+        self.context
+    }
+
+    pub fn feed<I>(mut self, inputs: I) -> Self
+    where
+        I: IntoIterator<Item = ActionInput<C>>,
+    {
+        for input in inputs {
+            self.context.process_input(&input);
+        }
+        self
+    }
+
+    pub fn advance(mut self, elapsed: Duration) -> Self {
+        self.context.update(elapsed);
+        self
+    }
+
+    /// Collects the commands issued so far and asserts they are exactly `expected`.
+    #[track_caller]
+    pub fn expect_commands<I>(mut self, expected: I) -> Self
+    where
+        I: IntoIterator<Item = C::Command>,
+    {
+        let expected = expected.into_iter().collect::<Vec<_>>();
+        let commands = self.collect_commands();
+        assert_eq!(commands, expected, "unexpected commands");
+        self
+    }
+
+    #[track_caller]
+    pub fn expect_no_commands(mut self) -> Self {
+        let commands = self.collect_commands();
+        assert!(commands.is_empty(), "unexpected commands: {:?}", commands);
+        self
+    }
+
+    /// Asserts that all commands have been collected and no execution is halfway through.
+    #[track_caller]
+    pub fn expect_no_pending(mut self) -> Self {
+        let commands = self.collect_commands();
+        assert!(
+            commands.is_empty(),
+            "commands still pending: {:?}",
+            commands
+        );
+        assert!(
+            !self.context.has_active_executions(),
+            "executions still in progress: {:?}",
+            self.context
+        );
+        self
+    }
+
+    fn collect_commands(&mut self) -> Vec<C::Command> {
+        match self.context.collect_commands() {
+            Some(commands) => commands.collect(),
+            None => Vec::new(),
+        }
+    }
+}
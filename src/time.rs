@@ -0,0 +1,50 @@
+use shared::MaybeSendSync;
+use std::time::{Duration, Instant};
+
+/// Where the context reads the time from, e.g. a monotonic clock, a frame counter
+/// or a fake a test moves by hand. Installed with
+/// `ActionContextBuilder::with_time_source`.
+pub trait ActionTimeSource {
+    /// Monotonic time elapsed since an arbitrary fixed starting point.
+    fn now(&self) -> Duration;
+}
+
+impl<F> ActionTimeSource for F
+where
+    F: Fn() -> Duration,
+{
+    fn now(&self) -> Duration {
+        self()
+    }
+}
+
+pub(crate) trait ActionTimeSourceObj: ActionTimeSource + MaybeSendSync {}
+
+impl<S: ActionTimeSource + MaybeSendSync> ActionTimeSourceObj for S {}
+
+/// Time since the clock was created, as measured by `std::time::Instant`. Targets
+/// without a system clock, such as wasm in a browser, need a source of their own.
+#[derive(Copy, Clone, Debug)]
+pub struct ActionSystemClock {
+    start: Instant,
+}
+
+impl ActionSystemClock {
+    pub fn new() -> Self {
+        ActionSystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for ActionSystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActionTimeSource for ActionSystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
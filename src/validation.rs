@@ -0,0 +1,258 @@
+use context::{ActionRecipeItemIdx, ActionRecipeItemStore};
+use error::ConcertoError;
+use execution::{ActionExecutionCtx, ExecutionContextResult};
+use recipe::{ActionCondition, ActionInput, ActionRecipeItem};
+use std::collections::BTreeSet;
+use ActionConfiguration;
+
+/// Checks the item tree of a single recipe rooted at `root_item` before it is handed to a context.
+pub(crate) fn validate_recipe<C: ActionConfiguration>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    root_item: ActionRecipeItemIdx,
+    nest_recipe_count: usize,
+) -> Result<(), ConcertoError> {
+    let mut reachable = BTreeSet::new();
+    let mut eliminated = Vec::new();
+    let mut pending = vec![root_item];
+    while let Some(item_idx) = pending.pop() {
+        let item = recipe_items.get(item_idx)?;
+        reachable.insert(item_idx);
+        match item {
+            ActionRecipeItem::Sequential(seq)
+            | ActionRecipeItem::Unordered(seq, _)
+            | ActionRecipeItem::Choice(seq)
+            | ActionRecipeItem::Optional(seq)
+            | ActionRecipeItem::Repeated(seq, _) => {
+                if seq.is_empty() {
+                    return Err(ConcertoError::EmptySequence(item_idx));
+                }
+                if let ActionRecipeItem::Unordered(_, required) = item {
+                    if *required == 0 || *required > seq.len() {
+                        return Err(ConcertoError::InvalidMatchCount(item_idx));
+                    }
+                }
+                match item {
+                    ActionRecipeItem::Sequential(_) => {
+                        validate_optionals(recipe_items, seq)?;
+                    }
+                    ActionRecipeItem::Optional(_) | ActionRecipeItem::Repeated(..) => {
+                        if !recipe_items.get(seq[0])?.is_interactive() {
+                            return Err(ConcertoError::NonInteractiveBranch {
+                                container: item_idx,
+                                item: seq[0],
+                            });
+                        }
+                        validate_optionals(recipe_items, seq)?;
+                    }
+                    _ => validate_branches(recipe_items, item_idx, item)?,
+                }
+                pending.extend(seq.iter().cloned());
+            }
+            ActionRecipeItem::EliminateItem(eliminated_idx) => {
+                eliminated.push(*eliminated_idx);
+            }
+            ActionRecipeItem::StartNestRecipe(nest_recipe_idx)
+            | ActionRecipeItem::DisableNestRecipe(nest_recipe_idx)
+                if *nest_recipe_idx >= nest_recipe_count =>
+            {
+                return Err(ConcertoError::NestRecipeOutOfRange {
+                    nest_recipe_idx: *nest_recipe_idx,
+                    nest_recipe_count,
+                });
+            }
+            _ => {}
+        }
+    }
+    if let Some(idx) = eliminated.into_iter().find(|idx| !reachable.contains(idx)) {
+        return Err(ConcertoError::UnknownItem(idx));
+    }
+    if !requires_input(recipe_items, root_item)? {
+        return Err(ConcertoError::NoInteractiveItem(root_item));
+    }
+    validate_contracts(recipe_items, root_item, &mut Vec::new())
+}
+
+/// Checks that every item registered in the store belongs to one of the recipes.
+pub(crate) fn validate_items_in_use<C: ActionConfiguration, I>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    root_items: I,
+) -> Result<(), ConcertoError>
+where
+    I: Iterator<Item = ActionRecipeItemIdx>,
+{
+    let mut reachable = BTreeSet::new();
+    let mut pending = root_items.collect::<Vec<_>>();
+    while let Some(item_idx) = pending.pop() {
+        if !reachable.insert(item_idx) {
+            continue;
+        }
+        let item = recipe_items.get(item_idx)?;
+        if item.is_compound() {
+            pending.extend(item.compound_sequence(item_idx)?.iter().cloned());
+        }
+    }
+    match recipe_items
+        .item_indices()
+        .into_iter()
+        .find(|item_idx| !reachable.contains(item_idx))
+    {
+        Some(item_idx) => Err(ConcertoError::UnusedItem(item_idx)),
+        None => Ok(()),
+    }
+}
+
+enum ActiveContract<'a, C: ActionConfiguration> {
+    Input(&'a ActionInput<C>),
+    Condition(&'a ActionCondition<'a, C>),
+}
+
+impl<'a, C: ActionConfiguration> Clone for ActiveContract<'a, C> {
+    fn clone(&self) -> Self {
+        match self {
+            ActiveContract::Input(input) => ActiveContract::Input(input),
+            ActiveContract::Condition(condition) => ActiveContract::Condition(condition),
+        }
+    }
+}
+
+// Follows the recipe the same way the executor does, keeping track of the input and
+// condition contracts that are still in effect when each unordered or choice item is reached.
+fn validate_contracts<'a, C: ActionConfiguration>(
+    recipe_items: &'a ActionRecipeItemStore<C>,
+    item_idx: ActionRecipeItemIdx,
+    active_contracts: &mut Vec<(ActionRecipeItemIdx, ActiveContract<'a, C>)>,
+) -> Result<(), ConcertoError> {
+    match recipe_items.get(item_idx)? {
+        ActionRecipeItem::StartInput(input) => {
+            active_contracts.push((item_idx, ActiveContract::Input(input)));
+        }
+        ActionRecipeItem::StartCondition(condition) => {
+            active_contracts.push((item_idx, ActiveContract::Condition(condition)));
+        }
+        ActionRecipeItem::EliminateItem(eliminated_idx) => {
+            active_contracts.retain(|(contract_idx, _)| contract_idx != eliminated_idx);
+        }
+        ActionRecipeItem::Sequential(seq) => {
+            for &child_idx in seq.iter() {
+                validate_contracts(recipe_items, child_idx, active_contracts)?;
+            }
+        }
+        ActionRecipeItem::Optional(seq) | ActionRecipeItem::Repeated(seq, _) => {
+            // the body may be skipped or end with its iteration, so nothing it changes
+            // can be assumed afterwards.
+            let mut body_contracts = active_contracts.clone();
+            for &child_idx in seq.iter() {
+                validate_contracts(recipe_items, child_idx, &mut body_contracts)?;
+            }
+        }
+        item @ ActionRecipeItem::Unordered(..) | item @ ActionRecipeItem::Choice(_) => {
+            let mut branch_contracts = Vec::new();
+            for &branch_idx in item.compound_sequence(item_idx)? {
+                let input = match recipe_items.get(branch_idx)? {
+                    ActionRecipeItem::StartInput(input) => input,
+                    _ => continue,
+                };
+                let conflict = active_contracts.iter().find(|(_, contract)| {
+                    let result = match contract {
+                        ActiveContract::Input(expected_input) => {
+                            ActionExecutionCtx::check_input_match_input(expected_input, input)
+                        }
+                        ActiveContract::Condition(condition) => {
+                            ActionExecutionCtx::check_input_match_condition(condition, input)
+                        }
+                    };
+                    matches!(result, ExecutionContextResult::Abort)
+                });
+                if let Some((contract_idx, _)) = conflict {
+                    return Err(ConcertoError::ConflictingBranch {
+                        container: item_idx,
+                        item: branch_idx,
+                        contract: *contract_idx,
+                    });
+                }
+                branch_contracts.push((branch_idx, ActiveContract::Input(input)));
+            }
+            // only one choice branch is ever taken, and only some unordered ones may be, so
+            // none of them can be assumed in effect.
+            if let ActionRecipeItem::Unordered(seq, required) = item {
+                if *required == seq.len() {
+                    active_contracts.extend(branch_contracts);
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// The executor only ever matches the children of unordered and choice items against
+// an input directly, so each of them has to be a plain input.
+fn validate_branches<C: ActionConfiguration>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    container: ActionRecipeItemIdx,
+    item: &ActionRecipeItem<C>,
+) -> Result<(), ConcertoError> {
+    let is_choice = matches!(item, ActionRecipeItem::Choice(_));
+    let mut seen_inputs = Vec::new();
+    for &branch_idx in item.compound_sequence(container)? {
+        let branch = recipe_items.get(branch_idx)?;
+        if !branch.is_interactive() {
+            return Err(ConcertoError::NonInteractiveBranch {
+                container,
+                item: branch_idx,
+            });
+        }
+        if let (true, ActionRecipeItem::StartInput(input)) = (is_choice, branch) {
+            if seen_inputs.contains(&input) {
+                return Err(ConcertoError::UnreachableBranch {
+                    container,
+                    item: branch_idx,
+                });
+            }
+            seen_inputs.push(input);
+        }
+    }
+    Ok(())
+}
+
+// The executor decides whether an optional item was skipped, or a repeated item
+// ended, by looking at the item right after it, so that item has to be a plain input.
+fn validate_optionals<C: ActionConfiguration>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    seq: &[ActionRecipeItemIdx],
+) -> Result<(), ConcertoError> {
+    for (pos, &child_idx) in seq.iter().enumerate() {
+        let error = match recipe_items.get(child_idx)? {
+            ActionRecipeItem::Optional(_) => ConcertoError::OptionalWithoutFollowingInput,
+            ActionRecipeItem::Repeated(..) => ConcertoError::RepeatedWithoutFollowingInput,
+            _ => continue,
+        };
+        let followed_by_input = match seq.get(pos + 1) {
+            Some(&next_idx) => recipe_items.get(next_idx)?.is_interactive(),
+            None => false,
+        };
+        if !followed_by_input {
+            return Err(error(child_idx));
+        }
+    }
+    Ok(())
+}
+
+fn requires_input<C: ActionConfiguration>(
+    recipe_items: &ActionRecipeItemStore<C>,
+    item_idx: ActionRecipeItemIdx,
+) -> Result<bool, ConcertoError> {
+    let item = recipe_items.get(item_idx)?;
+    match item {
+        ActionRecipeItem::Sequential(seq) => {
+            for &child_idx in seq.iter() {
+                if requires_input(recipe_items, child_idx)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        ActionRecipeItem::Unordered(..) | ActionRecipeItem::Choice(_) => Ok(true),
+        _ => Ok(item.is_interactive()),
+    }
+}